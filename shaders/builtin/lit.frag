@@ -0,0 +1,48 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "../include/engine.glsl"
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in float fragDepth;
+layout(location = 2) in vec3 fragWorldPos;
+layout(location = 3) in vec3 fragToEye;
+
+layout(location = 0) out vec4 outColor;
+
+// Light from the scene's lights reaching a surface, Lambertian
+vec3 lighting(vec3 pos, vec3 normal) {
+    vec3 total = vec3(0.0);
+    for (uint i = 0; i < min(lights.count, MAX_LIGHTS); i++) {
+        Light light = lights.lights[i];
+        vec3 to_light;
+        float attenuation = 1.0;
+        if (light.kind == LIGHT_DIRECTIONAL) {
+            to_light = -light.direction;
+        } else {
+            vec3 offset = light.position - pos;
+            float dist = length(offset);
+            to_light = offset / max(dist, 1e-5);
+            attenuation = clamp(1.0 - dist / light.range, 0.0, 1.0);
+            attenuation *= attenuation;
+            if (light.kind == LIGHT_SPOT) {
+                float cos_angle = dot(-to_light, light.direction);
+                attenuation *= smoothstep(light.cos_outer_angle, light.cos_inner_angle, cos_angle);
+            }
+        }
+        float diffuse = max(dot(normal, to_light), 0.0);
+        total += light.color * light.intensity * diffuse * attenuation;
+    }
+    return total;
+}
+
+void main() {
+    ALPHA_TEST(model.color.a);
+
+    // Vertices carry no normals, so shade each face flat, facing the viewer
+    vec3 normal = normalize(cross(dFdx(fragWorldPos), dFdy(fragWorldPos)));
+    normal = faceforward(normal, -fragToEye, normal);
+    vec3 albedo = fragColor * model.color.rgb;
+    vec3 color = albedo * lighting(fragWorldPos, normal) + model.emissive.rgb;
+    outColor = finish_color(color, model.color.a, fragDepth);
+}
@@ -0,0 +1,24 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "../include/engine.glsl"
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out float fragDepth;
+layout(location = 2) out vec3 fragWorldPos;
+layout(location = 3) out vec3 fragToEye;
+
+void main() {
+    vec4 world = model.matrix * vec4(inPosition, 1.0);
+    gl_Position = realtime.matrix * world;
+    fragColor = inColor;
+    fragDepth = gl_Position.w;
+    fragWorldPos = world.xyz;
+
+    // The eye is the one point the projection sends to w = 0
+    vec4 eye = inverse(realtime.matrix) * vec4(0.0, 0.0, 1.0, 0.0);
+    fragToEye = eye.xyz / eye.w - world.xyz;
+}
@@ -0,0 +1,16 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "../include/engine.glsl"
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in float fragDepth;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    ALPHA_TEST(model.color.a);
+
+    vec3 color = fragColor * model.color.rgb + model.emissive.rgb;
+    outColor = finish_color(color, model.color.a, fragDepth);
+}
@@ -0,0 +1,16 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "../include/engine.glsl"
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out float fragDepth;
+
+void main() {
+    gl_Position = realtime.matrix * model.matrix * vec4(inPosition, 1.0);
+    fragColor = inColor;
+    fragDepth = gl_Position.w;
+}
@@ -0,0 +1,35 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "../include/engine.glsl"
+#include "water.glsl"
+
+layout(location = 0) in vec3 fragNormal;
+layout(location = 1) in float fragDepth;
+layout(location = 2) in vec3 fragWorldPos;
+layout(location = 3) in vec3 fragToEye;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec3 normal = normalize(fragNormal);
+    vec3 view = normalize(fragToEye);
+    float facing = max(dot(normal, view), 0.0);
+
+    // Looking straight down shows the water's depth; grazing angles reflect the sky
+    vec3 color = mix(water.deep_color.rgb, water.shallow_color.rgb, facing);
+    float fresnel = pow(1.0 - facing, water.fresnel_power);
+    color = mix(color, water.sky_color.rgb, fresnel);
+
+    // Glints of directional lights
+    for (uint i = 0; i < min(lights.count, MAX_LIGHTS); i++) {
+        Light light = lights.lights[i];
+        if (light.kind == LIGHT_DIRECTIONAL) {
+            float glint = pow(max(dot(reflect(light.direction, normal), view), 0.0), 64.0);
+            color += light.color * light.intensity * glint;
+        }
+    }
+
+    color = color * model.color.rgb + model.emissive.rgb;
+    outColor = finish_color(color, model.color.a, fragDepth);
+}
@@ -0,0 +1,11 @@
+// Material uniforms of the built-in water shader, matching WaterParams in
+// src/builtin_shaders.rs
+layout(set = 2, binding = 0) uniform Water {
+    vec4 shallow_color;
+    vec4 deep_color;
+    vec4 sky_color;
+    float wave_height;
+    float wave_length;
+    float wave_speed;
+    float fresnel_power;
+} water;
@@ -0,0 +1,42 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "../include/engine.glsl"
+#include "water.glsl"
+
+layout(location = 0) in vec3 inPosition;
+
+layout(location = 0) out vec3 fragNormal;
+layout(location = 1) out float fragDepth;
+layout(location = 2) out vec3 fragWorldPos;
+layout(location = 3) out vec3 fragToEye;
+
+const vec2 DIRECTIONS[3] = vec2[](
+    vec2(0.96, 0.28), vec2(-0.37, 0.93), vec2(0.6, -0.8)
+);
+
+void main() {
+    vec4 world = model.matrix * vec4(inPosition, 1.0);
+
+    // A few sine waves travelling across the XZ plane at the wave speed
+    float height = 0.0;
+    vec2 slope = vec2(0.0);
+    for (int i = 0; i < 3; i++) {
+        float wave_length = water.wave_length / float(i + 1);
+        float amplitude = water.wave_height / float(i + 1);
+        float k = 6.2831853 / wave_length;
+        float phase = k * (dot(DIRECTIONS[i], world.xz) - water.wave_speed * realtime.time);
+        height += amplitude * sin(phase);
+        slope += amplitude * k * cos(phase) * DIRECTIONS[i];
+    }
+    world.y += height;
+
+    gl_Position = realtime.matrix * world;
+    fragNormal = normalize(vec3(-slope.x, 1.0, -slope.y));
+    fragDepth = gl_Position.w;
+    fragWorldPos = world.xyz;
+
+    // The eye is the one point the projection sends to w = 0
+    vec4 eye = inverse(realtime.matrix) * vec4(0.0, 0.0, 1.0, 0.0);
+    fragToEye = eye.xyz / eye.w - world.xyz;
+}
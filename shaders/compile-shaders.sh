@@ -6,3 +6,20 @@ compile() {
 
 compile triangle.vert
 compile triangle.frag
+compile points.vert
+compile splats.vert
+compile splats.frag
+compile outline.vert
+compile outline.frag
+
+# Embedded in the crate with the builtin-shaders feature
+compile builtin/unlit.vert
+compile builtin/unlit.frag
+compile builtin/lit.vert
+compile builtin/lit.frag
+compile builtin/water.vert
+compile builtin/water.frag
+
+# Fixtures for the shader interface tests
+compile test/interface.vert
+compile test/push_constants.vert
@@ -0,0 +1,78 @@
+// Blocks shared with the engine. These must match the layouts in src/engine/mod.rs and
+// src/lights.rs; include this instead of copying them into each shader.
+#ifndef ENGINE_GLSL
+#define ENGINE_GLSL
+
+layout(binding = 0) uniform RealtimeUBO {
+    mat4 matrix;
+    float time;
+    float delta_time;
+    vec4 fog_color;
+    vec3 fog_params;
+    uint fog_mode;
+    vec4 color_scale;
+    vec4 color_bias;
+} realtime;
+
+layout(push_constant) uniform Model {
+    mat4 matrix;
+    vec4 color;
+    // Only used by the engine's outline shader
+    vec2 outline_offset;
+    vec4 emissive;
+} model;
+
+// Material features, set per variant by Engine::material_variant. The IDs follow
+// FEATURE_CONSTANT_BASE in src/engine/permutations.rs.
+layout(constant_id = 1000) const bool FEATURE_TEXTURED = false;
+layout(constant_id = 1001) const bool FEATURE_SKINNED = false;
+layout(constant_id = 1002) const bool FEATURE_INSTANCED = false;
+layout(constant_id = 1003) const bool FEATURE_ALPHA_TESTED = false;
+// MaterialOptions::alpha_cutoff
+layout(constant_id = 1004) const float ALPHA_CUTOFF = 0.5;
+
+// Discard the fragment if the material is alpha tested and `alpha` is below its cutoff.
+// Fragment shaders only.
+#define ALPHA_TEST(alpha) if (FEATURE_ALPHA_TESTED && (alpha) < ALPHA_CUTOFF) discard
+
+#define MAX_LIGHTS 64
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+struct Light {
+    vec3 position;
+    uint kind;
+    vec3 direction;
+    float range;
+    vec3 color;
+    float intensity;
+    float cos_inner_angle;
+    float cos_outer_angle;
+    uint cast_shadows;
+};
+
+layout(binding = 2) uniform Lights {
+    uint count;
+    Light lights[MAX_LIGHTS];
+} lights;
+
+// Fraction of fog at a view depth of `dist`
+float fog_factor(float dist) {
+    if (realtime.fog_mode == 1) {
+        float start = realtime.fog_params.x;
+        float end = realtime.fog_params.y;
+        return clamp((dist - start) / max(end - start, 1e-5), 0.0, 1.0);
+    } else if (realtime.fog_mode == 2) {
+        return 1.0 - exp(-realtime.fog_params.z * dist);
+    }
+    return 0.0;
+}
+
+// Fog, then the color scale and bias, as applied by every built-in shader
+vec4 finish_color(vec3 color, float alpha, float depth) {
+    color = mix(color, realtime.fog_color.rgb, fog_factor(depth));
+    return vec4(color, alpha) * realtime.color_scale + realtime.color_bias;
+}
+
+#endif
@@ -0,0 +1,10 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "include/engine.glsl"
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = model.color * realtime.color_scale + realtime.color_bias;
+}
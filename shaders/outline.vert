@@ -0,0 +1,14 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "include/engine.glsl"
+
+layout(location = 0) in vec3 inPosition;
+
+// Owned by the engine, which draws highlighted objects several times with this shader, each
+// copy shifted a few pixels in a different direction, so that together they outline the
+// object's silhouette
+void main() {
+    gl_Position = realtime.matrix * model.matrix * vec4(inPosition, 1.0);
+    gl_Position.xy += model.outline_offset * gl_Position.w;
+}
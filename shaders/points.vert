@@ -0,0 +1,26 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
+
+#include "include/engine.glsl"
+
+struct Point {
+    vec3 pos;
+    float size;
+    vec4 color;
+};
+
+layout(std430, set = 1, binding = 0) readonly buffer Points {
+    Point points[];
+};
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out float fragDepth;
+
+void main() {
+    Point point = points[gl_VertexIndex];
+    gl_Position = realtime.matrix * model.matrix * vec4(point.pos, 1.0);
+    gl_PointSize = point.size;
+    fragColor = point.color.rgb;
+    fragDepth = gl_Position.w;
+}
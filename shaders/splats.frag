@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec4 fragColor;
+layout(location = 1) in vec2 fragOffset;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    float r2 = dot(fragOffset, fragOffset);
+    if (r2 > 1.0) {
+        discard;
+    }
+    // Gaussian falloff, with the quad's edge at roughly two standard deviations
+    outColor = vec4(fragColor.rgb, fragColor.a * exp(-4.0 * r2));
+}
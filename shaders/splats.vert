@@ -0,0 +1,43 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
+
+#include "include/engine.glsl"
+
+struct Splat {
+    vec3 pos;
+    float radius;
+    vec4 color;
+};
+
+layout(std430, set = 1, binding = 0) readonly buffer Splats {
+    Splat splats[];
+};
+
+layout(std430, set = 1, binding = 1) readonly buffer Order {
+    uint order[];
+};
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec2 fragOffset;
+
+const vec2 corners[6] = vec2[](
+    vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+);
+
+void main() {
+    Splat splat = splats[order[gl_VertexIndex / 6]];
+    vec2 corner = corners[gl_VertexIndex % 6];
+
+    // The first two rows of the view-projection matrix point along the screen's axes
+    mat4 m = realtime.matrix;
+    vec3 right = normalize(vec3(m[0][0], m[1][0], m[2][0]));
+    vec3 up = normalize(vec3(m[0][1], m[1][1], m[2][1]));
+
+    vec3 center = (model.matrix * vec4(splat.pos, 1.0)).xyz;
+    vec3 pos = center + (right * corner.x + up * corner.y) * splat.radius;
+    gl_Position = m * vec4(pos, 1.0);
+    fragColor = splat.color * model.color;
+    fragOffset = corner;
+}
@@ -0,0 +1,29 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "../include/engine.glsl"
+
+// Declares every optional binding, for the shader interface tests
+
+layout(binding = 1) uniform User {
+    vec4 tint;
+} user;
+
+layout(set = 2, binding = 0) uniform Material {
+    vec4 offset;
+} material;
+
+layout(std430, set = 2, binding = 1) readonly buffer Palette {
+    mat4 palette[];
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+    vec4 pos = palette[gl_InstanceIndex] * vec4(inPosition, 1.0) + material.offset;
+    gl_Position = realtime.matrix * model.matrix * pos;
+    fragColor = inColor * user.tint.rgb * lights.lights[0].color;
+}
@@ -0,0 +1,14 @@
+#version 450
+
+// Pushes more than the engine does, for the shader interface tests
+
+layout(push_constant) uniform Model {
+    mat4 matrix;
+    mat4 normal_matrix;
+} model;
+
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    gl_Position = model.normal_matrix * model.matrix * vec4(inPosition, 1.0);
+}
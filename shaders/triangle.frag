@@ -1,15 +1,18 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
 
-layout(binding = 0) uniform RealtimeUBO {
-    mat4 matrix;
-    float time;
-} realtime;
+#include "include/engine.glsl"
 
 layout(location = 0) in vec3 fragColor;
+layout(location = 1) in float fragDepth;
 
 layout(location = 0) out vec4 outColor;
 
 void main() {
-    outColor = vec4(fragColor + vec3(cos(realtime.time)), 1.0);
+    ALPHA_TEST(model.color.a);
+
+    vec3 color = (fragColor + vec3(cos(realtime.time))) * model.color.rgb;
+    color += model.emissive.rgb;
+    outColor = finish_color(color, model.color.a, fragDepth);
 }
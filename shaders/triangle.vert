@@ -1,23 +1,17 @@
-
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
 
-layout(binding = 0) uniform RealtimeUBO {
-    mat4 matrix;
-    float time;
-} realtime;
-
-layout(push_constant) uniform Model {
-    mat4 matrix;
-} model;
+#include "include/engine.glsl"
 
 layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec3 inColor;
 
 layout(location = 0) out vec3 fragColor;
+layout(location = 1) out float fragDepth;
 
 void main() {
     gl_Position = realtime.matrix * model.matrix * vec4(inPosition, 1.0);
     fragColor = inColor;
+    fragDepth = gl_Position.w;
 }
-
@@ -1,13 +1,12 @@
+use crate::deletion_queue::{DeletionQueue, Garbage};
+use crate::memory::{self, Allocation, MemoryAllocator, MemoryLocation};
 use anyhow::Result;
-use erupt::{
-    utils::allocator::{self, Allocator},
-    vk1_0 as vk, DeviceLoader,
-};
+use erupt::{vk1_0 as vk, DeviceLoader};
 use std::marker::PhantomData;
 
 pub struct AllocatedBuffer<T> {
     pub buffer: vk::Buffer,
-    pub allocation: Option<allocator::Allocation<vk::Buffer>>,
+    pub allocation: Option<Allocation>,
     create_info: vk::BufferCreateInfoBuilder<'static>,
     dynamic: bool,
     _phantom: PhantomData<T>,
@@ -18,7 +17,7 @@ impl<T: Sized + bytemuck::Pod> AllocatedBuffer<T> {
     pub fn new(
         count: usize,
         create_info: vk::BufferCreateInfoBuilder<'static>,
-        allocator: &mut Allocator,
+        allocator: &mut dyn MemoryAllocator,
         device: &DeviceLoader,
     ) -> Result<Self> {
         anyhow::ensure!(count > 0, "Must allocate at least one object");
@@ -26,9 +25,8 @@ impl<T: Sized + bytemuck::Pod> AllocatedBuffer<T> {
         let mut create_info = create_info.size(size as u64);
         create_info.usage |= vk::BufferUsageFlags::TRANSFER_SRC;
         let buffer = unsafe { device.create_buffer(&create_info, None, None) }.result()?;
-        let allocation = allocator
-            .allocate(device, buffer, allocator::MemoryTypeFinder::dynamic())
-            .result()?;
+        let allocation =
+            memory::allocate_buffer(allocator, device, buffer, MemoryLocation::CpuToGpu)?;
         Ok(Self {
             buffer,
             allocation: Some(allocation),
@@ -39,6 +37,30 @@ impl<T: Sized + bytemuck::Pod> AllocatedBuffer<T> {
         })
     }
 
+    /// Allocate uninitialized device-local memory, to be filled by transfer commands
+    pub fn new_gpu_only(
+        count: usize,
+        create_info: vk::BufferCreateInfoBuilder<'static>,
+        allocator: &mut dyn MemoryAllocator,
+        device: &DeviceLoader,
+    ) -> Result<Self> {
+        anyhow::ensure!(count > 0, "Must allocate at least one object");
+        let size = std::mem::size_of::<T>() * count;
+        let mut create_info = create_info.size(size as u64);
+        create_info.usage |= vk::BufferUsageFlags::TRANSFER_DST;
+        let buffer = unsafe { device.create_buffer(&create_info, None, None) }.result()?;
+        let allocation =
+            memory::allocate_buffer(allocator, device, buffer, MemoryLocation::GpuOnly)?;
+        Ok(Self {
+            buffer,
+            allocation: Some(allocation),
+            dynamic: false,
+            freed: false,
+            create_info,
+            _phantom: PhantomData::default(),
+        })
+    }
+
     pub fn map(&self, device: &DeviceLoader, data: &[T]) -> Result<()> {
         if !self.dynamic {
             anyhow::bail!("Cannot write to gpu-only memory");
@@ -46,15 +68,35 @@ impl<T: Sized + bytemuck::Pod> AllocatedBuffer<T> {
         if std::mem::size_of::<T>() * data.len() != self.create_info.size as usize {
             anyhow::bail!("Size must match exactly");
         }
-        let mut map = self
-            .allocation
+        self.allocation
             .as_ref()
             .expect("Use-after-free")
-            .map(device, ..)
-            .result()?;
-        map.import(bytemuck::cast_slice(data));
-        map.unmap(&device).result()?;
-        Ok(())
+            .write(device, bytemuck::cast_slice(data))
+    }
+
+    /// Overwrite the elements starting at index `offset`
+    pub fn map_range(&self, device: &DeviceLoader, offset: usize, data: &[T]) -> Result<()> {
+        if !self.dynamic {
+            anyhow::bail!("Cannot write to gpu-only memory");
+        }
+        if offset + data.len() > self.count() {
+            anyhow::bail!("Range exceeds buffer");
+        }
+        self.allocation.as_ref().expect("Use-after-free").write_at(
+            device,
+            (std::mem::size_of::<T>() * offset) as u64,
+            bytemuck::cast_slice(data),
+        )
+    }
+
+    /// Number of elements the buffer holds
+    pub fn count(&self) -> usize {
+        self.create_info.size as usize / std::mem::size_of::<T>()
+    }
+
+    /// Size of the buffer in bytes
+    pub fn size(&self) -> u64 {
+        self.create_info.size
     }
 
     pub fn is_dynamic(&self) -> bool {
@@ -64,15 +106,14 @@ impl<T: Sized + bytemuck::Pod> AllocatedBuffer<T> {
     pub fn gpu_only(
         mut self,
         device: &DeviceLoader,
-        allocator: &mut Allocator,
+        allocator: &mut dyn MemoryAllocator,
         command_pool: vk::CommandPool,
         queue: vk::Queue,
     ) -> Result<Self> {
         self.create_info.usage |= vk::BufferUsageFlags::TRANSFER_DST;
         let buffer = unsafe { device.create_buffer(&self.create_info, None, None) }.result()?;
-        let allocation = allocator
-            .allocate(device, buffer, allocator::MemoryTypeFinder::gpu_only())
-            .result()?;
+        let allocation =
+            memory::allocate_buffer(allocator, device, buffer, MemoryLocation::GpuOnly)?;
 
         let create_info = vk::CommandBufferAllocateInfoBuilder::new()
             .level(vk::CommandBufferLevel::PRIMARY)
@@ -111,16 +152,42 @@ impl<T: Sized + bytemuck::Pod> AllocatedBuffer<T> {
         })
     }
 
-    pub fn free(&mut self, device: &DeviceLoader, allocator: &mut Allocator) -> Result<()> {
+    pub fn free(
+        &mut self,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+    ) -> Result<()> {
         unsafe {
             device.device_wait_idle().result()?;
         }
+        self.free_unused(device, allocator);
+        Ok(())
+    }
+
+    /// Free immediately without waiting for the device. Nothing may still be using the buffer,
+    /// e.g. because the fences of the frames which used it have signaled.
+    pub(crate) fn free_unused(
+        &mut self,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+    ) {
         allocator.free(
             &device,
             self.allocation.take().expect("Already deallocated"),
         );
+        unsafe {
+            device.destroy_buffer(Some(self.buffer), None);
+        }
+        self.freed = true;
+    }
+
+    /// Free once every frame which may be using the buffer has completed, without waiting
+    pub(crate) fn retire(&mut self, deletion_queue: &mut DeletionQueue) {
+        deletion_queue.push(Garbage::Buffer(
+            self.buffer,
+            self.allocation.take().expect("Already deallocated"),
+        ));
         self.freed = true;
-        Ok(())
     }
 }
 
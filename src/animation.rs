@@ -0,0 +1,105 @@
+use crate::engine::ObjectId;
+use crate::snapshot::ObjectPose;
+use nalgebra::Vector3;
+
+/// Shape of the blend between two keyframes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    /// Starts slow
+    EaseIn,
+    /// Ends slow
+    EaseOut,
+    /// Starts and ends slow
+    EaseInOut,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::Linear
+    }
+}
+
+impl Easing {
+    /// Map linear progress `t` in `0..=1` to eased progress
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// An object's pose at a point in a track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    /// Seconds since the start of the track
+    pub time: f32,
+    pub pose: ObjectPose,
+}
+
+/// Keyframed transform animation, played with `Engine::play_animation`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    /// In ascending order of time
+    pub keyframes: Vec<Keyframe>,
+    /// Applied between each pair of keyframes
+    pub easing: Easing,
+    /// Start over after the last keyframe instead of finishing
+    pub looping: bool,
+}
+
+impl Track {
+    /// Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|k| k.time).unwrap_or(0.0)
+    }
+
+    /// Move every keyframe by `offset`
+    pub fn translate(&mut self, offset: &Vector3<f32>) {
+        for keyframe in &mut self.keyframes {
+            for (t, o) in keyframe.pose.translation.iter_mut().zip(offset.iter()) {
+                *t += o;
+            }
+        }
+    }
+
+    /// Pose `time` seconds into the track, clamped to its ends
+    pub fn sample(&self, time: f32) -> Option<ObjectPose> {
+        let first = self.keyframes.first()?;
+        if time <= first.time {
+            return Some(first.pose);
+        }
+        for pair in self.keyframes.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if time < b.time {
+                let t = (time - a.time) / (b.time - a.time);
+                return Some(a.pose.interpolate(&b.pose, self.easing.apply(t)));
+            }
+        }
+        self.keyframes.last().map(|k| k.pose)
+    }
+}
+
+/// A track being played on an object
+pub(crate) struct Playback {
+    pub track: Track,
+    /// `Clock::elapsed` when playback started
+    pub start: f64,
+    pub on_complete: Option<Box<dyn FnOnce(ObjectId)>>,
+}
+
+impl Playback {
+    /// Pose at clock time `now`, and whether the track has finished
+    pub fn sample(&self, now: f64) -> (Option<ObjectPose>, bool) {
+        let duration = self.track.duration();
+        let time = (now - self.start) as f32;
+        if self.track.looping && duration > 0.0 {
+            (self.track.sample(time % duration), false)
+        } else {
+            (self.track.sample(time), time >= duration)
+        }
+    }
+}
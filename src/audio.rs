@@ -0,0 +1,73 @@
+use crate::camera::Camera;
+use nalgebra::{Point3, Vector3};
+
+/// Pose of the listener for spatial audio, taken from the camera of the last rendered frame
+#[derive(Debug, Clone, Copy)]
+pub struct Listener {
+    pub position: Point3<f32>,
+    pub forward: Vector3<f32>,
+    pub up: Vector3<f32>,
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Self {
+            position: Point3::origin(),
+            forward: -Vector3::z(),
+            up: Vector3::y(),
+        }
+    }
+}
+
+impl Listener {
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            position: camera.eye,
+            forward: camera.forward(),
+            up: camera.up(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(eye: [f32; 3], at: [f32; 3]) -> Camera {
+        Camera {
+            eye: Point3::from(eye),
+            at: Point3::from(at),
+            fovy: 1.0,
+            clip_near: 0.1,
+            clip_far: 100.0,
+        }
+    }
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).norm() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn listener_follows_the_camera() {
+        let listener = Listener::from_camera(&camera([0.0, 2.0, 0.0], [0.0, 2.0, -5.0]));
+        assert_close(listener.forward, -Vector3::z());
+        assert_close(listener.up, Vector3::y());
+
+        // Pitched down 45 degrees, up tilts forward with the view
+        let listener = Listener::from_camera(&camera([0.0, 1.0, 0.0], [0.0, 0.0, -1.0]));
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(listener.forward, Vector3::new(0.0, -s, -s));
+        assert_close(listener.up, Vector3::new(0.0, s, -s));
+    }
+
+    #[test]
+    fn degenerate_cameras_give_unit_vectors() {
+        let listener = Listener::from_camera(&camera([1.0; 3], [1.0; 3]));
+        assert_close(listener.forward, -Vector3::z());
+        assert_close(listener.up, Vector3::y());
+
+        let listener = Listener::from_camera(&camera([0.0; 3], [0.0, -3.0, 0.0]));
+        assert_close(listener.forward, -Vector3::y());
+        assert_close(listener.up, -Vector3::z());
+    }
+}
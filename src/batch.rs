@@ -0,0 +1,41 @@
+use crate::vertex::Vertex;
+use anyhow::Result;
+use nalgebra::{Matrix4, Point3};
+
+/// A piece of static geometry to be merged into a batch
+pub struct StaticMesh<'a> {
+    pub vertices: &'a [Vertex],
+    pub indices: &'a [u16],
+    pub transform: Matrix4<f32>,
+}
+
+/// Merge several meshes into one vertex/index buffer pair, baking each mesh's transform into its
+/// vertices. Fails if the merged mesh would not be addressable by 16-bit indices.
+pub fn merge_static_meshes(meshes: &[StaticMesh]) -> Result<(Vec<Vertex>, Vec<u16>)> {
+    let n_vertices: usize = meshes.iter().map(|m| m.vertices.len()).sum();
+    let n_indices: usize = meshes.iter().map(|m| m.indices.len()).sum();
+    anyhow::ensure!(
+        n_vertices <= u16::MAX as usize + 1,
+        "Static batch has {} vertices, which exceeds the 16-bit index limit",
+        n_vertices
+    );
+
+    let mut vertices = Vec::with_capacity(n_vertices);
+    let mut indices = Vec::with_capacity(n_indices);
+
+    for mesh in meshes {
+        let base = vertices.len() as u16;
+        vertices.extend(mesh.vertices.iter().map(|v| {
+            let pos = mesh
+                .transform
+                .transform_point(&Point3::new(v.pos[0], v.pos[1], v.pos[2]));
+            Vertex {
+                pos: *pos.coords.as_ref(),
+                ..*v
+            }
+        }));
+        indices.extend(mesh.indices.iter().map(|i| base + i));
+    }
+
+    Ok((vertices, indices))
+}
@@ -0,0 +1,147 @@
+//! Shaders shipped with the engine, embedded at build time from the SPIR-V checked in next to
+//! their sources; rerun `shaders/compile-shaders.sh` after editing them. Their GLSL sources,
+//! along with `shaders/include/engine.glsl` which declares the engine's uniform blocks and push
+//! constants, are a starting point for custom shaders.
+use crate::pipeline::{DrawType, MaterialOptions};
+use crate::vertex::Vertex;
+use crate::{Engine, MaterialId};
+use anyhow::Result;
+use nalgebra::Vector2;
+
+/// A compiled vertex and fragment shader pair, and the primitives it draws
+#[derive(Debug, Clone, Copy)]
+pub struct BuiltinShader {
+    pub vertex: &'static [u8],
+    pub fragment: &'static [u8],
+    pub draw_type: DrawType,
+}
+
+const UNLIT_VERT: &[u8] = include_bytes!("../shaders/builtin/unlit.vert.spv");
+const UNLIT_FRAG: &[u8] = include_bytes!("../shaders/builtin/unlit.frag.spv");
+const LIT_VERT: &[u8] = include_bytes!("../shaders/builtin/lit.vert.spv");
+const LIT_FRAG: &[u8] = include_bytes!("../shaders/builtin/lit.frag.spv");
+const POINTS_VERT: &[u8] = include_bytes!("../shaders/points.vert.spv");
+const WATER_VERT: &[u8] = include_bytes!("../shaders/builtin/water.vert.spv");
+const WATER_FRAG: &[u8] = include_bytes!("../shaders/builtin/water.frag.spv");
+
+/// Vertex colors, tinted by the object color
+pub const UNLIT: BuiltinShader = BuiltinShader {
+    vertex: UNLIT_VERT,
+    fragment: UNLIT_FRAG,
+    draw_type: DrawType::Triangles,
+};
+
+/// Vertex colors lit by the engine's lights, flat shaded per face
+pub const LIT: BuiltinShader = BuiltinShader {
+    vertex: LIT_VERT,
+    fragment: LIT_FRAG,
+    draw_type: DrawType::Triangles,
+};
+
+/// Unlit line lists, e.g. for grids and debug geometry
+pub const LINES: BuiltinShader = BuiltinShader {
+    vertex: UNLIT_VERT,
+    fragment: UNLIT_FRAG,
+    draw_type: DrawType::Lines,
+};
+
+/// Point clouds added with `Engine::add_point_cloud`, unlit
+pub const POINTS: BuiltinShader = BuiltinShader {
+    vertex: POINTS_VERT,
+    fragment: UNLIT_FRAG,
+    draw_type: DrawType::PointCloud,
+};
+
+/// Animated water, displaced by waves over time, blending from the water's color to the sky's
+/// at grazing angles, with glints of directional lights. Needs a `WaterParams` material
+/// uniform; load it with `Engine::load_water_material`. Waves only show on meshes with enough
+/// vertices, such as a `water_plane`.
+pub const WATER: BuiltinShader = BuiltinShader {
+    vertex: WATER_VERT,
+    fragment: WATER_FRAG,
+    draw_type: DrawType::Triangles,
+};
+
+/// Material uniforms of `WATER`
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct WaterParams {
+    /// Seen looking straight down
+    pub shallow_color: [f32; 4],
+    /// Seen at shallower angles
+    pub deep_color: [f32; 4],
+    /// Reflected at grazing angles
+    pub sky_color: [f32; 4],
+    /// Amplitude of the largest wave
+    pub wave_height: f32,
+    /// Length of the largest wave
+    pub wave_length: f32,
+    /// Speed waves travel at, in units per second
+    pub wave_speed: f32,
+    /// Higher values confine the sky's reflection to more grazing angles
+    pub fresnel_power: f32,
+}
+
+unsafe impl bytemuck::Zeroable for WaterParams {}
+unsafe impl bytemuck::Pod for WaterParams {}
+
+impl Default for WaterParams {
+    fn default() -> Self {
+        Self {
+            shallow_color: [0.1, 0.4, 0.45, 1.0],
+            deep_color: [0.02, 0.1, 0.2, 1.0],
+            sky_color: [0.6, 0.75, 0.9, 1.0],
+            wave_height: 0.1,
+            wave_length: 4.0,
+            wave_speed: 1.0,
+            fresnel_power: 5.0,
+        }
+    }
+}
+
+/// A flat grid in the XZ plane, centered on the origin and spanning `size`, with
+/// `subdivisions` quads along each side
+pub fn water_plane(size: Vector2<f32>, subdivisions: u16) -> (Vec<Vertex>, Vec<u16>) {
+    let n = subdivisions.max(1).min(255);
+    let mut vertices = Vec::with_capacity((n as usize + 1).pow(2));
+    for j in 0..=n {
+        for i in 0..=n {
+            let x = (i as f32 / n as f32 - 0.5) * size.x;
+            let z = (j as f32 / n as f32 - 0.5) * size.y;
+            vertices.push(Vertex::from_position([x, 0.0, z]));
+        }
+    }
+    let mut indices = Vec::with_capacity(n as usize * n as usize * 6);
+    for j in 0..n {
+        for i in 0..n {
+            let a = j * (n + 1) + i;
+            let (b, c, d) = (a + 1, a + n + 1, a + n + 2);
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+    (vertices, indices)
+}
+
+impl Engine {
+    pub fn load_builtin_material(&mut self, shader: &BuiltinShader) -> Result<MaterialId> {
+        self.load_material(shader.vertex, shader.fragment, shader.draw_type)
+    }
+
+    /// Load `WATER` with its material uniforms set to `params`. Change them later with
+    /// `set_material_uniforms`.
+    pub fn load_water_material(&mut self, params: &WaterParams) -> Result<MaterialId> {
+        let options = MaterialOptions {
+            name: Some("Water".to_string()),
+            uniform_bytes: std::mem::size_of::<WaterParams>(),
+            ..Default::default()
+        };
+        let material = self.load_material_with_options(
+            WATER.vertex,
+            WATER.fragment,
+            WATER.draw_type,
+            options,
+        )?;
+        self.set_material_uniforms(material, params)?;
+        Ok(material)
+    }
+}
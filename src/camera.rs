@@ -1,5 +1,6 @@
 use nalgebra::{Point3, Matrix4, Vector3};
 
+#[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub eye: Point3<f32>,
     pub at: Point3<f32>,
@@ -13,6 +14,23 @@ impl Camera {
         Matrix4::look_at_rh(&self.eye, &self.at, &Vector3::new(0.0, -1.0, 0.0))
     }
 
+    /// Unit vector the camera looks along, or -Z if `eye` and `at` coincide
+    pub fn forward(&self) -> Vector3<f32> {
+        (self.at - self.eye)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| -Vector3::z())
+    }
+
+    /// Unit vector towards the top of the rendered image, perpendicular to `forward`. `view`
+    /// passes -Y as its up vector to make up for Vulkan's downward Y axis, so this is +Y
+    /// tilted along with the view. Looking straight up or down, it's +Z or -Z.
+    pub fn up(&self) -> Vector3<f32> {
+        let forward = self.forward();
+        (Vector3::y() - forward * forward.y)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| Vector3::z() * forward.y.signum())
+    }
+
     pub fn projection(&self, aspect: f32) -> Matrix4<f32> {
         Matrix4::new_perspective(aspect, self.fovy, self.clip_near, self.clip_far)
     }
@@ -20,4 +38,16 @@ impl Camera {
     pub fn matrix(&self, aspect: f32) -> Matrix4<f32> {
         self.projection(aspect) * self.view()
     }
+
+    /// Move the camera by `transform`, which scales uniformly by `scale`. Clip distances are
+    /// scaled along with it.
+    pub(crate) fn transformed(&self, transform: &Matrix4<f32>, scale: f32) -> Self {
+        Self {
+            eye: transform.transform_point(&self.eye),
+            at: transform.transform_point(&self.at),
+            clip_near: self.clip_near * scale,
+            clip_far: self.clip_far * scale,
+            ..*self
+        }
+    }
 }
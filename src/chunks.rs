@@ -0,0 +1,130 @@
+use crate::culling::Aabb;
+use crate::deletion_queue::DeletionQueue;
+use crate::engine::MaterialId;
+use crate::floating_origin::FloatingOrigin;
+use crate::memory::MemoryAllocator;
+use crate::streamed_buffer::StreamedBuffer;
+use crate::vertex::Vertex;
+use anyhow::Result;
+use erupt::{vk1_0 as vk, DeviceLoader};
+use nalgebra::{Point3, Vector3};
+
+/// A chunk's mesh, occupying one slot of its pool
+pub(crate) struct ChunkSlot {
+    pub n_indices: u32,
+    /// Relative to `origin`
+    pub bounds: Aabb,
+    /// World position the chunk's vertices are relative to
+    pub origin: Point3<f64>,
+    /// `origin` relative to the floating origin, kept up to date by `ChunkPool::rebase`
+    pub local_origin: Vector3<f32>,
+}
+
+/// Fixed-size mesh slots sharing one vertex and one index buffer, for workloads such as voxel
+/// terrain where many small meshes are replaced often. Chunk vertices are relative to a world
+/// position of the chunk's own, kept in `f64` so chunks stay precise far from the world origin,
+/// and chunk indices are relative to the chunk's own vertices.
+pub(crate) struct ChunkPool {
+    pub material: MaterialId,
+    pub vertices: StreamedBuffer<Vertex>,
+    pub indices: StreamedBuffer<u16>,
+    pub slot_vertices: usize,
+    pub slot_indices: usize,
+    pub slots: Vec<Option<ChunkSlot>>,
+}
+
+impl ChunkPool {
+    pub fn new(
+        material: MaterialId,
+        n_slots: usize,
+        slot_vertices: usize,
+        slot_indices: usize,
+        frames_in_flight: usize,
+        allocator: &mut dyn MemoryAllocator,
+        device: &DeviceLoader,
+    ) -> Result<Self> {
+        anyhow::ensure!(
+            slot_vertices <= u16::MAX as usize + 1,
+            "Chunk vertices must be addressable by 16-bit indices"
+        );
+
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let vertices = StreamedBuffer::new_dynamic(
+            &vec![Vertex::default(); n_slots * slot_vertices],
+            frames_in_flight,
+            create_info,
+            allocator,
+            device,
+        )?;
+
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::INDEX_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let indices = StreamedBuffer::new_dynamic(
+            &vec![0; n_slots * slot_indices],
+            frames_in_flight,
+            create_info,
+            allocator,
+            device,
+        )?;
+
+        Ok(Self {
+            material,
+            vertices,
+            indices,
+            slot_vertices,
+            slot_indices,
+            slots: (0..n_slots).map(|_| None).collect(),
+        })
+    }
+
+    /// Replace the mesh in `slot`, with vertices relative to the world position `origin`. The
+    /// previous mesh stays visible in frames already in flight.
+    pub fn set(
+        &mut self,
+        slot: usize,
+        origin: &Point3<f64>,
+        floating_origin: &FloatingOrigin,
+        vertices: &[Vertex],
+        indices: &[u16],
+    ) -> Result<()> {
+        anyhow::ensure!(slot < self.slots.len(), "Chunk slot out of range");
+        anyhow::ensure!(
+            vertices.len() <= self.slot_vertices && indices.len() <= self.slot_indices,
+            "Chunk mesh exceeds slot size"
+        );
+        let bounds = match Aabb::from_vertices(vertices) {
+            Some(bounds) => bounds,
+            None => {
+                self.slots[slot] = None;
+                return Ok(());
+            }
+        };
+        self.vertices
+            .write_range(slot * self.slot_vertices, vertices)?;
+        self.indices
+            .write_range(slot * self.slot_indices, indices)?;
+        self.slots[slot] = Some(ChunkSlot {
+            n_indices: indices.len() as u32,
+            bounds,
+            origin: *origin,
+            local_origin: floating_origin.to_local(origin).coords,
+        });
+        Ok(())
+    }
+
+    /// Place every chunk relative to a moved floating origin
+    pub fn rebase(&mut self, floating_origin: &FloatingOrigin) {
+        for chunk in self.slots.iter_mut().flatten() {
+            chunk.local_origin = floating_origin.to_local(&chunk.origin).coords;
+        }
+    }
+
+    /// Free once every frame which may be using the pool has completed, without waiting
+    pub fn retire(&mut self, deletion_queue: &mut DeletionQueue) {
+        self.vertices.retire(deletion_queue);
+        self.indices.retire(deletion_queue);
+    }
+}
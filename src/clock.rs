@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+/// Period after which the time sent to shaders wraps back to zero. An `f32` counting seconds
+/// still resolves a quarter of a millisecond at an hour, but loses precision without bound over
+/// longer sessions.
+pub const SHADER_TIME_WRAP: f64 = 3600.0;
+
+/// Time since the engine started and between frames, advanced once per `next_frame`
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    last_tick: Option<Instant>,
+    elapsed: f64,
+    delta: f32,
+    fixed_step: Option<f64>,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            last_tick: None,
+            elapsed: 0.0,
+            delta: 0.0,
+            fixed_step: None,
+        }
+    }
+
+    /// Seconds elapsed up to the current frame, at full precision
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Seconds between the previous frame and the current one. Zero on the first frame.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// `elapsed()` wrapped to `SHADER_TIME_WRAP`, as seen by shaders
+    pub fn shader_time(&self) -> f32 {
+        (self.elapsed % SHADER_TIME_WRAP) as f32
+    }
+
+    /// Advance by exactly `step` each frame instead of by wall time, e.g. for deterministic
+    /// capture; `None` returns to wall time
+    pub fn set_fixed_step(&mut self, step: Option<Duration>) {
+        self.fixed_step = step.map(|step| step.as_secs_f64());
+    }
+
+    /// Jump to `seconds`, e.g. when seeking a replay. The next frame's delta is unaffected.
+    pub fn set_elapsed(&mut self, seconds: f64) {
+        self.elapsed = seconds;
+    }
+
+    pub(crate) fn tick(&mut self) {
+        let now = Instant::now();
+        let delta = match (self.fixed_step, self.last_tick) {
+            (Some(step), _) => step,
+            (None, Some(last_tick)) => (now - last_tick).as_secs_f64(),
+            (None, None) => 0.0,
+        };
+        self.last_tick = Some(now);
+        self.elapsed += delta;
+        self.delta = delta as f32;
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,76 @@
+use crate::vertex::Vertex;
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+
+/// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// Smallest box containing every vertex, or `None` if there are no vertices
+    pub fn from_vertices(vertices: &[Vertex]) -> Option<Self> {
+        let [x, y, z] = vertices.first()?.pos;
+        let first = Point3::new(x, y, z);
+        let mut aabb = Self {
+            min: first,
+            max: first,
+        };
+        for vertex in vertices {
+            for axis in 0..3 {
+                aabb.min[axis] = aabb.min[axis].min(vertex.pos[axis]);
+                aabb.max[axis] = aabb.max[axis].max(vertex.pos[axis]);
+            }
+        }
+        Some(aabb)
+    }
+
+    pub fn translated(&self, offset: &Vector3<f32>) -> Self {
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+}
+
+/// View frustum as six inward-facing planes, extracted from a view-projection matrix
+pub(crate) struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Self {
+        let row = |i: usize| matrix.row(i).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, w + z, w - z],
+        }
+    }
+
+    /// Conservative test; may report boxes just outside the corners as visible
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The box corner furthest along the plane normal
+            let corner = Vector4::new(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+                1.0,
+            );
+            plane.dot(&corner) >= 0.0
+        })
+    }
+}
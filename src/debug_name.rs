@@ -0,0 +1,24 @@
+use anyhow::Result;
+use erupt::{extensions::ext_debug_utils, vk1_0 as vk, DeviceLoader};
+use std::ffi::CString;
+
+/// Label a Vulkan object for validation messages and graphics debuggers. `handle` is the raw
+/// handle value. Does nothing unless `VK_EXT_debug_utils` was loaded, as it only is in debug
+/// builds.
+pub(crate) fn set_debug_name(
+    device: &DeviceLoader,
+    object_type: vk::ObjectType,
+    handle: u64,
+    name: &str,
+) -> Result<()> {
+    if device.set_debug_utils_object_name_ext.is_none() {
+        return Ok(());
+    }
+    let name = CString::new(name)?;
+    let name_info = ext_debug_utils::DebugUtilsObjectNameInfoEXTBuilder::new()
+        .object_type(object_type)
+        .object_handle(handle)
+        .object_name(&name);
+    unsafe { device.set_debug_utils_object_name_ext(&name_info) }.result()?;
+    Ok(())
+}
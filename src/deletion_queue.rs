@@ -0,0 +1,81 @@
+use crate::memory::{Allocation, MemoryAllocator};
+use erupt::{vk1_0 as vk, DeviceLoader};
+
+/// A resource waiting for the GPU to finish with it
+pub(crate) enum Garbage {
+    Buffer(vk::Buffer, Allocation),
+    /// A set and the pool it came from
+    DescriptorSet(vk::DescriptorPool, vk::DescriptorSet),
+}
+
+impl Garbage {
+    fn destroy(self, device: &DeviceLoader, allocator: &mut dyn MemoryAllocator) {
+        match self {
+            Garbage::Buffer(buffer, allocation) => {
+                allocator.free(device, allocation);
+                unsafe {
+                    device.destroy_buffer(Some(buffer), None);
+                }
+            }
+            Garbage::DescriptorSet(pool, set) => {
+                // vkFreeDescriptorSets can only succeed
+                let _ = unsafe { device.free_descriptor_sets(pool, &[set]) };
+            }
+        }
+    }
+}
+
+/// Defers destruction of resources until every frame that may have used them has completed,
+/// tracked per frame slot so nothing waits on the device.
+pub(crate) struct DeletionQueue {
+    /// Submissions made from each frame slot
+    submitted: Vec<u64>,
+    /// Submissions from each frame slot known to have completed
+    completed: Vec<u64>,
+    /// Each resource, with the submissions which may still be using it
+    pending: Vec<(Vec<u64>, Garbage)>,
+}
+
+impl DeletionQueue {
+    pub fn new(frames_in_flight: usize) -> Self {
+        Self {
+            submitted: vec![0; frames_in_flight],
+            completed: vec![0; frames_in_flight],
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, garbage: Garbage) {
+        self.pending.push((self.submitted.clone(), garbage));
+    }
+
+    /// Record a queue submission from `frame`
+    pub fn submitted(&mut self, frame: usize) {
+        self.submitted[frame] += 1;
+    }
+
+    /// Call once `frame`'s fence has signaled; destroys whatever is no longer in use
+    pub fn frame_complete(
+        &mut self,
+        frame: usize,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+    ) {
+        self.completed[frame] = self.submitted[frame];
+        let completed = &self.completed;
+        let (ready, pending) = self.pending.drain(..).partition::<Vec<_>, _>(|(users, _)| {
+            users.iter().zip(completed).all(|(used, done)| done >= used)
+        });
+        self.pending = pending;
+        for (_, garbage) in ready {
+            garbage.destroy(device, allocator);
+        }
+    }
+
+    /// Destroy everything immediately. The device must be idle.
+    pub fn free(&mut self, device: &DeviceLoader, allocator: &mut dyn MemoryAllocator) {
+        for (_, garbage) in self.pending.drain(..) {
+            garbage.destroy(device, allocator);
+        }
+    }
+}
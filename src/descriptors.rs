@@ -0,0 +1,231 @@
+use crate::arena::Arena;
+use crate::deletion_queue::{DeletionQueue, Garbage};
+use crate::engine::BufferId;
+use crate::memory::MemoryAllocator;
+use crate::streamed_buffer::StreamedBuffer;
+use anyhow::Result;
+use erupt::{vk1_0 as vk, DeviceLoader};
+
+/// Descriptor sets each pool can hold before another pool is created
+const SETS_PER_POOL: u32 = 64;
+
+/// A descriptor set and the pool it came from
+#[derive(Clone, Copy)]
+pub(crate) struct DescriptorAllocation {
+    pub set: vk::DescriptorSet,
+    pool: vk::DescriptorPool,
+}
+
+impl DescriptorAllocation {
+    /// Return the set to its pool once every frame which may be using it has completed
+    pub fn retire(self, deletion_queue: &mut DeletionQueue) {
+        deletion_queue.push(Garbage::DescriptorSet(self.pool, self.set));
+    }
+}
+
+/// Hands out descriptor sets of any layout, creating pools as existing ones fill up. Sets may be
+/// freed individually.
+#[derive(Default)]
+pub(crate) struct DescriptorAllocator {
+    pools: Vec<vk::DescriptorPool>,
+}
+
+impl DescriptorAllocator {
+    pub fn allocate(
+        &mut self,
+        device: &DeviceLoader,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<DescriptorAllocation> {
+        let layouts = [layout];
+
+        // Newer pools are the most likely to have room
+        for &pool in self.pools.iter().rev() {
+            let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
+                .descriptor_pool(pool)
+                .set_layouts(&layouts);
+            if let Ok(sets) = unsafe { device.allocate_descriptor_sets(&create_info) }.result() {
+                return Ok(DescriptorAllocation { set: sets[0], pool });
+            }
+        }
+
+        let pool = Self::create_pool(device)?;
+        self.pools.push(pool);
+        let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        let sets = unsafe { device.allocate_descriptor_sets(&create_info) }.result()?;
+        Ok(DescriptorAllocation { set: sets[0], pool })
+    }
+
+    /// Return a set to its pool. It must not be in use by any frame in flight.
+    pub fn free(&mut self, device: &DeviceLoader, allocation: DescriptorAllocation) -> Result<()> {
+        unsafe { device.free_descriptor_sets(allocation.pool, &[allocation.set]) }.result()?;
+        Ok(())
+    }
+
+    /// Destroy every pool, and with them every set
+    pub fn free_all(&mut self, device: &DeviceLoader) {
+        for pool in self.pools.drain(..) {
+            unsafe {
+                device.destroy_descriptor_pool(Some(pool), None);
+            }
+        }
+    }
+
+    fn create_pool(device: &DeviceLoader) -> Result<vk::DescriptorPool> {
+        let pool_sizes = [
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(3 * SETS_PER_POOL),
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(4 * SETS_PER_POOL),
+        ];
+        let create_info = vk::DescriptorPoolCreateInfoBuilder::new()
+            .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .pool_sizes(&pool_sizes)
+            .max_sets(SETS_PER_POOL);
+        Ok(unsafe { device.create_descriptor_pool(&create_info, None, None) }.result()?)
+    }
+}
+
+/// A material's own uniform block and storage buffers, bound as set 2 of its pipelines. Each
+/// frame in flight has its own copy, so updates never touch data the GPU may be reading.
+pub(crate) struct MaterialDescriptors {
+    /// Present if the material declares a uniform block
+    pub uniforms: Option<StreamedBuffer<u8>>,
+    /// Storage buffers bound so far, by binding
+    pub storage_buffers: Vec<(u32, BufferId)>,
+    sets: Vec<DescriptorAllocation>,
+    /// Storage buffers each frame's set points at, which lag behind `storage_buffers` until
+    /// the frame comes around
+    written: Vec<Vec<(u32, BufferId)>>,
+}
+
+impl MaterialDescriptors {
+    /// `contents` is the initial uniform block, if the material has one
+    pub fn new(
+        contents: Option<&[u8]>,
+        layout: vk::DescriptorSetLayout,
+        frames_in_flight: usize,
+        descriptor_allocator: &mut DescriptorAllocator,
+        allocator: &mut dyn MemoryAllocator,
+        device: &DeviceLoader,
+    ) -> Result<Self> {
+        let uniforms = match contents {
+            Some(contents) => {
+                let create_info = vk::BufferCreateInfoBuilder::new()
+                    .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE);
+                Some(StreamedBuffer::new_dynamic(
+                    contents,
+                    frames_in_flight,
+                    create_info,
+                    allocator,
+                    device,
+                )?)
+            }
+            None => None,
+        };
+
+        let mut sets = Vec::with_capacity(frames_in_flight);
+        for frame in 0..frames_in_flight {
+            let allocation = descriptor_allocator.allocate(device, layout)?;
+            if let Some(uniforms) = &uniforms {
+                write_buffer(
+                    device,
+                    allocation.set,
+                    0,
+                    vk::DescriptorType::UNIFORM_BUFFER,
+                    uniforms.buffer(frame),
+                );
+            }
+            sets.push(allocation);
+        }
+
+        Ok(Self {
+            uniforms,
+            storage_buffers: Vec::new(),
+            written: vec![Vec::new(); sets.len()],
+            sets,
+        })
+    }
+
+    /// Bind `id` at `binding`, replacing any buffer bound there before. Each frame's set is
+    /// updated by `flush_storage_buffers` when that frame comes around.
+    pub fn bind_storage_buffer(&mut self, binding: u32, id: BufferId) {
+        self.storage_buffers.retain(|(b, _)| *b != binding);
+        self.storage_buffers.push((binding, id));
+    }
+
+    /// Point `frame`'s set at that frame's copy of each storage buffer bound since the set was
+    /// last updated. Call only once that frame's fence has signaled.
+    pub fn flush_storage_buffers(
+        &mut self,
+        device: &DeviceLoader,
+        storage_buffers: &Arena<BufferId, StreamedBuffer<u8>>,
+        frame: usize,
+    ) {
+        let written = &mut self.written[frame];
+        for &(binding, id) in &self.storage_buffers {
+            if written.contains(&(binding, id)) {
+                continue;
+            }
+            // Bound buffers can't be removed, so they're always present
+            let storage = storage_buffers.get(id).unwrap();
+            write_buffer(
+                device,
+                self.sets[frame].set,
+                binding,
+                vk::DescriptorType::STORAGE_BUFFER,
+                storage.buffer(frame),
+            );
+            written.retain(|(b, _)| *b != binding);
+            written.push((binding, id));
+        }
+    }
+
+    /// Set to bind when recording `frame`
+    pub fn set(&self, frame: usize) -> vk::DescriptorSet {
+        self.sets[frame].set
+    }
+
+    /// Free the descriptor sets and retire the uniform buffers. No frame in flight may be using
+    /// the sets.
+    pub fn free(
+        mut self,
+        device: &DeviceLoader,
+        descriptor_allocator: &mut DescriptorAllocator,
+        deletion_queue: &mut DeletionQueue,
+    ) -> Result<()> {
+        for allocation in self.sets.drain(..) {
+            descriptor_allocator.free(device, allocation)?;
+        }
+        if let Some(uniforms) = &mut self.uniforms {
+            uniforms.retire(deletion_queue);
+        }
+        Ok(())
+    }
+}
+
+fn write_buffer(
+    device: &DeviceLoader,
+    set: vk::DescriptorSet,
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    buffer: vk::Buffer,
+) {
+    let buffer_infos = [vk::DescriptorBufferInfoBuilder::new()
+        .buffer(buffer)
+        .offset(0)
+        .range(vk::WHOLE_SIZE)];
+    let writes = [vk::WriteDescriptorSetBuilder::new()
+        .buffer_info(&buffer_infos)
+        .descriptor_type(descriptor_type)
+        .dst_set(set)
+        .dst_binding(binding)
+        .dst_array_element(0)];
+    unsafe {
+        device.update_descriptor_sets(&writes, &[]);
+    }
+}
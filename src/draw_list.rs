@@ -0,0 +1,181 @@
+use crate::engine::{MaterialId, ObjectPushConstants};
+use crate::pipeline::Pipeline;
+use erupt::{vk1_0 as vk, DeviceLoader};
+use std::collections::HashMap;
+
+/// One indexed draw of a mesh
+pub(crate) struct DrawCommand {
+    /// Draws are recorded in ascending order of layer first
+    pub order: i32,
+    pub material: MaterialId,
+    pub vertex_buffer: vk::Buffer,
+    pub index_buffer: vk::Buffer,
+    pub n_indices: u32,
+    pub stencil_reference: u32,
+    /// Viewport depth range the draw's depth values are remapped into
+    pub depth_range: [f32; 2],
+    pub push_constants: ObjectPushConstants,
+}
+
+/// Which of a material's pipelines a draw list is recorded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DrawPass {
+    /// Depth only, skipping materials without a depth prepass
+    DepthPrepass,
+    Shaded,
+    Outline,
+}
+
+/// Depth range of draws that aren't remapped
+pub(crate) const FULL_DEPTH_RANGE: [f32; 2] = [0.0, 1.0];
+
+/// Set the full-screen viewport with the given depth range
+pub(crate) unsafe fn set_viewport(
+    device: &DeviceLoader,
+    command_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+    depth_range: [f32; 2],
+) {
+    let viewports = [vk::ViewportBuilder::new()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(depth_range[0])
+        .max_depth(depth_range[1])];
+    device.cmd_set_viewport(command_buffer, 0, &viewports);
+}
+
+/// Draws collected for a pass, sorted so each pipeline and buffer is bound only once in a row
+#[derive(Default)]
+pub(crate) struct DrawList {
+    commands: Vec<DrawCommand>,
+}
+
+impl DrawList {
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    pub fn push(&mut self, command: DrawCommand) {
+        self.commands.push(command);
+    }
+
+    /// Order draws by layer, then group them by pipeline, then by mesh, then by stencil
+    /// reference and depth range. The sort is stable, so otherwise equal draws keep their
+    /// insertion order.
+    pub fn sort(&mut self) {
+        self.commands.sort_by_key(|c| {
+            (
+                c.order,
+                c.material,
+                c.vertex_buffer.0,
+                c.index_buffer.0,
+                c.stencil_reference,
+                c.depth_range[0].to_bits(),
+                c.depth_range[1].to_bits(),
+            )
+        });
+    }
+
+    /// Record every draw, skipping binds of state that is already bound. Set 0 must already be
+    /// bound with a compatible layout; `material_set` gives each material's own set 2, if any.
+    /// Draws whose material has no pipeline for `pass` are skipped. The viewport is left with the
+    /// full depth range. Returns the number of draws recorded.
+    pub unsafe fn record(
+        &self,
+        device: &DeviceLoader,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        pipelines: &HashMap<MaterialId, Pipeline>,
+        material_set: &dyn Fn(MaterialId) -> Option<vk::DescriptorSet>,
+        pass: DrawPass,
+    ) -> u32 {
+        let mut draws = 0;
+        let mut bound_material = None;
+        let mut bound_vertex_buffer = None;
+        let mut bound_index_buffer = None;
+        let mut bound_stencil_reference = None;
+        let mut bound_depth_range = FULL_DEPTH_RANGE;
+
+        for command in &self.commands {
+            let pipeline = match pipelines.get(&command.material) {
+                Some(pipeline) => pipeline,
+                None => continue,
+            };
+
+            let handle = match pass {
+                DrawPass::DepthPrepass => match pipeline.prepass_pipeline {
+                    Some(prepass) => prepass,
+                    None => continue,
+                },
+                DrawPass::Shaded => pipeline.pipeline,
+                DrawPass::Outline => match pipeline.outline_pipeline {
+                    Some(outline) => outline,
+                    None => continue,
+                },
+            };
+
+            if bound_material != Some(command.material) {
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, handle);
+                if let Some(set) = material_set(command.material) {
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline.pipeline_layout,
+                        2,
+                        &[set],
+                        &[],
+                    );
+                }
+                bound_material = Some(command.material);
+            }
+
+            if bound_vertex_buffer != Some(command.vertex_buffer) {
+                device.cmd_bind_vertex_buffers(command_buffer, 0, &[command.vertex_buffer], &[0]);
+                bound_vertex_buffer = Some(command.vertex_buffer);
+            }
+
+            if bound_index_buffer != Some(command.index_buffer) {
+                device.cmd_bind_index_buffer(
+                    command_buffer,
+                    command.index_buffer,
+                    0,
+                    vk::IndexType::UINT16,
+                );
+                bound_index_buffer = Some(command.index_buffer);
+            }
+
+            if bound_stencil_reference != Some(command.stencil_reference) {
+                device.cmd_set_stencil_reference(
+                    command_buffer,
+                    vk::StencilFaceFlags::FRONT_AND_BACK,
+                    command.stencil_reference,
+                );
+                bound_stencil_reference = Some(command.stencil_reference);
+            }
+
+            if bound_depth_range != command.depth_range {
+                set_viewport(device, command_buffer, extent, command.depth_range);
+                bound_depth_range = command.depth_range;
+            }
+
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::mem::size_of::<ObjectPushConstants>() as u32,
+                &command.push_constants as *const ObjectPushConstants as _,
+            );
+
+            device.cmd_draw_indexed(command_buffer, command.n_indices, 1, 0, 0, 0);
+            draws += 1;
+        }
+
+        if bound_depth_range != FULL_DEPTH_RANGE {
+            set_viewport(device, command_buffer, extent, FULL_DEPTH_RANGE);
+        }
+        draws
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{Engine, MaterialId, MeshRetention, ObjectId, Vertex};
+use anyhow::Result;
+use nalgebra::Matrix4;
+use std::collections::{HashMap, HashSet};
+
+/// Geometry component; entities with it are mirrored into engine objects
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+    pub material: MaterialId,
+}
+
+/// World transform component; defaults to identity when absent
+pub struct Transform(pub Matrix4<f32>);
+
+/// Mirrors `Mesh`/`Transform` components of a hecs world into engine objects. Objects are created
+/// when an entity gains a `Mesh` and removed when it loses it or is despawned. Changing a `Mesh`
+/// component in place is not detected; remove and re-insert it instead.
+#[derive(Default)]
+pub struct HecsSync {
+    objects: HashMap<hecs::Entity, ObjectId>,
+}
+
+impl HecsSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Engine object backing an entity, if any
+    pub fn object(&self, entity: hecs::Entity) -> Option<ObjectId> {
+        self.objects.get(&entity).copied()
+    }
+
+    /// Call once per frame before `Engine::next_frame`
+    pub fn sync(&mut self, world: &hecs::World, engine: &mut Engine) -> Result<()> {
+        let mut seen = HashSet::new();
+
+        for (entity, (mesh, transform)) in world.query::<(&Mesh, Option<&Transform>)>().iter() {
+            seen.insert(entity);
+            let object = match self.objects.get(&entity) {
+                Some(object) => *object,
+                None => {
+                    let object = engine.add_object(
+                        &mesh.vertices,
+                        &mesh.indices,
+                        mesh.material,
+                        false,
+                        MeshRetention::Discard,
+                    )?;
+                    self.objects.insert(entity, object);
+                    object
+                }
+            };
+            let transform = transform.map(|t| t.0).unwrap_or_else(Matrix4::identity);
+            engine.set_transform(object, transform)?;
+        }
+
+        let removed = self
+            .objects
+            .keys()
+            .filter(|entity| !seen.contains(entity))
+            .copied()
+            .collect::<Vec<_>>();
+        for entity in removed {
+            if let Some(object) = self.objects.remove(&entity) {
+                engine.remove_object(object)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,24 +1,53 @@
-use super::{Engine, RealtimeUBO};
+use super::{Engine, FrameStats, ObjectPushConstants, RawStage, RealtimeUBO};
+use crate::audio::Listener;
 use crate::camera::Camera;
-use crate::swapchain::Swapchain;
+use crate::culling::Frustum;
+use crate::draw_list::{self, DrawCommand, DrawPass, FULL_DEPTH_RANGE};
+use crate::frame_pacing::FrameTimings;
+use crate::offscreen::OffscreenTarget;
+use crate::swapchain::{self, Swapchain};
 use anyhow::Result;
 use erupt::{extensions::khr_swapchain, vk1_0 as vk};
+use nalgebra::{Matrix4, Point3, Vector3};
+use std::time::Instant;
+
+/// Width of highlight outlines in pixels
+const OUTLINE_WIDTH: f32 = 3.0;
+/// Directions highlighted objects are shifted in to draw their outline
+const OUTLINE_DIRECTIONS: [[f32; 2]; 8] = [
+    [1.0, 0.0],
+    [-1.0, 0.0],
+    [0.0, 1.0],
+    [0.0, -1.0],
+    [0.7071, 0.7071],
+    [-0.7071, 0.7071],
+    [0.7071, -0.7071],
+    [-0.7071, -0.7071],
+];
 
 impl Engine {
-    pub fn next_frame(&mut self, camera: &Camera, time: f32) -> Result<()> {
-        // Recreate the swapchain if necessary
-        if self.swapchain.is_none() {
-            let mut swapchain = Swapchain::new(
-                &self.instance,
-                &self.device,
-                &self.hardware,
-                self.surface,
-                &mut self.allocator,
-            )?;
-            for (id, material) in self.materials.iter() {
-                swapchain.add_pipeline(&self.device, self.descriptor_set_layout, *id, material)?;
-            }
-            self.swapchain = Some(swapchain);
+    pub fn next_frame(&mut self, camera: &Camera) -> Result<()> {
+        self.next_frame_with(|_| *camera)
+    }
+
+    /// Render a frame with a camera sampled as late as possible, to cut motion-to-photon
+    /// latency: `late_update` is called once the frame's resources are free, just before the
+    /// camera and transforms are uploaded. It may also move objects, e.g. ones attached to a
+    /// tracked device, but must not render frames itself. It isn't called for frames skipped
+    /// because the swapchain was out of date.
+    pub fn next_frame_with(
+        &mut self,
+        late_update: impl FnOnce(&mut Engine) -> Camera,
+    ) -> Result<()> {
+        let frame_start = Instant::now();
+        self.clock.tick();
+
+        // Changes queued from other threads
+        self.apply_proxy_commands();
+
+        // Recreate the swapchain if necessary. Nothing is drawn while the window is minimized.
+        if !self.ensure_swapchain()? {
+            return Ok(());
         }
         let swapchain = self.swapchain.as_mut().unwrap();
         let render_pass = swapchain.render_pass; // These two needed for borrowing reasons
@@ -26,166 +55,597 @@ impl Engine {
         let aspect = extent.width as f32 / extent.height as f32;
 
         // Wait for the next frame to become available
-        let (frame_idx, frame) = self.frame_sync.next_frame(&self.device)?;
+        let frame_idx = self.frame_sync.next_frame(&self.device)?;
+
+        // Resources removed before this frame slot's previous submission can now be destroyed
+        self.deletion_queue.frame_complete(frame_idx, &self.device, &mut *self.allocator);
+
+        // This frame slot's previous submission has finished, so its timestamps are available
+        let gpu_time = match &self.gpu_timer {
+            Some(timer) => timer.read(&self.device, frame_idx)?,
+            None => None,
+        };
 
         // Wait for a swapchain image to become available and assign it the current frame
+        let frame = self.frame_sync.frame(frame_idx);
         let swapchain_image = swapchain.next_image(&self.device, frame)?;
 
         // Swapchain is out of date, reconstruct on the next pass
-        let (swapchain_image_idx, swapchain_image) = match swapchain_image {
+        let (swapchain_image_idx, swapchain_image, suboptimal) = match swapchain_image {
             Some(s) => s,
             None => {
                 self.invalidate_swapchain()?;
                 return Ok(());
             }
         };
+        let framebuffer = swapchain_image.framebuffer;
+
+        // The frame that last rendered to this image must finish before it is reused
+        self.frame_sync.wait(&self.device, swapchain_image.frame, u64::MAX)?;
+
+        self.update_animations();
+        let camera = late_update(self);
+        let camera = &self.world_camera(&camera);
+        self.update_head_locked(&camera.view());
+        self.update_terrains(&camera.eye)?;
+        self.prepare_frame(frame_idx, &camera.eye)?;
+
+        let wait_time = frame_start.elapsed();
+        let cpu_start = Instant::now();
 
-        // Upload camera matrix and time
-        let realtime_ubo = RealtimeUBO::new(&camera.matrix(aspect), time);
+        self.listener = Listener::from_camera(camera);
+
+        // Upload camera matrix, time and fog
+        let camera_matrix = camera.matrix(aspect);
+        let realtime_ubo = RealtimeUBO::new(
+            &camera_matrix,
+            self.clock.shader_time(),
+            self.clock.delta(),
+            &self.fog,
+        )
+        .with_color_transform(self.color_scale, self.color_bias);
 
         self.realtime_ubo[frame_idx].map(&self.device, &[realtime_ubo])?;
 
-        // Reset and write command buffers for this frame
+        let command_buffer = unsafe {
+            self.record_frame(frame_idx, render_pass, framebuffer, extent, &camera_matrix, None)?
+        };
+
+        // Submit to the queue
+        let frame_number =
+            self.frame_sync.submit(&self.device, self.queue, frame_idx, command_buffer, true)?;
+        self.deletion_queue.submitted(frame_idx);
+        let swapchain = self.swapchain.as_mut().unwrap();
+        swapchain.image_mut(swapchain_image_idx).frame = frame_number;
+
+        // Present to swapchain
+        let signal_semaphores = [self.frame_sync.frame(frame_idx).render_finished];
+        let swapchains = [swapchain.swapchain];
+        let image_indices = [swapchain_image_idx];
+        let present_info = khr_swapchain::PresentInfoKHRBuilder::new()
+            .wait_semaphores(&signal_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+
+        let queue_result = unsafe { self.device.queue_present_khr(self.queue, &present_info) };
+        let present = queue_result.raw;
+        match present {
+            // Not presented; the next frame rebuilds the swapchain
+            vk::Result::ERROR_OUT_OF_DATE_KHR => (),
+            vk::Result::SUBOPTIMAL_KHR => (),
+            _ => queue_result.result()?,
+        }
+        if swapchain::needs_rebuild(present, suboptimal) {
+            self.invalidate_swapchain()?;
+        }
+        if present == vk::Result::ERROR_OUT_OF_DATE_KHR {
+            return Ok(());
+        }
+
+        self.frame_pacer.push(FrameTimings {
+            wait: wait_time,
+            cpu: cpu_start.elapsed(),
+            gpu: gpu_time,
+        });
+        self.update_quality();
+
+        Ok(())
+    }
+
+    /// Create the swapchain and its pipelines if it was invalidated. Returns false if there is
+    /// no swapchain because the window has no area, e.g. while minimized.
+    pub(crate) fn ensure_swapchain(&mut self) -> Result<bool> {
+        if self.swapchain.is_none() {
+            let swapchain = Swapchain::new(
+                &self.instance,
+                &self.device,
+                &self.hardware,
+                self.surface,
+                &mut *self.allocator,
+                self.swapchain_image_count,
+                self.window_extent,
+            )?;
+            let mut swapchain = match swapchain {
+                Some(swapchain) => swapchain,
+                None => return Ok(false),
+            };
+            log::debug!(
+                "Created swapchain at {}x{}",
+                swapchain.extent.width,
+                swapchain.extent.height
+            );
+            let layouts = self.descriptor_set_layouts();
+            let materials = self.materials.iter().collect::<Vec<_>>();
+            swapchain.add_pipelines(
+                &self.device,
+                self.pipeline_cache,
+                &self.outline_shaders,
+                &layouts,
+                &materials,
+            )?;
+            self.swapchain = Some(swapchain);
+        }
+        Ok(true)
+    }
+
+    /// Bring `frame_idx`'s copies of dynamic buffers up to date once its previous submission has
+    /// finished, and sort splats for the viewpoint at `eye`
+    pub(crate) fn prepare_frame(&mut self, frame_idx: usize, eye: &Point3<f32>) -> Result<()> {
+        for object in self.objects.values_mut() {
+            object.vertices.flush(
+                &self.device,
+                &mut *self.allocator,
+                &mut self.deletion_queue,
+                frame_idx,
+            )?;
+            object.indices.flush(
+                &self.device,
+                &mut *self.allocator,
+                &mut self.deletion_queue,
+                frame_idx,
+            )?;
+        }
+        if let Some(uniform) = &mut self.user_uniform {
+            uniform.flush(
+                &self.device,
+                &mut *self.allocator,
+                &mut self.deletion_queue,
+                frame_idx,
+            )?;
+        }
+        self.lights_uniform.flush(
+            &self.device,
+            &mut *self.allocator,
+            &mut self.deletion_queue,
+            frame_idx,
+        )?;
+        for uniforms in self
+            .material_descriptors
+            .values_mut()
+            .filter_map(|d| d.uniforms.as_mut())
+        {
+            uniforms.flush(
+                &self.device,
+                &mut *self.allocator,
+                &mut self.deletion_queue,
+                frame_idx,
+            )?;
+        }
+        for buffer in self.storage_buffers.values_mut() {
+            buffer.flush(
+                &self.device,
+                &mut *self.allocator,
+                &mut self.deletion_queue,
+                frame_idx,
+            )?;
+        }
+        for descriptors in self.material_descriptors.values_mut() {
+            descriptors.flush_storage_buffers(&self.device, &self.storage_buffers, frame_idx);
+        }
+        for pool in self.chunk_pools.values_mut() {
+            pool.vertices.flush(
+                &self.device,
+                &mut *self.allocator,
+                &mut self.deletion_queue,
+                frame_idx,
+            )?;
+            pool.indices.flush(
+                &self.device,
+                &mut *self.allocator,
+                &mut self.deletion_queue,
+                frame_idx,
+            )?;
+        }
+
+        for cloud in self.splat_clouds.values_mut() {
+            cloud.sort(
+                eye,
+                &self.device,
+                &mut *self.allocator,
+                &mut self.deletion_queue,
+                frame_idx,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reset and record `frame_idx`'s command buffer, drawing the scene into `framebuffer`.
+    /// The realtime UBO for the frame must already be written. If `readback` is given, its color
+    /// image is copied out after the render pass.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) unsafe fn record_frame(
+        &mut self,
+        frame_idx: usize,
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+        camera_matrix: &Matrix4<f32>,
+        readback: Option<&OffscreenTarget>,
+    ) -> Result<vk::CommandBuffer> {
+        let frustum = Frustum::from_matrix(camera_matrix);
+        let mut stats = FrameStats::default();
         let command_buffer = self.command_buffers[frame_idx];
-        let descriptor_set = self.descriptor_sets[frame_idx];
-        unsafe {
-            self.device
-                .reset_command_buffer(command_buffer, None)
-                .result()?;
-
-            let begin_info = vk::CommandBufferBeginInfoBuilder::new();
-            self.device
-                .begin_command_buffer(command_buffer, &begin_info)
-                .result()?;
-
-            // Set render pass
-            let clear_values = [
-            vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.0, 0.0, 0.0, 1.0],
-                },
+        let descriptor_set = self.descriptor_sets[frame_idx].set;
+        self.device
+            .reset_command_buffer(command_buffer, None)
+            .result()?;
+
+        let begin_info = vk::CommandBufferBeginInfoBuilder::new();
+        self.device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .result()?;
+
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.begin(&self.device, command_buffer, frame_idx);
+        }
+
+        // Copy newly appended points into place before anything is drawn
+        for cloud in self.point_clouds.values_mut() {
+            cloud.record_uploads(
+                &self.device,
+                &mut *self.allocator,
+                command_buffer,
+                frame_idx,
+            )?;
+        }
+
+        if let Some(recorder) = &mut self.raw_recorder {
+            recorder(&self.device, command_buffer, RawStage::BeforeRenderPass);
+        }
+
+        // Offscreen targets carry their own pipelines, so they can be drawn without a swapchain
+        let pipelines = match readback {
+            Some(target) => &target.pipelines,
+            None => &self.swapchain.as_ref().unwrap().pipelines,
+        };
+
+        // Set render pass
+        let clear_values = [
+        vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 1.0],
             },
-            vk::ClearValue {
-                depth_stencil: vk::ClearDepthStencilValue {
-                    depth: 1.0,
-                    stencil: 0,
-                }
-            }];
-
-            let begin_info = vk::RenderPassBeginInfoBuilder::new()
-                .framebuffer(swapchain_image.framebuffer)
-                .render_pass(render_pass)
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent,
-                })
-                .clear_values(&clear_values);
-
-            self.device.cmd_begin_render_pass(
+        },
+        vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            }
+        }];
+
+        let begin_info = vk::RenderPassBeginInfoBuilder::new()
+            .framebuffer(framebuffer)
+            .render_pass(render_pass)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            })
+            .clear_values(&clear_values);
+
+        self.device.cmd_begin_render_pass(
+            command_buffer,
+            &begin_info,
+            vk::SubpassContents::INLINE,
+        );
+        draw_list::set_viewport(&self.device, command_buffer, extent, FULL_DEPTH_RANGE);
+        let scissors = [vk::Rect2DBuilder::new()
+            .offset(vk::Offset2D { x: 0, y: 0 })
+            .extent(extent)];
+        self.device.cmd_set_scissor(command_buffer, 0, &scissors);
+
+        // Every pipeline layout shares set 0, so it only needs binding once
+        if let Some(pipeline) = pipelines.values().next() {
+            self.device.cmd_bind_descriptor_sets(
                 command_buffer,
-                &begin_info,
-                vk::SubpassContents::INLINE,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
             );
+        }
 
-            for (pipeline_id, pipeline) in &swapchain.pipelines {
-                self.device.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    pipeline.pipeline,
-                );
+        let material_descriptors = &self.material_descriptors;
+        let material_set = |id| material_descriptors.get(&id).map(|d| d.set(frame_idx));
+
+        // Screen size of one unit at a view depth of one, for picking levels of detail
+        let pixels_per_unit = Vector3::new(
+            camera_matrix[(1, 0)],
+            camera_matrix[(1, 1)],
+            camera_matrix[(1, 2)],
+        )
+        .norm()
+            * extent.height as f32
+            / 2.0;
+        let lod_threshold = (self.lod_threshold + self.lod_bias).max(0.0);
+
+        self.draw_list.clear();
+        for object in self.objects.values() {
+            let layer = object.layer(&self.layers);
+            if !layer.visible {
+                stats.objects_hidden += 1;
+                continue;
+            }
+            stats.objects_drawn += 1;
+            let transform = self.transforms.get(object.transform_index);
+            let (vertices, indices) = object.lod(
+                transform,
+                camera_matrix,
+                pixels_per_unit,
+                lod_threshold,
+            );
+            self.draw_list.push(DrawCommand {
+                order: layer.order,
+                material: object.material,
+                vertex_buffer: vertices.buffer(frame_idx),
+                index_buffer: indices.buffer(frame_idx),
+                n_indices: indices.count(frame_idx) as u32,
+                stencil_reference: if object.highlight.is_some() { 1 } else { 0 },
+                depth_range: object.depth_range,
+                push_constants: ObjectPushConstants::new(transform, object.color)
+                    .with_emissive(object.emissive),
+            });
+        }
+        self.draw_list.sort();
+        for pass in &[DrawPass::DepthPrepass, DrawPass::Shaded] {
+            stats.draw_calls += self.draw_list.record(
+                &self.device,
+                command_buffer,
+                extent,
+                pipelines,
+                &material_set,
+                *pass,
+            );
+        }
+
+        for (pipeline_id, pipeline) in pipelines {
+            let has_chunks = self.chunk_pools.values().any(|p| p.material == *pipeline_id);
+            let has_points = self
+                .point_clouds
+                .values()
+                .any(|c| c.material == *pipeline_id && c.len > 0);
+            if !has_chunks && !has_points {
+                continue;
+            }
 
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.pipeline,
+            );
+            if let Some(set) = material_set(*pipeline_id) {
                 self.device.cmd_bind_descriptor_sets(
                     command_buffer,
                     vk::PipelineBindPoint::GRAPHICS,
                     pipeline.pipeline_layout,
-                    0,
-                    &[descriptor_set],
+                    2,
+                    &[set],
                     &[],
                 );
+            }
 
-                for object in self
-                    .objects
-                    .values_mut()
-                    .filter(|o| o.material == *pipeline_id)
-                {
-                    self.device.cmd_bind_vertex_buffers(
-                        command_buffer,
-                        0,
-                        &[object.vertices.buffer],
-                        &[0],
-                    );
+            // Chunks are never highlighted
+            self.device.cmd_set_stencil_reference(
+                command_buffer,
+                vk::StencilFaceFlags::FRONT_AND_BACK,
+                0,
+            );
+            for pool in self
+                .chunk_pools
+                .values()
+                .filter(|p| p.material == *pipeline_id)
+            {
+                self.device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    0,
+                    &[pool.vertices.buffer(frame_idx)],
+                    &[0],
+                );
 
-                    self.device.cmd_bind_index_buffer(
-                        command_buffer,
-                        object.indices.buffer,
-                        0,
-                        vk::IndexType::UINT16,
-                    );
+                self.device.cmd_bind_index_buffer(
+                    command_buffer,
+                    pool.indices.buffer(frame_idx),
+                    0,
+                    vk::IndexType::UINT16,
+                );
 
-                    let descriptor_sets = [self.descriptor_sets[frame_idx]];
-                    self.device.cmd_bind_descriptor_sets(
+                for (slot, chunk) in pool.slots.iter().enumerate() {
+                    let chunk = match chunk {
+                        Some(chunk) => chunk,
+                        None => continue,
+                    };
+                    if !frustum.intersects(&chunk.bounds.translated(&chunk.local_origin)) {
+                        stats.chunks_culled += 1;
+                        continue;
+                    }
+                    stats.chunks_drawn += 1;
+                    stats.draw_calls += 1;
+                    let model = Matrix4::new_translation(&chunk.local_origin);
+                    let push_constants = ObjectPushConstants::new(&model, [1.0; 4]);
+                    self.device.cmd_push_constants(
                         command_buffer,
-                        vk::PipelineBindPoint::GRAPHICS,
                         pipeline.pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
                         0,
-                        &descriptor_sets,
-                        &[],
+                        std::mem::size_of::<ObjectPushConstants>() as u32,
+                        &push_constants as *const ObjectPushConstants as _,
                     );
-
-                    self.device.cmd_push_constants(
+                    self.device.cmd_draw_indexed(
                         command_buffer,
-                        pipeline.pipeline_layout,
-                        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                        chunk.n_indices,
+                        1,
+                        (slot * pool.slot_indices) as u32,
+                        (slot * pool.slot_vertices) as i32,
                         0,
-                        std::mem::size_of::<RealtimeUBO>() as u32,
-                        object.transform.data.as_ptr() as _,
                     );
-
-                    self.device
-                        .cmd_draw_indexed(command_buffer, object.n_indices, 1, 0, 0, 0);
                 }
             }
 
-            self.device.cmd_end_render_pass(command_buffer);
+            for cloud in self
+                .point_clouds
+                .values()
+                .filter(|c| c.material == *pipeline_id && c.len > 0)
+            {
+                self.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline.pipeline_layout,
+                    1,
+                    &[cloud.descriptor.set],
+                    &[],
+                );
+
+                let push_constants = ObjectPushConstants::new(&cloud.transform, [1.0; 4]);
+                self.device.cmd_push_constants(
+                    command_buffer,
+                    pipeline.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    std::mem::size_of::<ObjectPushConstants>() as u32,
+                    &push_constants as *const ObjectPushConstants as _,
+                );
+
+                self.device.cmd_draw(command_buffer, cloud.len as u32, 1, 0, 0);
+                stats.draw_calls += 1;
+            }
+        }
+
+        // Splats are blended over everything opaque, so they're drawn last
+        for (pipeline_id, pipeline) in pipelines {
+            let mut clouds = self
+                .splat_clouds
+                .values()
+                .filter(|c| c.material == *pipeline_id)
+                .peekable();
+            if clouds.peek().is_none() {
+                continue;
+            }
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.pipeline,
+            );
+            if let Some(set) = material_set(*pipeline_id) {
+                self.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline.pipeline_layout,
+                    2,
+                    &[set],
+                    &[],
+                );
+            }
+
+            for cloud in clouds {
+                self.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline.pipeline_layout,
+                    1,
+                    &[cloud.descriptors[frame_idx].set],
+                    &[],
+                );
 
-            self.device.end_command_buffer(command_buffer).result()?;
+                let push_constants = ObjectPushConstants::new(&cloud.transform, [1.0; 4]);
+                self.device.cmd_push_constants(
+                    command_buffer,
+                    pipeline.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    std::mem::size_of::<ObjectPushConstants>() as u32,
+                    &push_constants as *const ObjectPushConstants as _,
+                );
+
+                self.device.cmd_draw(command_buffer, 6 * cloud.len() as u32, 1, 0, 0);
+                stats.draw_calls += 1;
+            }
         }
 
-        // Submit to the queue
-        let wait_semaphores = [frame.image_available];
-        let command_buffers = [command_buffer];
-        let signal_semaphores = [frame.render_finished];
-        let submit_info = vk::SubmitInfoBuilder::new()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-            .command_buffers(&command_buffers)
-            .signal_semaphores(&signal_semaphores);
-        unsafe {
-            self.device
-                .reset_fences(&[frame.in_flight_fence])
-                .result()?; // TODO: Move this into the swapchain next_image
-            self.device
-                .queue_submit(self.queue, &[submit_info], Some(frame.in_flight_fence))
-                .result()?;
+        // Outline pass: redraw highlighted objects shifted a few pixels in each direction, where
+        // they didn't mark the stencil buffer
+        let pixel = [2.0 / extent.width as f32, 2.0 / extent.height as f32];
+        self.draw_list.clear();
+        for object in self.objects.values() {
+            let color = match object.highlight {
+                Some(color) => color,
+                None => continue,
+            };
+            let layer = object.layer(&self.layers);
+            if !layer.visible {
+                continue;
+            }
+            let transform = self.transforms.get(object.transform_index);
+            let (vertices, indices) = object.lod(
+                transform,
+                camera_matrix,
+                pixels_per_unit,
+                lod_threshold,
+            );
+            for [x, y] in &OUTLINE_DIRECTIONS {
+                let offset = [x * OUTLINE_WIDTH * pixel[0], y * OUTLINE_WIDTH * pixel[1]];
+                self.draw_list.push(DrawCommand {
+                    material: object.material,
+                    vertex_buffer: vertices.buffer(frame_idx),
+                    index_buffer: indices.buffer(frame_idx),
+                    n_indices: indices.count(frame_idx) as u32,
+                    order: layer.order,
+                    stencil_reference: 1,
+                    depth_range: object.depth_range,
+                    push_constants: ObjectPushConstants::outline(transform, color, offset),
+                });
+            }
         }
+        self.draw_list.sort();
+        stats.draw_calls += self.draw_list.record(
+            &self.device,
+            command_buffer,
+            extent,
+            pipelines,
+            &material_set,
+            DrawPass::Outline,
+        );
 
-        // Present to swapchain
-        let swapchains = [swapchain.swapchain];
-        let image_indices = [swapchain_image_idx];
-        let present_info = khr_swapchain::PresentInfoKHRBuilder::new()
-            .wait_semaphores(&signal_semaphores)
-            .swapchains(&swapchains)
-            .image_indices(&image_indices);
+        if let Some(recorder) = &mut self.raw_recorder {
+            recorder(&self.device, command_buffer, RawStage::InRenderPass);
+        }
 
-        let queue_result = unsafe { self.device.queue_present_khr(self.queue, &present_info) };
+        self.device.cmd_end_render_pass(command_buffer);
 
-        if queue_result.raw == vk::Result::ERROR_OUT_OF_DATE_KHR {
-            self.invalidate_swapchain()?;
-            return Ok(());
-        } else {
-            queue_result.result()?;
-        };
+        if let Some(target) = readback {
+            target.record_readback(&self.device, command_buffer);
+        }
 
-        Ok(())
+        if let Some(recorder) = &mut self.raw_recorder {
+            recorder(&self.device, command_buffer, RawStage::AfterRenderPass);
+        }
+
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.end(&self.device, command_buffer, frame_idx);
+        }
+
+        self.device.end_command_buffer(command_buffer).result()?;
+        self.frame_stats = stats;
+        Ok(command_buffer)
     }
 }
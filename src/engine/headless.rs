@@ -0,0 +1,153 @@
+use super::{Engine, RealtimeUBO};
+use crate::camera::Camera;
+use crate::offscreen::OffscreenTarget;
+use anyhow::Result;
+use erupt::vk1_0 as vk;
+use nalgebra::{Matrix4, Point3};
+
+/// A viewpoint rendered by `Engine::render_test_frame`. Like the camera given to `next_frame`,
+/// `view` is in stage space, and moved into the world by `set_world_transform` and
+/// `set_world_scale`.
+#[derive(Debug, Clone, Copy)]
+pub struct TestView {
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TestView {
+    /// The view `next_frame` would render for `camera` at this size
+    pub fn from_camera(camera: &Camera, width: u32, height: u32) -> Self {
+        Self {
+            view: camera.view(),
+            projection: camera.projection(width as f32 / height as f32),
+            width,
+            height,
+        }
+    }
+}
+
+/// A stage space view matrix moved into the world, matching `Engine::world_camera`
+fn world_view(view: &Matrix4<f32>, stage_to_world: &Matrix4<f32>) -> Matrix4<f32> {
+    view * stage_to_world
+        .try_inverse()
+        .unwrap_or_else(Matrix4::identity)
+}
+
+impl Engine {
+    /// Render the scene once per view into offscreen images, through the same record and submit
+    /// path as `next_frame`, and read the results back. Nothing is presented, and no swapchain
+    /// is needed, so this also works while the window is minimized. Pixels are in the
+    /// swapchain's format (usually BGRA), row by row. With a fixed `time`, the output only
+    /// depends on the scene and the views, which makes it suitable for golden-image tests. The
+    /// engine clock is not advanced, and shaders see a delta time of zero.
+    pub fn render_test_frame(&mut self, views: &[TestView], time: f32) -> Result<Vec<Vec<u8>>> {
+        let mut images = Vec::with_capacity(views.len());
+        for view in views {
+            let extent = vk::Extent2D {
+                width: view.width,
+                height: view.height,
+            };
+            let mut target =
+                OffscreenTarget::new(&self.device, &self.hardware, &mut *self.allocator, extent)?;
+            let pixels = self.render_test_view(view, time, &mut target);
+            target.free(&self.device, &mut *self.allocator)?;
+            images.push(pixels?);
+        }
+        Ok(images)
+    }
+
+    fn render_test_view(
+        &mut self,
+        view: &TestView,
+        time: f32,
+        target: &mut OffscreenTarget,
+    ) -> Result<Vec<u8>> {
+        let layouts = self.descriptor_set_layouts();
+        let materials = self.materials.iter().collect::<Vec<_>>();
+        target.add_pipelines(
+            &self.device,
+            self.pipeline_cache,
+            &self.outline_shaders,
+            &layouts,
+            &materials,
+        )?;
+
+        let frame_idx = self.frame_sync.next_frame(&self.device)?;
+        self.deletion_queue.frame_complete(frame_idx, &self.device, &mut *self.allocator);
+
+        let world_view = world_view(&view.view, &self.stage_to_world());
+        let eye = match world_view.try_inverse() {
+            Some(inverse) => inverse.transform_point(&Point3::origin()),
+            None => Point3::origin(),
+        };
+        self.update_head_locked(&world_view);
+        self.update_terrains(&eye)?;
+        self.prepare_frame(frame_idx, &eye)?;
+
+        let camera_matrix = view.projection * world_view;
+        let realtime_ubo = RealtimeUBO::new(&camera_matrix, time, 0.0, &self.fog)
+            .with_color_transform(self.color_scale, self.color_bias);
+        self.realtime_ubo[frame_idx].map(&self.device, &[realtime_ubo])?;
+
+        let command_buffer = unsafe {
+            self.record_frame(
+                frame_idx,
+                target.render_pass,
+                target.framebuffer,
+                target.extent,
+                &camera_matrix,
+                Some(target),
+            )?
+        };
+
+        let frame_number =
+            self.frame_sync.submit(&self.device, self.queue, frame_idx, command_buffer, false)?;
+        self.deletion_queue.submitted(frame_idx);
+        self.frame_sync.wait(&self.device, frame_number, u64::MAX)?;
+
+        target.read(&self.device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{Vector3, Vector4};
+
+    #[test]
+    fn test_views_follow_the_world_transform() {
+        let camera = Camera {
+            eye: Point3::new(1.0, 1.5, 3.0),
+            at: Point3::new(0.0, 1.0, 0.0),
+            fovy: 1.2,
+            clip_near: 0.1,
+            clip_far: 100.0,
+        };
+        let scale = 2.5;
+        let stage_to_world = Matrix4::new_translation(&Vector3::new(10.0, -2.0, 4.0))
+            * Matrix4::from_euler_angles(0.0, 0.7, 0.0)
+            * Matrix4::new_scaling(scale);
+        let (width, height) = (640, 480);
+
+        // What next_frame draws with
+        let expected = camera
+            .transformed(&stage_to_world, scale)
+            .matrix(width as f32 / height as f32);
+        let view = TestView::from_camera(&camera, width, height);
+        let actual = view.projection * world_view(&view.view, &stage_to_world);
+
+        for &point in &[[0.0, 0.0, 0.0], [12.0, 1.0, 9.0], [-3.0, 4.0, 20.0]] {
+            let point = Vector4::new(point[0], point[1], point[2], 1.0);
+            let (expected, actual) = (expected * point, actual * point);
+            let (expected, actual) = (expected / expected.w, actual / actual.w);
+            assert!(
+                (expected - actual).norm() < 1e-4,
+                "{} != {}",
+                expected,
+                actual
+            );
+        }
+    }
+}
@@ -4,7 +4,8 @@ use anyhow::Result;
 impl Engine {
     pub(crate) fn invalidate_swapchain(&mut self) -> Result<()> {
         if let Some(swapchain) = &mut self.swapchain {
-            swapchain.free(&self.device, &mut self.allocator)?;
+            log::debug!("Swapchain out of date, recreating on the next frame");
+            swapchain.free(&self.device, &mut *self.allocator)?;
         }
         self.swapchain = None;
         Ok(())
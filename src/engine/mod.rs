@@ -1,52 +1,355 @@
 mod frame;
+mod headless;
 mod internals;
+mod permutations;
+mod proxy;
+mod raw;
 mod setup;
 mod unsetup;
+
+pub use headless::TestView;
+pub use permutations::{MaterialFeatures, FEATURE_CONSTANT_BASE};
+pub(crate) use permutations::ALPHA_TESTED_CONSTANT;
+pub use proxy::{EngineProxy, PendingId};
+pub use raw::{RawRecorder, RawStage};
+pub use setup::DEFAULT_FRAMES_IN_FLIGHT;
 use crate::allocated_buffer::AllocatedBuffer;
+use crate::animation::{Easing, Keyframe, Playback, Track};
+use crate::arena::{Arena, ArenaKey, Handle};
+use crate::audio::Listener;
+use crate::batch::{merge_static_meshes, StaticMesh};
+use crate::chunks::ChunkPool;
+use crate::camera::Camera;
+use crate::clock::Clock;
+use crate::deletion_queue::DeletionQueue;
+use crate::descriptors::{DescriptorAllocation, DescriptorAllocator, MaterialDescriptors};
+use crate::draw_list::{DrawList, FULL_DEPTH_RANGE};
+use crate::floating_origin::FloatingOrigin;
+use crate::fog::Fog;
+use crate::frame_pacing::{FramePacer, FramePacingReport, GpuTimer, PACING_WINDOW};
 use crate::frame_sync::FrameSync;
-use crate::hardware_query::HardwareSelection;
-use crate::pipeline::DrawType;
-use crate::pipeline::Material;
-use crate::swapchain::Swapchain;
-use crate::vertex::Vertex;
-use anyhow::Result;
+use crate::hardware_query::{Capabilities, HardwareSelection};
+use crate::lights::{Light, LightsUniform, MAX_LIGHTS};
+use crate::memory::MemoryAllocator;
+use crate::memory_stats::MemoryStats;
+use crate::mesh::{MeshData, MeshRetention};
+use crate::pipeline::{DrawType, MaterialOptions};
+use crate::quality::{QualityGovernor, QualitySettings};
+use crate::snapshot::{ObjectPose, TransformSnapshot};
+use crate::streamed_buffer::StreamedBuffer;
+use crate::pipeline::{Material, OutlineShaders};
+use permutations::ShaderFamily;
+use proxy::ProxyCommand;
+use crate::point_cloud::{Point, PointCloud};
+use crate::splats::{Splat, SplatCloud};
+use crate::swapchain::{PresentMode, Swapchain};
+use crate::terrain::{self, Heightmap, Terrain};
+use crate::transforms::TransformStore;
+use crate::vertex::{Vertex, VertexFormat};
+use anyhow::{Context, Result};
 use erupt::{
     extensions::khr_surface,
-    utils::{self, allocator::Allocator},
+    utils,
     vk1_0 as vk, DeviceLoader, InstanceLoader,
 };
-use nalgebra::Matrix4;
-use std::collections::HashMap;
+use nalgebra::{Matrix4, Point3, Vector2, Vector3};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Duration;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MaterialId(Handle);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObjectId(Handle);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkPoolId(Handle);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PointCloudId(Handle);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SplatCloudId(Handle);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferId(Handle);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerId(Handle);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LightId(Handle);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct MaterialId(u32);
+pub struct ShaderFamilyId(Handle);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct ObjectId(u32);
+pub struct TerrainId(Handle);
+
+impl ArenaKey for MaterialId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+impl ArenaKey for ObjectId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+impl ArenaKey for ChunkPoolId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+impl ArenaKey for PointCloudId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+impl ArenaKey for SplatCloudId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+impl ArenaKey for BufferId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+impl ArenaKey for LayerId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+impl ArenaKey for LightId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+impl ArenaKey for ShaderFamilyId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+impl ArenaKey for TerrainId {
+    fn from_handle(handle: Handle) -> Self {
+        Self(handle)
+    }
+
+    fn handle(self) -> Handle {
+        self.0
+    }
+}
+
+/// Returned when an ID refers to a resource which has been removed
+#[derive(Debug, Clone, Copy)]
+pub enum StaleId {
+    Object(ObjectId),
+    Material(MaterialId),
+    ChunkPool(ChunkPoolId),
+    PointCloud(PointCloudId),
+    SplatCloud(SplatCloudId),
+    Buffer(BufferId),
+    Layer(LayerId),
+    Light(LightId),
+    ShaderFamily(ShaderFamilyId),
+    Terrain(TerrainId),
+}
+
+impl std::fmt::Display for StaleId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StaleId::Object(id) => write!(f, "{:?} is stale or was never valid", id),
+            StaleId::Material(id) => write!(f, "{:?} is stale or was never valid", id),
+            StaleId::ChunkPool(id) => write!(f, "{:?} is stale or was never valid", id),
+            StaleId::PointCloud(id) => write!(f, "{:?} is stale or was never valid", id),
+            StaleId::SplatCloud(id) => write!(f, "{:?} is stale or was never valid", id),
+            StaleId::Buffer(id) => write!(f, "{:?} is stale or was never valid", id),
+            StaleId::Layer(id) => write!(f, "{:?} is stale or was never valid", id),
+            StaleId::Light(id) => write!(f, "{:?} is stale or was never valid", id),
+            StaleId::ShaderFamily(id) => write!(f, "{:?} is stale or was never valid", id),
+            StaleId::Terrain(id) => write!(f, "{:?} is stale or was never valid", id),
+        }
+    }
+}
+
+impl std::error::Error for StaleId {}
+
+/// Marks the point at which data passed to an upload call has reached the GPU: the first frame
+/// rendered with it. Check it with `Engine::is_upload_complete`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadTicket(u64);
+
+/// Summary of an object's resources, for tools and debug displays
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectInfo {
+    pub name: Option<String>,
+    pub material: MaterialId,
+    pub vertex_count: usize,
+    pub index_count: usize,
+    pub dynamic: bool,
+    /// Size of the object's vertex and index buffers, including per-frame copies
+    pub gpu_bytes: u64,
+}
+
+/// Work recorded for the most recent frame, for profiling displays and tools
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Every draw command, including depth prepass and outline draws
+    pub draw_calls: u32,
+    pub objects_drawn: u32,
+    /// Objects skipped because their layer is hidden
+    pub objects_hidden: u32,
+    pub chunks_drawn: u32,
+    /// Chunks outside the view frustum
+    pub chunks_culled: u32,
+}
 
 #[repr(C)]
 #[derive(Default, Copy, Clone)]
 pub struct RealtimeUBO {
     camera: [[f32; 4]; 4],
+    /// Seconds, wrapped to `SHADER_TIME_WRAP`
     time: f32,
+    delta_time: f32,
+    _pad: [f32; 2],
+    fog_color: [f32; 4],
+    fog_params: [f32; 3],
+    fog_mode: u32,
+    /// Final colors are `color * color_scale + color_bias`
+    color_scale: [f32; 4],
+    color_bias: [f32; 4],
 }
 
 unsafe impl bytemuck::Zeroable for RealtimeUBO {}
 unsafe impl bytemuck::Pod for RealtimeUBO {}
 
 impl RealtimeUBO {
-    pub fn new(camera: &Matrix4<f32>, time: f32) -> Self {
+    pub fn new(camera: &Matrix4<f32>, time: f32, delta_time: f32, fog: &Fog) -> Self {
+        let (fog_mode, fog_params) = fog.params();
+        let [r, g, b] = fog.color;
         Self {
             camera: *camera.as_ref(),
             time,
+            delta_time,
+            fog_color: [r, g, b, 1.0],
+            fog_params,
+            fog_mode,
+            color_scale: [1.0; 4],
+            ..Default::default()
+        }
+    }
+
+    pub fn with_color_transform(self, scale: [f32; 4], bias: [f32; 4]) -> Self {
+        Self {
+            color_scale: scale,
+            color_bias: bias,
+            ..self
+        }
+    }
+}
+
+/// Per-object data sent through push constants
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct ObjectPushConstants {
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
+    /// Screen-space shift of an outline draw, in normalized device coordinates
+    outline_offset: [f32; 2],
+    _pad: [u32; 2],
+    /// Light given off regardless of lighting, in the rgb components
+    emissive: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for ObjectPushConstants {}
+unsafe impl bytemuck::Pod for ObjectPushConstants {}
+
+impl ObjectPushConstants {
+    pub fn new(model: &Matrix4<f32>, color: [f32; 4]) -> Self {
+        Self {
+            model: *model.as_ref(),
+            color,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_emissive(self, emissive: [f32; 3]) -> Self {
+        let [r, g, b] = emissive;
+        Self {
+            emissive: [r, g, b, 0.0],
+            ..self
+        }
+    }
+
+    /// Push constants for one of the shifted copies drawn by the outline pass
+    pub fn outline(model: &Matrix4<f32>, color: [f32; 4], offset: [f32; 2]) -> Self {
+        Self {
+            outline_offset: offset,
+            ..Self::new(model, color)
         }
     }
 }
 
 pub struct Engine {
-    materials: HashMap<MaterialId, Material>,
-    objects: HashMap<ObjectId, Object>,
+    materials: Arena<MaterialId, Material>,
+    shader_families: Arena<ShaderFamilyId, ShaderFamily>,
+    objects: Arena<ObjectId, Object>,
+    /// Indexed by `Object::transform_index`
+    transforms: TransformStore,
+    animations: HashMap<ObjectId, Playback>,
+    chunk_pools: Arena<ChunkPoolId, ChunkPool>,
+    terrains: Arena<TerrainId, Terrain>,
+    point_clouds: Arena<PointCloudId, PointCloud>,
+    splat_clouds: Arena<SplatCloudId, SplatCloud>,
+    storage_buffers: Arena<BufferId, StreamedBuffer<u8>>,
+    layers: Arena<LayerId, Layer>,
     swapchain: Option<Swapchain>,
-    allocator: Allocator,
+    /// Requested number of swapchain images, if not the default
+    swapchain_image_count: Option<u32>,
+    /// Window size in physical pixels, last reported through `resize`
+    window_extent: Option<vk::Extent2D>,
+    allocator: Box<dyn MemoryAllocator>,
+    deletion_queue: DeletionQueue,
     frame_sync: FrameSync,
     command_pool: vk::CommandPool,
     command_buffers: Vec<vk::CommandBuffer>,
@@ -55,12 +358,52 @@ pub struct Engine {
     hardware: HardwareSelection,
     surface: khr_surface::SurfaceKHR,
     instance: InstanceLoader,
-    descriptor_pool: vk::DescriptorPool,
+    /// Whether `device`, `surface` and `instance` were created by the engine, rather than
+    /// borrowed through `from_raw_vulkan`, and so are destroyed along with it
+    owns_vulkan: bool,
+    descriptor_allocator: DescriptorAllocator,
+    pipeline_cache: vk::PipelineCache,
+    outline_shaders: OutlineShaders,
     descriptor_set_layout: vk::DescriptorSetLayout,
-    descriptor_sets: Vec<vk::DescriptorSet>,
+    /// Set 1 of every pipeline: a point or splat cloud's storage buffer, and for splats their
+    /// draw order
+    point_cloud_set_layout: vk::DescriptorSetLayout,
+    descriptor_sets: Vec<DescriptorAllocation>,
+    material_descriptors: HashMap<MaterialId, MaterialDescriptors>,
+    /// Set 0, binding 1, once `set_user_uniform` has been called
+    user_uniform: Option<StreamedBuffer<u8>>,
+    lights: Arena<LightId, Light>,
+    /// Set 0, binding 2
+    lights_uniform: StreamedBuffer<LightsUniform>,
     realtime_ubo: Vec<AllocatedBuffer<RealtimeUBO>>,
-    next_material_id: u32,
-    next_object_id: u32,
+    fog: Fog,
+    color_scale: [f32; 4],
+    color_bias: [f32; 4],
+    clock: Clock,
+    floating_origin: FloatingOrigin,
+    /// Stage space, in which cameras are given, to world space
+    world_transform: Matrix4<f32>,
+    /// World units per stage unit, applied before `world_transform`
+    world_scale: f32,
+    /// Pixels of error allowed when picking levels of detail
+    lod_threshold: f32,
+    /// Added to `lod_threshold`, from the quality governor's current settings
+    lod_bias: f32,
+    /// Quality governor and its target frame time, if one is installed
+    quality_governor: Option<(QualityGovernor, Duration)>,
+    /// Frames presented since the quality governor was last fed a report
+    frames_since_quality_update: usize,
+    listener: Listener,
+    frame_pacer: FramePacer,
+    gpu_timer: Option<GpuTimer>,
+    /// Reused every frame to avoid reallocating
+    draw_list: DrawList,
+    frame_stats: FrameStats,
+    /// Application commands recorded into each frame, set through `set_raw_recorder`
+    raw_recorder: Option<RawRecorder>,
+    /// Cloned into every `EngineProxy`
+    proxy_sender: Sender<ProxyCommand>,
+    proxy_commands: Receiver<ProxyCommand>,
     _entry: utils::loading::DefaultEntryLoader,
 }
 
@@ -71,117 +414,1675 @@ impl Engine {
         fragment: &[u8],
         draw_type: DrawType,
     ) -> Result<MaterialId> {
-        let id = MaterialId(self.next_material_id);
-        self.next_material_id += 1;
-        let material = Material::new(&self.device, vertex, fragment, draw_type)?;
+        self.load_material_with_options(vertex, fragment, draw_type, Default::default())
+    }
+
+    pub fn load_material_with_options(
+        &mut self,
+        vertex: &[u8],
+        fragment: &[u8],
+        draw_type: DrawType,
+        mut options: MaterialOptions,
+    ) -> Result<MaterialId> {
+        self.hardware.capabilities.clamp_options(&mut options)?;
+        let name = options.name.clone();
+        let user_uniform = self.user_uniform.is_some();
+        let material = Material::new(
+            &self.device,
+            vertex,
+            fragment,
+            draw_type,
+            options,
+            user_uniform,
+        )
+        .with_context(|| match &name {
+            Some(name) => format!("Failed to load material \"{}\"", name),
+            None => "Failed to load material".to_string(),
+        })?;
+        let id = self.materials.insert(material);
+        self.create_material_descriptors(id, None)?;
+        let layouts = self.descriptor_set_layouts();
         if let Some(swapchain) = &mut self.swapchain {
-            swapchain.add_pipeline(&self.device, self.descriptor_set_layout, id, &material)?;
+            let material = self.materials.get(id).unwrap();
+            swapchain.add_pipeline(
+                &self.device,
+                self.pipeline_cache,
+                &self.outline_shaders,
+                &layouts,
+                id,
+                material,
+            )?;
         }
-        self.materials.insert(id, material);
         Ok(id)
     }
 
-    pub fn unload_material(&mut self, material: MaterialId) {
-        if let Some(mut mat) = self.materials.remove(&material) {
-            mat.free(&self.device);
+    /// Replace a material's shaders in place, keeping its ID and settings
+    pub fn reload_material(
+        &mut self,
+        material: MaterialId,
+        vertex: &[u8],
+        fragment: &[u8],
+    ) -> Result<()> {
+        let old = self
+            .materials
+            .get_mut(material)
+            .ok_or(StaleId::Material(material))?;
+        let mut new =
+            old.with_shaders(&self.device, vertex, fragment, self.user_uniform.is_some())?;
+
+        // The old pipeline may still be in use by frames in flight
+        unsafe {
+            self.device.device_wait_idle().result()?;
+        }
+        std::mem::swap(old, &mut new);
+        new.free(&self.device);
+
+        // Sets allocated with the old material's layout can't be used with the new pipeline
+        let (contents, storage_buffers) = match self.material_descriptors.remove(&material) {
+            Some(descriptors) => {
+                let contents = descriptors.uniforms.as_ref().map(|u| u.contents().to_vec());
+                let storage_buffers = descriptors.storage_buffers.clone();
+                descriptors.free(
+                    &self.device,
+                    &mut self.descriptor_allocator,
+                    &mut self.deletion_queue,
+                )?;
+                (contents, storage_buffers)
+            }
+            None => (None, Vec::new()),
+        };
+        self.create_material_descriptors(material, contents.as_deref())?;
+        for (binding, buffer) in storage_buffers {
+            self.bind_storage_buffer(material, binding, buffer)?;
+        }
+
+        let layouts = self.descriptor_set_layouts();
+        if let Some(swapchain) = &mut self.swapchain {
+            swapchain.remove_pipeline(&self.device, material);
+            let material_data = self.materials.get(material).unwrap();
+            swapchain.add_pipeline(
+                &self.device,
+                self.pipeline_cache,
+                &self.outline_shaders,
+                &layouts,
+                material,
+                material_data,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn unload_material(&mut self, material: MaterialId) -> Result<()> {
+        anyhow::ensure!(
+            self.materials.contains(material),
+            StaleId::Material(material)
+        );
+
+        // The material's pipeline and descriptor sets may still be in use by frames in flight.
+        // Nothing is removed until the wait succeeds, so a failure leaves the material intact.
+        unsafe {
+            self.device.device_wait_idle().result()?;
         }
+        let mut mat = self.materials.remove(material).unwrap();
+        if let Some(descriptors) = self.material_descriptors.remove(&material) {
+            descriptors.free(&self.device, &mut self.descriptor_allocator, &mut self.deletion_queue)?;
+        }
+        mat.free(&self.device);
         if let Some(swapchain) = &mut self.swapchain {
             swapchain.remove_pipeline(&self.device, material);
         }
+        Ok(())
+    }
+
+    /// Update a material's uniform block, declared with `MaterialOptions::uniform_bytes`. Takes
+    /// effect from the next frame.
+    pub fn set_material_uniforms<T: bytemuck::Pod>(
+        &mut self,
+        material: MaterialId,
+        value: &T,
+    ) -> Result<()> {
+        anyhow::ensure!(
+            self.materials.contains(material),
+            StaleId::Material(material)
+        );
+        let uniforms = self
+            .material_descriptors
+            .get_mut(&material)
+            .and_then(|d| d.uniforms.as_mut())
+            .ok_or_else(|| anyhow::format_err!("Material has no uniform block"))?;
+        let bytes = bytemuck::bytes_of(value);
+        anyhow::ensure!(
+            bytes.len() == uniforms.contents().len(),
+            "Uniform size must match the material's uniform_bytes"
+        );
+        uniforms.write(bytes)
+    }
+
+    /// Update the user uniform block, bound at set 0, binding 1 of every pipeline, for
+    /// application-wide shader inputs such as weather or audio levels. The first call creates
+    /// it with the size of `T`, and must come before loading materials whose shaders read it;
+    /// later calls must use a type of the same size. Takes effect from the next frame.
+    pub fn set_user_uniform<T: bytemuck::Pod>(&mut self, value: &T) -> Result<()> {
+        let bytes = bytemuck::bytes_of(value);
+        if let Some(uniform) = &mut self.user_uniform {
+            anyhow::ensure!(
+                bytes.len() == uniform.contents().len(),
+                "User uniform size must match its first value"
+            );
+            return uniform.write(bytes);
+        }
+
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let uniform = StreamedBuffer::new_dynamic(
+            bytes,
+            self.command_buffers.len(),
+            create_info,
+            &mut *self.allocator,
+            &self.device,
+        )?;
+
+        // The sets may be in use by frames in flight
+        unsafe {
+            self.device.device_wait_idle().result()?;
+        }
+        for (frame, allocation) in self.descriptor_sets.iter().enumerate() {
+            let buffer_infos = [vk::DescriptorBufferInfoBuilder::new()
+                .buffer(uniform.buffer(frame))
+                .offset(0)
+                .range(vk::WHOLE_SIZE)];
+            let writes = [vk::WriteDescriptorSetBuilder::new()
+                .buffer_info(&buffer_infos)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .dst_set(allocation.set)
+                .dst_binding(1)
+                .dst_array_element(0)];
+            unsafe {
+                self.device.update_descriptor_sets(&writes, &[]);
+            }
+        }
+        self.user_uniform = Some(uniform);
+        Ok(())
+    }
+
+    /// Add a light, visible to shaders from the next frame. At most `MAX_LIGHTS` may exist at
+    /// once.
+    pub fn add_light(&mut self, light: Light) -> Result<LightId> {
+        anyhow::ensure!(
+            self.lights.values().count() < MAX_LIGHTS,
+            "Cannot add more than {} lights",
+            MAX_LIGHTS
+        );
+        let id = self.lights.insert(light);
+        self.write_lights()?;
+        Ok(id)
+    }
+
+    pub fn set_light(&mut self, id: LightId, light: Light) -> Result<()> {
+        *self.lights.get_mut(id).ok_or(StaleId::Light(id))? = light;
+        self.write_lights()
+    }
+
+    pub fn light(&self, id: LightId) -> Option<&Light> {
+        self.lights.get(id)
+    }
+
+    pub fn remove_light(&mut self, id: LightId) -> Result<()> {
+        self.lights.remove(id).ok_or(StaleId::Light(id))?;
+        self.write_lights()
+    }
+
+    /// IDs of every light
+    pub fn lights(&self) -> impl Iterator<Item = LightId> + '_ {
+        self.lights.keys()
+    }
+
+    fn write_lights(&mut self) -> Result<()> {
+        let uniform = LightsUniform::new(self.lights.values());
+        self.lights_uniform.write(&[uniform])
     }
 
+    /// Add an object. `dynamic` objects may have their vertices re-uploaded; `retention` decides
+    /// whether a host-side copy of the geometry is kept, readable through `mesh_data()`.
     pub fn add_object(
         &mut self,
         vertices: &[Vertex],
         indices: &[u16],
         material: MaterialId,
         dynamic: bool,
+        retention: MeshRetention,
     ) -> Result<ObjectId> {
-        let id = ObjectId(self.next_object_id);
-        self.next_object_id += 1;
+        let vertex_format = self
+            .materials
+            .get(material)
+            .ok_or(StaleId::Material(material))?
+            .vertex_format();
+        let encoded = vertex_format.encode(vertices);
+        let (vertex_buffer, index_buffer) = self.create_mesh_buffers(&encoded, indices, dynamic)?;
 
-        let n_indices = indices.len() as u32;
+        let object = Object {
+            material,
+            indices: index_buffer,
+            vertices: vertex_buffer,
+            vertex_format,
+            transform_index: self.transforms.len(),
+            color: [1.0; 4],
+            emissive: [0.0; 3],
+            head_locked: None,
+            highlight: None,
+            depth_range: FULL_DEPTH_RANGE,
+            layer: None,
+            lods: Vec::new(),
+            name: None,
+            user_data: None,
+            mesh_data: match retention {
+                MeshRetention::Retain => Some(MeshData::new(vertices, indices)),
+                MeshRetention::Discard => None,
+            },
+        };
+
+        let id = self.objects.insert(object);
+        self.transforms.push(id, Matrix4::identity());
+
+        let stats = self.memory_stats();
+        if stats.near_budget() {
+            log::warn!(
+                "GPU memory usage at {:.0}% of device-local budget",
+                stats.budget_fraction() * 100.0
+            );
+        }
 
+        Ok(id)
+    }
+
+    /// Vertex and index buffers for one mesh, with one copy per frame in flight if `dynamic`
+    fn create_mesh_buffers(
+        &mut self,
+        encoded: &[u8],
+        indices: &[u16],
+        dynamic: bool,
+    ) -> Result<(StreamedBuffer<u8>, StreamedBuffer<u16>)> {
         //TODO: Use staging buffers as well!
         let create_info = vk::BufferCreateInfoBuilder::new()
             .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
-        let mut vertex_buffer = AllocatedBuffer::new(
-            vertices.len(),
-            create_info,
-            &mut self.allocator,
-            &self.device,
-        )?;
-        vertex_buffer.map(&self.device, vertices)?;
-        if !dynamic {
-            vertex_buffer = vertex_buffer.gpu_only(
+        let vertex_buffer = if dynamic {
+            StreamedBuffer::new_dynamic(
+                encoded,
+                self.command_buffers.len(),
+                create_info,
+                &mut *self.allocator,
+                &self.device,
+            )?
+        } else {
+            StreamedBuffer::new_static(
+                encoded,
+                create_info,
+                &mut *self.allocator,
                 &self.device,
-                &mut self.allocator,
                 self.command_pool,
                 self.queue,
-            )?;
-        }
+            )?
+        };
 
         let create_info = vk::BufferCreateInfoBuilder::new()
             .usage(vk::BufferUsageFlags::INDEX_BUFFER)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
-        let mut index_buffer = AllocatedBuffer::new(
-            indices.len(),
-            create_info,
-            &mut self.allocator,
-            &self.device,
-        )?;
-        index_buffer.map(&self.device, indices)?;
-        index_buffer = index_buffer.gpu_only(
-            &self.device,
-            &mut self.allocator,
-            self.command_pool,
-            self.queue,
-        )?;
+        let index_buffer = if dynamic {
+            StreamedBuffer::new_dynamic(
+                indices,
+                self.command_buffers.len(),
+                create_info,
+                &mut *self.allocator,
+                &self.device,
+            )?
+        } else {
+            StreamedBuffer::new_static(
+                indices,
+                create_info,
+                &mut *self.allocator,
+                &self.device,
+                self.command_pool,
+                self.queue,
+            )?
+        };
 
-        let object = Object {
+        Ok((vertex_buffer, index_buffer))
+    }
+
+    /// Add a static object along with `lod_count - 1` coarser levels of detail simplified from
+    /// it. Each frame, the coarsest level whose simplification error covers at most
+    /// `lod_threshold` pixels on screen is drawn. Levels that would not remove any triangles
+    /// are skipped. The mesh is optimized for the vertex cache first.
+    pub fn add_object_auto_lod(
+        &mut self,
+        mesh: &MeshData,
+        material: MaterialId,
+        lod_count: usize,
+    ) -> Result<ObjectId> {
+        anyhow::ensure!(
+            lod_count > 0,
+            "An object needs at least one level of detail"
+        );
+        let full = mesh.optimized()?;
+        let id = self.add_object(
+            &full.vertices,
+            &full.indices,
             material,
-            indices: index_buffer,
-            vertices: vertex_buffer,
-            n_indices,
-            transform: Matrix4::identity(),
-        };
+            false,
+            MeshRetention::Discard,
+        )?;
 
-        self.objects.insert(id, object);
+        // Start with cells a 64th of the mesh's size, doubling every level
+        let (min, max) = full.vertices.iter().fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(mut min, mut max), vertex| {
+                for i in 0..3 {
+                    min[i] = min[i].min(vertex.pos[i]);
+                    max[i] = max[i].max(vertex.pos[i]);
+                }
+                (min, max)
+            },
+        );
+        let size = Vector3::from(max) - Vector3::from(min);
+        let mut cell_size = size.norm() / 64.0;
 
+        let vertex_format = self.objects.get(id).unwrap().vertex_format;
+        let mut index_count = full.indices.len();
+        let mut lods = Vec::new();
+        for _ in 1..lod_count {
+            if !cell_size.is_normal() {
+                break;
+            }
+            let lod = full.simplified(cell_size)?;
+            if lod.indices.is_empty() || lod.indices.len() >= index_count {
+                cell_size *= 2.0;
+                continue;
+            }
+            index_count = lod.indices.len();
+            let encoded = vertex_format.encode(&lod.vertices);
+            let (vertices, indices) = self.create_mesh_buffers(&encoded, &lod.indices, false)?;
+            lods.push(Lod {
+                vertices,
+                indices,
+                // Vertices move at most across their cell
+                error: cell_size * 3f32.sqrt(),
+            });
+            cell_size *= 2.0;
+        }
+        log::debug!("Generated {} levels of detail for {:?}", lods.len(), id);
+        self.objects.get_mut(id).unwrap().lods = lods;
         Ok(id)
     }
 
-    pub fn reupload_vertices(&mut self, object: ObjectId, vertices: &[Vertex]) -> Result<()> {
-        if let Some(object) = self.objects.get_mut(&object) {
-            object.vertices.map(&self.device, vertices)?;
-        }
+    /// Screen-space error, in pixels, up to which coarser levels of detail are drawn. Higher
+    /// values trade quality for speed. Defaults to 1. The quality governor's
+    /// `QualitySettings::lod_bias` is added on top.
+    pub fn set_lod_threshold(&mut self, pixels: f32) {
+        self.lod_threshold = pixels;
+    }
+
+    /// Add an object from positions alone, e.g. as loaded from a file without vertex colors.
+    /// Vertices get `Vertex::DEFAULT_COLOR`, which materials using `VertexFormat::Position`
+    /// never upload.
+    pub fn add_object_positions(
+        &mut self,
+        positions: &[[f32; 3]],
+        indices: &[u16],
+        material: MaterialId,
+        dynamic: bool,
+        retention: MeshRetention,
+    ) -> Result<ObjectId> {
+        let vertices = positions
+            .iter()
+            .map(|&pos| Vertex::from_position(pos))
+            .collect::<Vec<_>>();
+        self.add_object(&vertices, indices, material, dynamic, retention)
+    }
+
+    /// Add an object with a name, shown in error messages and `object_info()` and given to its
+    /// buffers for debugging tools
+    pub fn add_object_named(
+        &mut self,
+        name: &str,
+        vertices: &[Vertex],
+        indices: &[u16],
+        material: MaterialId,
+        dynamic: bool,
+        retention: MeshRetention,
+    ) -> Result<ObjectId> {
+        let id = self.add_object(vertices, indices, material, dynamic, retention)?;
+        self.set_object_name(id, name)?;
+        Ok(id)
+    }
+
+    pub fn set_object_name(&mut self, id: ObjectId, name: &str) -> Result<()> {
+        let object = self.objects.get_mut(id).ok_or(StaleId::Object(id))?;
+        object
+            .vertices
+            .set_debug_name(&self.device, &format!("{} (vertices)", name))?;
+        object
+            .indices
+            .set_debug_name(&self.device, &format!("{} (indices)", name))?;
+        object.name = Some(name.to_string());
         Ok(())
     }
 
-    pub fn remove_object(&mut self, id: ObjectId) -> Result<()> {
-        // Figure out how not to wait?
-        unsafe {
-            self.device.device_wait_idle().result()?;
+    pub fn object_name(&self, id: ObjectId) -> Option<&str> {
+        self.objects.get(id).and_then(|o| o.name.as_deref())
+    }
+
+    /// Name given through `MaterialOptions::name`
+    pub fn material_name(&self, id: MaterialId) -> Option<&str> {
+        self.materials.get(id).and_then(|m| m.name())
+    }
+
+    /// Merge static meshes sharing a material into a single object, drawn with one call
+    pub fn add_static_batch(
+        &mut self,
+        meshes: &[StaticMesh],
+        material: MaterialId,
+    ) -> Result<ObjectId> {
+        let (vertices, indices) = merge_static_meshes(meshes)?;
+        self.add_object(&vertices, &indices, material, false, MeshRetention::Discard)
+    }
+
+    /// Replace a dynamic object's vertices. The vertex count may differ from the previous upload.
+    pub fn reupload_vertices(&mut self, id: ObjectId, vertices: &[Vertex]) -> Result<UploadTicket> {
+        let object = self.objects.get_mut(id).ok_or(StaleId::Object(id))?;
+        object
+            .vertices
+            .write(&object.vertex_format.encode(vertices))
+            .with_context(|| object.describe(id))?;
+        if let Some(mesh_data) = &mut object.mesh_data {
+            mesh_data.vertices.clear();
+            mesh_data.vertices.extend_from_slice(vertices);
         }
-        if let Some(mut object) = self.objects.remove(&id) {
-            object.vertices.free(&self.device, &mut self.allocator)?;
-            object.indices.free(&self.device, &mut self.allocator)?;
+        Ok(self.upload_ticket())
+    }
+
+    /// Replace a dynamic object's indices. The index count may differ from the previous upload.
+    pub fn reupload_indices(&mut self, id: ObjectId, indices: &[u16]) -> Result<UploadTicket> {
+        let object = self.objects.get_mut(id).ok_or(StaleId::Object(id))?;
+        object
+            .indices
+            .write(indices)
+            .with_context(|| object.describe(id))?;
+        if let Some(mesh_data) = &mut object.mesh_data {
+            mesh_data.indices.clear();
+            mesh_data.indices.extend_from_slice(indices);
         }
-        Ok(())
+        Ok(self.upload_ticket())
+    }
+
+    /// Overwrite part of a dynamic object's vertices, starting at vertex `offset`
+    pub fn update_vertices(
+        &mut self,
+        id: ObjectId,
+        offset: usize,
+        vertices: &[Vertex],
+    ) -> Result<UploadTicket> {
+        let object = self.objects.get_mut(id).ok_or(StaleId::Object(id))?;
+        object
+            .vertices
+            .write_range(
+                offset * object.vertex_format.stride(),
+                &object.vertex_format.encode(vertices),
+            )
+            .with_context(|| object.describe(id))?;
+        if let Some(mesh_data) = &mut object.mesh_data {
+            mesh_data.vertices[offset..offset + vertices.len()].copy_from_slice(vertices);
+        }
+        Ok(self.upload_ticket())
     }
 
-    pub fn set_transform(&mut self, id: ObjectId, transform: Matrix4<f32>) {
-        if let Some(object) = self.objects.get_mut(&id) {
-            object.transform = transform;
+    /// Overwrite part of a dynamic object's indices, starting at index `offset`
+    pub fn update_indices(
+        &mut self,
+        id: ObjectId,
+        offset: usize,
+        indices: &[u16],
+    ) -> Result<UploadTicket> {
+        let object = self.objects.get_mut(id).ok_or(StaleId::Object(id))?;
+        object
+            .indices
+            .write_range(offset, indices)
+            .with_context(|| object.describe(id))?;
+        if let Some(mesh_data) = &mut object.mesh_data {
+            mesh_data.indices[offset..offset + indices.len()].copy_from_slice(indices);
         }
+        Ok(self.upload_ticket())
     }
-}
 
-pub struct Object {
-    pub indices: AllocatedBuffer<u16>,
-    pub vertices: AllocatedBuffer<Vertex>,
-    pub n_indices: u32,
-    pub material: MaterialId,
-    pub transform: Matrix4<f32>,
+    /// Host-side geometry of an object, if it was added with `MeshRetention::Retain`
+    pub fn mesh_data(&self, id: ObjectId) -> Option<&MeshData> {
+        self.objects.get(id).and_then(|o| o.mesh_data.as_ref())
+    }
+
+    /// Give an object new geometry, static or dynamic alike. Its buffers are recreated with the
+    /// same dynamic-ness and the old ones freed once no frame in flight uses them. LODs are
+    /// dropped, since they were built from the previous mesh.
+    pub fn replace_mesh(
+        &mut self,
+        id: ObjectId,
+        vertices: &[Vertex],
+        indices: &[u16],
+    ) -> Result<UploadTicket> {
+        let object = self.objects.get(id).ok_or(StaleId::Object(id))?;
+        let dynamic = object.vertices.is_dynamic();
+        let encoded = object.vertex_format.encode(vertices);
+        let (mut vertex_buffer, mut index_buffer) =
+            self.create_mesh_buffers(&encoded, indices, dynamic)?;
+
+        let object = self.objects.get_mut(id).unwrap();
+        std::mem::swap(&mut object.vertices, &mut vertex_buffer);
+        std::mem::swap(&mut object.indices, &mut index_buffer);
+        let lods = std::mem::take(&mut object.lods);
+        if let Some(mesh_data) = &mut object.mesh_data {
+            *mesh_data = MeshData::new(vertices, indices);
+        }
+
+        vertex_buffer.retire(&mut self.deletion_queue);
+        index_buffer.retire(&mut self.deletion_queue);
+        for mut lod in lods {
+            lod.vertices.retire(&mut self.deletion_queue);
+            lod.indices.retire(&mut self.deletion_queue);
+        }
+        Ok(self.upload_ticket())
+    }
+
+    /// Remove an object. Its buffers are destroyed once frames in flight are done with them.
+    pub fn remove_object(&mut self, id: ObjectId) -> Result<()> {
+        let mut object = self.objects.remove(id).ok_or(StaleId::Object(id))?;
+        self.animations.remove(&id);
+        if let Some(moved) = self.transforms.swap_remove(object.transform_index) {
+            self.objects.get_mut(moved).unwrap().transform_index = object.transform_index;
+        }
+        object.vertices.retire(&mut self.deletion_queue);
+        object.indices.retire(&mut self.deletion_queue);
+        for lod in &mut object.lods {
+            lod.vertices.retire(&mut self.deletion_queue);
+            lod.indices.retire(&mut self.deletion_queue);
+        }
+        Ok(())
+    }
+
+    /// Create a layer, for showing, hiding and ordering a group of objects together, such as
+    /// UI, debug geometry or the world. It starts visible, with render order 0.
+    pub fn create_layer(&mut self) -> LayerId {
+        self.layers.insert(Layer::default())
+    }
+
+    /// Remove a layer. Its objects are kept, outside of any layer.
+    pub fn remove_layer(&mut self, layer: LayerId) -> Result<()> {
+        self.layers.remove(layer).ok_or(StaleId::Layer(layer))?;
+        for object in self.objects.values_mut() {
+            if object.layer == Some(layer) {
+                object.layer = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move an object into a layer, or out of any layer with `None`
+    pub fn set_object_layer(&mut self, id: ObjectId, layer: Option<LayerId>) -> Result<()> {
+        if let Some(layer) = layer {
+            anyhow::ensure!(self.layers.contains(layer), StaleId::Layer(layer));
+        }
+        self.object_mut(id)?.layer = layer;
+        Ok(())
+    }
+
+    pub fn object_layer(&self, id: ObjectId) -> Option<LayerId> {
+        self.objects.get(id).and_then(|o| o.layer)
+    }
+
+    /// Show or hide every object in a layer
+    pub fn set_layer_visible(&mut self, layer: LayerId, visible: bool) -> Result<()> {
+        self.layers
+            .get_mut(layer)
+            .ok_or(StaleId::Layer(layer))?
+            .visible = visible;
+        Ok(())
+    }
+
+    /// Layers are drawn in ascending order, e.g. UI with a high order over the world. Objects
+    /// outside any layer have order 0. Chunks and point clouds are drawn after all objects.
+    pub fn set_layer_order(&mut self, layer: LayerId, order: i32) -> Result<()> {
+        self.layers
+            .get_mut(layer)
+            .ok_or(StaleId::Layer(layer))?
+            .order = order;
+        Ok(())
+    }
+
+    /// Remove several objects, e.g. when despawning many entities in one frame. Fails without
+    /// removing anything if any ID is stale or given more than once.
+    pub fn remove_objects(&mut self, ids: &[ObjectId]) -> Result<()> {
+        let mut seen = HashSet::with_capacity(ids.len());
+        for &id in ids {
+            anyhow::ensure!(self.objects.contains(id), StaleId::Object(id));
+            anyhow::ensure!(seen.insert(id), "{:?} is listed more than once", id);
+        }
+        for &id in ids {
+            self.remove_object(id)?;
+        }
+        Ok(())
+    }
+
+    /// Add a pool of `n_slots` chunk meshes drawn with `material`, each holding up to
+    /// `slot_vertices` vertices and `slot_indices` indices. Chunks are culled against the view
+    /// frustum individually, and can be replaced without waiting on the GPU.
+    pub fn add_chunk_pool(
+        &mut self,
+        material: MaterialId,
+        n_slots: usize,
+        slot_vertices: usize,
+        slot_indices: usize,
+    ) -> Result<ChunkPoolId> {
+        let vertex_format = self
+            .materials
+            .get(material)
+            .ok_or(StaleId::Material(material))?
+            .vertex_format();
+        anyhow::ensure!(
+            vertex_format == VertexFormat::Full,
+            "Chunk pools need a material with VertexFormat::Full"
+        );
+        let pool = ChunkPool::new(
+            material,
+            n_slots,
+            slot_vertices,
+            slot_indices,
+            self.command_buffers.len(),
+            &mut *self.allocator,
+            &self.device,
+        )?;
+        Ok(self.chunk_pools.insert(pool))
+    }
+
+    /// Replace the mesh in a chunk slot. Vertices are relative to `origin`, a world position
+    /// such as the chunk's corner, which keeps them small and precise however far the chunk is
+    /// from the world origin. Indices are relative to this chunk's vertices. An empty mesh
+    /// clears the slot.
+    pub fn set_chunk(
+        &mut self,
+        pool: ChunkPoolId,
+        slot: usize,
+        origin: &Point3<f64>,
+        vertices: &[Vertex],
+        indices: &[u16],
+    ) -> Result<UploadTicket> {
+        self.chunk_pools
+            .get_mut(pool)
+            .ok_or(StaleId::ChunkPool(pool))?
+            .set(slot, origin, &self.floating_origin, vertices, indices)?;
+        Ok(self.upload_ticket())
+    }
+
+    pub fn clear_chunk(&mut self, pool: ChunkPoolId, slot: usize) -> Result<UploadTicket> {
+        self.set_chunk(pool, slot, &Point3::origin(), &[], &[])
+    }
+
+    /// Remove a chunk pool once frames in flight are done with it
+    pub fn remove_chunk_pool(&mut self, pool: ChunkPoolId) -> Result<()> {
+        let mut pool = self
+            .chunk_pools
+            .remove(pool)
+            .ok_or(StaleId::ChunkPool(pool))?;
+        pool.retire(&mut self.deletion_queue);
+        Ok(())
+    }
+
+    /// Add terrain from a heightmap, centered on the world origin in X and Z and spanning
+    /// `extent` along them, with heights from 0 to `max_height` along Y. The terrain is split
+    /// into chunks of `TERRAIN_CHUNK_QUADS` samples a side, which are culled individually and
+    /// remeshed at coarser levels of detail as they get further from the camera. `material`
+    /// must use `VertexFormat::Full`, and the heightmap must have one height and color, if any,
+    /// per sample.
+    pub fn add_terrain(
+        &mut self,
+        heightmap: Heightmap,
+        extent: Vector2<f32>,
+        max_height: f32,
+        material: MaterialId,
+    ) -> Result<TerrainId> {
+        heightmap.validate()?;
+        let vertex_format = self
+            .materials
+            .get(material)
+            .ok_or(StaleId::Material(material))?
+            .vertex_format();
+        anyhow::ensure!(
+            vertex_format == VertexFormat::Full,
+            "Terrain needs a material with VertexFormat::Full, got {:?}",
+            vertex_format
+        );
+        let pool = self.add_chunk_pool(
+            material,
+            Terrain::chunk_count(&heightmap),
+            terrain::CHUNK_VERTICES,
+            terrain::CHUNK_INDICES,
+        )?;
+        let terrain = Terrain::new(pool, heightmap, extent, max_height);
+        Ok(self.terrains.insert(terrain))
+    }
+
+    pub fn remove_terrain(&mut self, id: TerrainId) -> Result<()> {
+        let terrain = self.terrains.remove(id).ok_or(StaleId::Terrain(id))?;
+        self.remove_chunk_pool(terrain.pool)
+    }
+
+    /// Remesh terrain chunks whose level of detail changed for an eye at `eye`
+    pub(crate) fn update_terrains(&mut self, eye: &Point3<f32>) -> Result<()> {
+        // Terrain is laid out in world space
+        let eye = self.floating_origin.to_world(eye);
+        for terrain in self.terrains.values_mut() {
+            let pool = self
+                .chunk_pools
+                .get_mut(terrain.pool)
+                .ok_or(StaleId::ChunkPool(terrain.pool))?;
+            for chunk in terrain.update(&eye) {
+                pool.set(
+                    chunk.slot,
+                    &chunk.origin,
+                    &self.floating_origin,
+                    &chunk.vertices,
+                    &chunk.indices,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a point cloud with room for `capacity` points, drawn with a `DrawType::PointCloud`
+    /// material
+    pub fn add_point_cloud(
+        &mut self,
+        material: MaterialId,
+        capacity: usize,
+        points: &[Point],
+    ) -> Result<PointCloudId> {
+        let draw_type = self
+            .materials
+            .get(material)
+            .ok_or(StaleId::Material(material))?
+            .draw_type();
+        anyhow::ensure!(
+            draw_type == DrawType::PointCloud,
+            "Point clouds need a DrawType::PointCloud material"
+        );
+        let mut cloud = PointCloud::new(
+            material,
+            capacity,
+            self.command_buffers.len(),
+            self.point_cloud_set_layout,
+            &mut self.descriptor_allocator,
+            &mut *self.allocator,
+            &self.device,
+        )?;
+        cloud.append(points, &mut *self.allocator, &self.device)?;
+        Ok(self.point_clouds.insert(cloud))
+    }
+
+    /// Add points after the existing ones. They become visible from the next frame.
+    pub fn append_points(&mut self, id: PointCloudId, points: &[Point]) -> Result<UploadTicket> {
+        self.point_clouds
+            .get_mut(id)
+            .ok_or(StaleId::PointCloud(id))?
+            .append(points, &mut *self.allocator, &self.device)?;
+        Ok(self.upload_ticket())
+    }
+
+    pub fn clear_points(&mut self, id: PointCloudId) -> Result<()> {
+        self.point_clouds
+            .get_mut(id)
+            .ok_or(StaleId::PointCloud(id))?
+            .clear(&self.device, &mut *self.allocator);
+        Ok(())
+    }
+
+    pub fn set_point_cloud_transform(
+        &mut self,
+        id: PointCloudId,
+        transform: Matrix4<f32>,
+    ) -> Result<()> {
+        self.point_clouds
+            .get_mut(id)
+            .ok_or(StaleId::PointCloud(id))?
+            .transform = transform;
+        Ok(())
+    }
+
+    /// Remove a point cloud once frames in flight are done with it
+    pub fn remove_point_cloud(&mut self, id: PointCloudId) -> Result<()> {
+        let mut cloud = self
+            .point_clouds
+            .remove(id)
+            .ok_or(StaleId::PointCloud(id))?;
+        cloud.retire(&self.device, &mut *self.allocator, &mut self.deletion_queue);
+        Ok(())
+    }
+
+    /// Add a cloud of gaussian splats drawn with a `DrawType::Splats` material, e.g. a captured
+    /// scene. Splats are sorted back to front on the host whenever the camera moves. Fails if
+    /// `splats` is empty.
+    pub fn add_splats(&mut self, material: MaterialId, splats: &[Splat]) -> Result<SplatCloudId> {
+        let draw_type = self
+            .materials
+            .get(material)
+            .ok_or(StaleId::Material(material))?
+            .draw_type();
+        anyhow::ensure!(
+            draw_type == DrawType::Splats,
+            "Splat clouds need a DrawType::Splats material"
+        );
+        let cloud = SplatCloud::new(
+            material,
+            splats,
+            self.command_buffers.len(),
+            self.point_cloud_set_layout,
+            &mut self.descriptor_allocator,
+            &mut *self.allocator,
+            &self.device,
+            self.command_pool,
+            self.queue,
+        )?;
+        Ok(self.splat_clouds.insert(cloud))
+    }
+
+    pub fn set_splat_transform(&mut self, id: SplatCloudId, transform: Matrix4<f32>) -> Result<()> {
+        self.splat_clouds
+            .get_mut(id)
+            .ok_or(StaleId::SplatCloud(id))?
+            .transform = transform;
+        Ok(())
+    }
+
+    /// Remove a splat cloud once frames in flight are done with it
+    pub fn remove_splats(&mut self, id: SplatCloudId) -> Result<()> {
+        let mut cloud = self
+            .splat_clouds
+            .remove(id)
+            .ok_or(StaleId::SplatCloud(id))?;
+        cloud.retire(&mut self.deletion_queue);
+        Ok(())
+    }
+
+    /// Create a storage buffer of `len` bytes, initially zeroed, which materials declaring
+    /// `MaterialOptions::storage_buffers` can read, e.g. for vertex pulling or skinning palettes
+    pub fn create_storage_buffer(&mut self, len: usize) -> Result<BufferId> {
+        anyhow::ensure!(len > 0, "Storage buffers must not be empty");
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = StreamedBuffer::new_dynamic(
+            &vec![0u8; len],
+            self.command_buffers.len(),
+            create_info,
+            &mut *self.allocator,
+            &self.device,
+        )?;
+        Ok(self.storage_buffers.insert(buffer))
+    }
+
+    /// Overwrite part of a storage buffer, starting `offset` bytes in. Takes effect from the
+    /// next frame.
+    pub fn write_storage_buffer<T: bytemuck::Pod>(
+        &mut self,
+        id: BufferId,
+        offset: usize,
+        data: &[T],
+    ) -> Result<UploadTicket> {
+        let buffer = self
+            .storage_buffers
+            .get_mut(id)
+            .ok_or(StaleId::Buffer(id))?;
+        buffer.write_range(offset, bytemuck::cast_slice(data))?;
+        Ok(self.upload_ticket())
+    }
+
+    /// Read `buffer` through `binding` of `material`'s set 2, replacing any buffer bound there
+    /// before. Takes effect from the next frame.
+    pub fn bind_storage_buffer(
+        &mut self,
+        material: MaterialId,
+        binding: u32,
+        buffer: BufferId,
+    ) -> Result<()> {
+        let material_data = self
+            .materials
+            .get(material)
+            .ok_or(StaleId::Material(material))?;
+        anyhow::ensure!(
+            (1..=material_data.storage_buffers()).contains(&binding),
+            "Material declares storage buffers at bindings 1 through {}, not {}",
+            material_data.storage_buffers(),
+            binding
+        );
+        anyhow::ensure!(
+            self.storage_buffers.contains(buffer),
+            StaleId::Buffer(buffer)
+        );
+        self.material_descriptors
+            .get_mut(&material)
+            .unwrap()
+            .bind_storage_buffer(binding, buffer);
+        Ok(())
+    }
+
+    /// Remove a storage buffer once frames in flight are done with it. Fails if a material
+    /// still reads it.
+    pub fn remove_storage_buffer(&mut self, id: BufferId) -> Result<()> {
+        anyhow::ensure!(self.storage_buffers.contains(id), StaleId::Buffer(id));
+        if let Some((material, _)) = self
+            .material_descriptors
+            .iter()
+            .find(|(_, d)| d.storage_buffers.iter().any(|(_, b)| *b == id))
+        {
+            anyhow::bail!("{:?} is still bound to {:?}", id, material);
+        }
+        let mut buffer = self.storage_buffers.remove(id).unwrap();
+        buffer.retire(&mut self.deletion_queue);
+        Ok(())
+    }
+
+    /// Frame time statistics over recent frames; frames slower than `target` count as missed
+    pub fn frame_pacing_report(&self, target: Duration) -> FramePacingReport {
+        self.frame_pacer.report(target)
+    }
+
+    /// Let `governor` adjust quality to keep frames under `target`, e.g. the headset's refresh
+    /// period. It is fed a pacing report every `PACING_WINDOW` frames. Its LOD bias is applied
+    /// immediately; the engine renders at the output resolution without multisampling, so the
+    /// render scale and sample count are left for the application to read from
+    /// `quality_settings()`. `None` removes the governor and its LOD bias.
+    pub fn set_quality_governor(&mut self, governor: Option<QualityGovernor>, target: Duration) {
+        self.lod_bias = governor.as_ref().map_or(0.0, |g| g.settings().lod_bias);
+        self.quality_governor = governor.map(|g| (g, target));
+        self.frames_since_quality_update = 0;
+    }
+
+    /// Settings chosen by the quality governor, if one is installed
+    pub fn quality_settings(&self) -> Option<QualitySettings> {
+        self.quality_governor.as_ref().map(|(g, _)| g.settings())
+    }
+
+    /// Feed the quality governor once a full pacing window has passed since its last report
+    fn update_quality(&mut self) {
+        self.frames_since_quality_update += 1;
+        if self.frames_since_quality_update < PACING_WINDOW {
+            return;
+        }
+        self.frames_since_quality_update = 0;
+        let pacer = &self.frame_pacer;
+        if let Some((governor, target)) = &mut self.quality_governor {
+            if let Some(settings) = governor.update(&pacer.report(*target), *target) {
+                log::debug!("Quality changed to {:?}", settings);
+                self.lod_bias = settings.lod_bias;
+            }
+        }
+    }
+
+    /// Choose how frames are presented, trading latency against tearing. Unsupported modes fall
+    /// back to the next lowest latency mode without tearing, ending at `Fifo`. Returns the mode
+    /// now in use. Recreates the swapchain.
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> Result<PresentMode> {
+        let mut mode = mode;
+        while !self.hardware.present_modes.contains(&mode.to_vk()) {
+            match mode.fallback() {
+                Some(fallback) => mode = fallback,
+                None => break,
+            }
+        }
+        log::info!("Using present mode {:?}", mode);
+        self.hardware.present_mode = mode.to_vk();
+        self.invalidate_swapchain()?;
+        Ok(mode)
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        PresentMode::from_vk(self.hardware.present_mode)
+    }
+
+    /// Ask for `count` swapchain images, clamped to what the surface allows. `None` restores
+    /// the default of one more than the minimum. Recreates the swapchain.
+    pub fn set_swapchain_image_count(&mut self, count: Option<u32>) -> Result<()> {
+        self.swapchain_image_count = count;
+        self.invalidate_swapchain()
+    }
+
+    /// Tell the engine the window's inner size in physical pixels changed, e.g. on
+    /// `WindowEvent::Resized` or `WindowEvent::ScaleFactorChanged`. The swapchain is rebuilt at
+    /// the new size on the next frame, and no frames are drawn while either side is zero.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        self.window_extent = Some(vk::Extent2D { width, height });
+        let unchanged = self.swapchain.as_ref().map_or(false, |swapchain| {
+            swapchain.extent.width == width && swapchain.extent.height == height
+        });
+        if unchanged {
+            return Ok(());
+        }
+        self.invalidate_swapchain()
+    }
+
+    /// Draw calls and culling results of the most recently recorded frame
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// Number of frames the CPU may record ahead of the GPU
+    pub fn frames_in_flight(&self) -> usize {
+        self.command_buffers.len()
+    }
+
+    /// Number of the most recently submitted frame, counting from 1. Zero before the first.
+    pub fn submitted_frame(&self) -> u64 {
+        self.frame_sync.submitted()
+    }
+
+    /// Number of the most recent frame the GPU has finished rendering
+    pub fn completed_frame(&self) -> Result<u64> {
+        self.frame_sync.completed(&self.device)
+    }
+
+    /// Block until the GPU has finished rendering frame `number`, as returned by
+    /// `submitted_frame()`. Returns false if `timeout` elapsed first.
+    pub fn wait_for_frame(&self, number: u64, timeout: Duration) -> Result<bool> {
+        let timeout = timeout.as_nanos().min(u64::MAX as u128) as u64;
+        self.frame_sync.wait(&self.device, number, timeout)
+    }
+
+    /// Ticket covering every upload made so far, including resources created since the last
+    /// frame, e.g. to tell when a newly added object's geometry is on the GPU
+    pub fn upload_ticket(&self) -> UploadTicket {
+        // Data written now is used from the next frame submitted
+        UploadTicket(self.frame_sync.submitted() + 1)
+    }
+
+    /// Whether the GPU has finished a frame rendered with the ticket's data
+    pub fn is_upload_complete(&self, ticket: UploadTicket) -> Result<bool> {
+        Ok(self.frame_sync.completed(&self.device)? >= ticket.0)
+    }
+
+    /// Limits and optional features of the device in use
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.hardware.capabilities
+    }
+
+    /// Report GPU memory held by the engine, by usage
+    pub fn memory_stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats::new(&self.instance, &self.hardware);
+        for object in self.objects.values() {
+            stats.vertex_bytes += object.vertices.size();
+            stats.index_bytes += object.indices.size();
+            for lod in &object.lods {
+                stats.vertex_bytes += lod.vertices.size();
+                stats.index_bytes += lod.indices.size();
+            }
+        }
+        for pool in self.chunk_pools.values() {
+            stats.vertex_bytes += pool.vertices.size();
+            stats.index_bytes += pool.indices.size();
+        }
+        for cloud in self.point_clouds.values() {
+            stats.vertex_bytes += cloud.buffer.size();
+        }
+        for cloud in self.splat_clouds.values() {
+            stats.vertex_bytes += cloud.buffer.size();
+            stats.index_bytes += cloud.order.size();
+        }
+        stats.uniform_bytes = self.realtime_ubo.iter().map(|ubo| ubo.size()).sum();
+        stats.storage_bytes = self.storage_buffers.values().map(|b| b.size()).sum();
+        if let Some(swapchain) = &self.swapchain {
+            stats.image_bytes = swapchain.image_bytes();
+        }
+        stats
+    }
+
+    /// Time as of the current frame
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Switch the clock to a fixed step, or seek it
+    pub fn clock_mut(&mut self) -> &mut Clock {
+        &mut self.clock
+    }
+
+    pub fn set_fog(&mut self, fog: Fog) {
+        self.fog = fog;
+    }
+
+    /// Multiply every final color by `scale`, passed to shaders in the realtime uniform. Fading
+    /// `scale` to zero fades the scene to `bias`, e.g. to black for comfortable transitions.
+    /// Defaults to ones.
+    pub fn set_color_scale(&mut self, scale: [f32; 4]) {
+        self.color_scale = scale;
+    }
+
+    /// Add `bias` to every final color, after `set_color_scale`. Defaults to zeroes.
+    pub fn set_color_bias(&mut self, bias: [f32; 4]) {
+        self.color_bias = bias;
+    }
+
+    /// Set the light an object gives off, added to its shaded color, e.g. for glowing UI or
+    /// beacons. Values above 1 are allowed for HDR targets. Defaults to black.
+    pub fn set_emissive(&mut self, id: ObjectId, emissive: [f32; 3]) -> Result<()> {
+        self.object_mut(id)?.emissive = emissive;
+        Ok(())
+    }
+
+    /// Set an object's tint, multiplied with its shaded color
+    pub fn set_color(&mut self, id: ObjectId, color: [f32; 4]) -> Result<()> {
+        self.object_mut(id)?.color = color;
+        Ok(())
+    }
+
+    pub fn color(&self, id: ObjectId) -> Option<[f32; 4]> {
+        self.objects.get(id).map(|o| o.color)
+    }
+
+    /// Draw an outline of the given color around an object, or remove it with `None`. The
+    /// outline is a few pixels wide and drawn by the engine's own shaders, whatever the
+    /// object's material. Like the object, it is hidden behind nearer geometry.
+    pub fn set_highlight(&mut self, id: ObjectId, color: Option<[f32; 4]>) -> Result<()> {
+        self.object_mut(id)?.highlight = color;
+        Ok(())
+    }
+
+    /// Remap an object's depth into `min..max` of the depth buffer. A range close to zero, e.g.
+    /// `0.0..0.05`, keeps hands or a cockpit in front of world geometry they would otherwise
+    /// clip into. Defaults to `0.0..1.0`.
+    pub fn set_depth_range(&mut self, id: ObjectId, min: f32, max: f32) -> Result<()> {
+        anyhow::ensure!(
+            0.0 <= min && min <= max && max <= 1.0,
+            "Depth range must lie within 0..1"
+        );
+        self.object_mut(id)?.depth_range = [min, max];
+        Ok(())
+    }
+
+    pub fn set_transform(&mut self, id: ObjectId, transform: Matrix4<f32>) -> Result<()> {
+        let index = self.object_mut(id)?.transform_index;
+        *self.transforms.get_mut(index) = transform;
+        Ok(())
+    }
+
+    pub fn set_transforms(&mut self, transforms: &[(ObjectId, Matrix4<f32>)]) -> Result<()> {
+        for (id, transform) in transforms {
+            self.set_transform(*id, *transform)?;
+        }
+        Ok(())
+    }
+
+    /// Capture every object's transform, stamped with `time`
+    pub fn snapshot_transforms(&self, time: f32) -> TransformSnapshot {
+        let poses = self
+            .objects
+            .iter()
+            .map(|(id, object)| {
+                let transform = self.transforms.get(object.transform_index);
+                (id, ObjectPose::from_matrix(transform))
+            })
+            .collect();
+        TransformSnapshot { time, poses }
+    }
+
+    /// Set the transforms of the objects in a snapshot. Objects that no longer exist are skipped,
+    /// as snapshots may arrive after an object was removed locally.
+    pub fn apply_snapshot(&mut self, snapshot: &TransformSnapshot) {
+        for (id, pose) in &snapshot.poses {
+            if let Some(object) = self.objects.get(*id) {
+                *self.transforms.get_mut(object.transform_index) = pose.to_matrix();
+            }
+        }
+    }
+
+    /// Mutable access to every object's transform, for animating many objects at once
+    pub fn transforms_mut(&mut self) -> impl Iterator<Item = (ObjectId, &mut Matrix4<f32>)> {
+        let (owners, transforms) = self.transforms.slices_mut();
+        owners.iter().copied().zip(transforms.iter_mut())
+    }
+
+    /// Every object's transform as one contiguous slice, alongside the object each belongs to.
+    /// Suited to updating transforms in bulk, e.g. split into chunks across threads. The order
+    /// is unspecified and changes when objects are removed.
+    pub fn transform_slices_mut(&mut self) -> (&[ObjectId], &mut [Matrix4<f32>]) {
+        self.transforms.slices_mut()
+    }
+
+    pub fn transform(&self, id: ObjectId) -> Option<&Matrix4<f32>> {
+        let object = self.objects.get(id)?;
+        Some(self.transforms.get(object.transform_index))
+    }
+
+    /// Offset between world and rendered coordinates. Transforms, point and splat clouds, and
+    /// cameras are all given relative to its origin; chunks are placed at world positions.
+    pub fn floating_origin(&self) -> &FloatingOrigin {
+        &self.floating_origin
+    }
+
+    /// Rebase automatically in `rebase` once the camera is further than `distance` from the
+    /// origin
+    pub fn set_rebase_distance(&mut self, distance: Option<f32>) {
+        self.floating_origin.set_rebase_distance(distance);
+    }
+
+    /// Move the origin to `origin`, shifting every object, animation, point cloud and splat
+    /// cloud so that they keep their world positions. The stage moves with them, so cameras need
+    /// no change.
+    /// Returns the shift, which the application must subtract from any other local positions it
+    /// keeps.
+    pub fn shift_origin(&mut self, origin: &Point3<f64>) -> Vector3<f32> {
+        let shift = self.floating_origin.shift_to(origin);
+        let translation = Matrix4::new_translation(&-shift);
+        self.world_transform = translation * self.world_transform;
+        let (_, transforms) = self.transforms.slices_mut();
+        for transform in transforms {
+            *transform = translation * *transform;
+        }
+        for playback in self.animations.values_mut() {
+            playback.track.translate(&-shift);
+        }
+        for cloud in self.point_clouds.values_mut() {
+            cloud.transform = translation * cloud.transform;
+        }
+        for cloud in self.splat_clouds.values_mut() {
+            cloud.transform = translation * cloud.transform;
+        }
+        for light in self.lights.values_mut() {
+            light.translate(&-shift);
+        }
+        for pool in self.chunk_pools.values_mut() {
+            pool.rebase(&self.floating_origin);
+        }
+        // The light uniform is dynamic and written whole, so this can't fail
+        self.write_lights().unwrap();
+        shift
+    }
+
+    /// Shift the origin to the camera if it has strayed past the rebase distance. Call before
+    /// `next_frame`. Returns the shift if one happened.
+    pub fn rebase(&mut self, camera: &Camera) -> Option<Vector3<f32>> {
+        let distance = self.floating_origin.rebase_distance()?;
+        let eye = self.stage_to_world().transform_point(&camera.eye);
+        if eye.coords.norm() <= distance {
+            return None;
+        }
+        let eye = self.floating_origin.to_world(&eye);
+        let shift = self.shift_origin(&eye);
+        log::debug!("Rebased floating origin to {:?}", eye);
+        Some(shift)
+    }
+
+    /// Place the stage, the space cameras passed to `next_frame` are given in, within the
+    /// world. Moving it moves the player through the world; see `locomotion::snap_turn` and
+    /// `locomotion::calibrate_height`. Should only rotate about the Y axis, as cameras keep
+    /// their up vector. Defaults to the identity.
+    pub fn set_world_transform(&mut self, transform: Matrix4<f32>) {
+        self.world_transform = transform;
+    }
+
+    pub fn world_transform(&self) -> &Matrix4<f32> {
+        &self.world_transform
+    }
+
+    /// Scale the stage by `scale` world units per stage unit, e.g. above 1 to tower over the
+    /// world as a giant, below 1 to shrink into a miniature one, or to match assets authored
+    /// in other units. Cameras, clip planes and head-locked objects scale together, so the
+    /// world appears resized rather than distorted. Stage positions passed to the
+    /// `locomotion` helpers must be multiplied by the scale. Defaults to 1.
+    pub fn set_world_scale(&mut self, scale: f32) -> Result<()> {
+        anyhow::ensure!(
+            scale.is_finite() && scale > 0.0,
+            "World scale must be positive, got {}",
+            scale
+        );
+        self.world_scale = scale;
+        Ok(())
+    }
+
+    pub fn world_scale(&self) -> f32 {
+        self.world_scale
+    }
+
+    /// Transform from stage space into the world, including the world scale
+    fn stage_to_world(&self) -> Matrix4<f32> {
+        self.world_transform * Matrix4::new_scaling(self.world_scale)
+    }
+
+    /// A stage space camera moved into the world
+    fn world_camera(&self, camera: &Camera) -> Camera {
+        camera.transformed(&self.stage_to_world(), self.world_scale)
+    }
+
+    /// Place an object at a world position, keeping the rest of its transform
+    pub fn set_world_position(&mut self, id: ObjectId, position: &Point3<f64>) -> Result<()> {
+        let local = self.floating_origin.to_local(position);
+        let index = self.object_mut(id)?.transform_index;
+        self.transforms
+            .get_mut(index)
+            .fixed_slice_mut::<nalgebra::U3, nalgebra::U1>(0, 3)
+            .copy_from(&local.coords);
+        Ok(())
+    }
+
+    /// Lock an object to the camera, e.g. for a HUD reticle, placing it at `offset` in view
+    /// space (looking down -Z) every frame and overriding `set_transform`. `None` leaves the
+    /// object where it last was.
+    pub fn set_head_locked(&mut self, id: ObjectId, offset: Option<Matrix4<f32>>) -> Result<()> {
+        self.object_mut(id)?.head_locked = offset;
+        Ok(())
+    }
+
+    /// Move head-locked objects to follow the camera with the given view matrix
+    pub(crate) fn update_head_locked(&mut self, view: &Matrix4<f32>) {
+        // Offsets are in stage units, like the camera
+        let pose = match view.try_inverse() {
+            Some(pose) => pose * Matrix4::new_scaling(self.world_scale),
+            None => return,
+        };
+        for object in self.objects.values() {
+            if let Some(offset) = &object.head_locked {
+                *self.transforms.get_mut(object.transform_index) = pose * offset;
+            }
+        }
+    }
+
+    /// Play a keyframed animation on an object's transform from the current frame, replacing
+    /// any animation already playing on it. Overrides `set_transform` while it plays. Keyframes
+    /// are relative to the floating origin, like transforms, and move with it.
+    pub fn play_animation(&mut self, id: ObjectId, track: Track) -> Result<()> {
+        self.start_animation(id, track, None)
+    }
+
+    /// Like `play_animation`, calling `on_complete` with the object's ID once a non-looping
+    /// track has finished. It isn't called if the animation is stopped or replaced first.
+    pub fn play_animation_then(
+        &mut self,
+        id: ObjectId,
+        track: Track,
+        on_complete: impl FnOnce(ObjectId) + 'static,
+    ) -> Result<()> {
+        self.start_animation(id, track, Some(Box::new(on_complete)))
+    }
+
+    fn start_animation(
+        &mut self,
+        id: ObjectId,
+        track: Track,
+        on_complete: Option<Box<dyn FnOnce(ObjectId)>>,
+    ) -> Result<()> {
+        anyhow::ensure!(self.objects.contains(id), StaleId::Object(id));
+        anyhow::ensure!(!track.keyframes.is_empty(), "Track has no keyframes");
+        anyhow::ensure!(
+            track.keyframes.windows(2).all(|k| k[0].time <= k[1].time),
+            "Track keyframes must be in ascending order of time"
+        );
+        let playback = Playback {
+            track,
+            start: self.clock.elapsed(),
+            on_complete,
+        };
+        self.animations.insert(id, playback);
+        Ok(())
+    }
+
+    /// Stop an object's animation, leaving it at its current pose. Returns whether one was
+    /// playing.
+    pub fn stop_animation(&mut self, id: ObjectId) -> bool {
+        self.animations.remove(&id).is_some()
+    }
+
+    pub fn is_animating(&self, id: ObjectId) -> bool {
+        self.animations.contains_key(&id)
+    }
+
+    /// Move an object smoothly from its current transform to `target` over `duration` seconds
+    /// of clock time, as a two-keyframe animation. Shear in either transform is discarded.
+    pub fn tween_transform(
+        &mut self,
+        id: ObjectId,
+        target: &Matrix4<f32>,
+        duration: f32,
+        easing: Easing,
+    ) -> Result<()> {
+        let current = self.transform(id).ok_or(StaleId::Object(id))?;
+        let track = Track {
+            keyframes: vec![
+                Keyframe {
+                    time: 0.0,
+                    pose: ObjectPose::from_matrix(current),
+                },
+                Keyframe {
+                    time: duration.max(0.0),
+                    pose: ObjectPose::from_matrix(target),
+                },
+            ],
+            easing,
+            looping: false,
+        };
+        self.play_animation(id, track)
+    }
+
+    /// Pose every animated object for the current frame's clock time, and finish animations
+    /// which have played through
+    pub(crate) fn update_animations(&mut self) {
+        let now = self.clock.elapsed();
+        let mut finished = Vec::new();
+        for (&id, playback) in &self.animations {
+            let (pose, done) = playback.sample(now);
+            if let (Some(pose), Some(object)) = (pose, self.objects.get(id)) {
+                *self.transforms.get_mut(object.transform_index) = pose.to_matrix();
+            }
+            if done {
+                finished.push(id);
+            }
+        }
+        for id in finished {
+            let playback = self.animations.remove(&id);
+            if let Some(on_complete) = playback.and_then(|p| p.on_complete) {
+                on_complete(id);
+            }
+        }
+    }
+
+    /// Listener pose for spatial audio, as of the last call to `next_frame`
+    pub fn listener(&self) -> Listener {
+        self.listener
+    }
+
+    /// World-space position of an object's origin, for placing audio emitters
+    pub fn object_position(&self, id: ObjectId) -> Result<Point3<f32>> {
+        let object = self.objects.get(id).ok_or(StaleId::Object(id))?;
+        let transform = self.transforms.get(object.transform_index);
+        Ok(transform.transform_point(&Point3::origin()))
+    }
+
+    /// Associate arbitrary application data with an object, replacing any previous data
+    pub fn set_user_data(&mut self, id: ObjectId, data: Box<dyn Any>) -> Result<()> {
+        self.object_mut(id)?.user_data = Some(data);
+        Ok(())
+    }
+
+    pub fn user_data(&self, id: ObjectId) -> Option<&dyn Any> {
+        self.objects.get(id).and_then(|o| o.user_data.as_deref())
+    }
+
+    pub fn user_data_mut(&mut self, id: ObjectId) -> Option<&mut dyn Any> {
+        self.objects.get_mut(id).and_then(|o| o.user_data.as_deref_mut())
+    }
+
+    /// IDs of every loaded material
+    pub fn materials(&self) -> impl Iterator<Item = MaterialId> + '_ {
+        self.materials.keys()
+    }
+
+    /// IDs of every object
+    pub fn objects(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        self.objects.keys()
+    }
+
+    pub fn object_info(&self, id: ObjectId) -> Option<ObjectInfo> {
+        let object = self.objects.get(id)?;
+        Some(ObjectInfo {
+            name: object.name.clone(),
+            material: object.material,
+            vertex_count: object.vertices.latest_count() / object.vertex_format.stride(),
+            index_count: object.indices.latest_count(),
+            dynamic: object.vertices.is_dynamic(),
+            gpu_bytes: object.vertices.size()
+                + object.indices.size()
+                + object
+                    .lods
+                    .iter()
+                    .map(|lod| lod.vertices.size() + lod.indices.size())
+                    .sum::<usize>(),
+        })
+    }
+
+    pub fn objects_with_material(
+        &self,
+        material: MaterialId,
+    ) -> impl Iterator<Item = ObjectId> + '_ {
+        self.objects
+            .iter()
+            .filter(move |(_, o)| o.material == material)
+            .map(|(id, _)| id)
+    }
+
+    fn object_mut(&mut self, id: ObjectId) -> Result<&mut Object> {
+        Ok(self.objects.get_mut(id).ok_or(StaleId::Object(id))?)
+    }
+
+    /// Allocate a material's own descriptor sets, if it declares a uniform block or storage
+    /// buffers. `contents` defaults to zeroes.
+    fn create_material_descriptors(
+        &mut self,
+        id: MaterialId,
+        contents: Option<&[u8]>,
+    ) -> Result<()> {
+        let material = self.materials.get(id).ok_or(StaleId::Material(id))?;
+        let layout = match material.descriptor_set_layout() {
+            Some(layout) => layout,
+            None => return Ok(()),
+        };
+        let zeroes = vec![0; material.uniform_bytes()];
+        let contents = if material.uniform_bytes() > 0 {
+            Some(contents.unwrap_or(&zeroes))
+        } else {
+            None
+        };
+        let descriptors = MaterialDescriptors::new(
+            contents,
+            layout,
+            self.command_buffers.len(),
+            &mut self.descriptor_allocator,
+            &mut *self.allocator,
+            &self.device,
+        )?;
+        self.material_descriptors.insert(id, descriptors);
+        Ok(())
+    }
+
+    /// Descriptor set layouts shared by every pipeline
+    fn descriptor_set_layouts(&self) -> [vk::DescriptorSetLayout; 2] {
+        [self.descriptor_set_layout, self.point_cloud_set_layout]
+    }
+}
+
+/// Visibility and render order shared by a group of objects
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Layer {
+    pub visible: bool,
+    pub order: i32,
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            order: 0,
+        }
+    }
+}
+
+pub struct Object {
+    pub indices: StreamedBuffer<u16>,
+    /// Encoded in `vertex_format`
+    pub vertices: StreamedBuffer<u8>,
+    pub vertex_format: VertexFormat,
+    pub material: MaterialId,
+    /// Position of the object's transform in `Engine::transforms`
+    pub transform_index: usize,
+    pub color: [f32; 4],
+    pub emissive: [f32; 3],
+    /// Offset from the camera, if the object follows it
+    pub head_locked: Option<Matrix4<f32>>,
+    pub highlight: Option<[f32; 4]>,
+    pub depth_range: [f32; 2],
+    pub layer: Option<LayerId>,
+    /// Coarser versions of the mesh, by increasing error
+    pub lods: Vec<Lod>,
+    pub name: Option<String>,
+    pub user_data: Option<Box<dyn Any>>,
+    pub mesh_data: Option<MeshData>,
+}
+
+/// A simplified version of an object's mesh
+pub struct Lod {
+    pub vertices: StreamedBuffer<u8>,
+    pub indices: StreamedBuffer<u16>,
+    /// Furthest any vertex moved from the full mesh, in object units
+    pub error: f32,
+}
+
+impl Object {
+    /// Buffers of the coarsest level of detail whose error spans at most `threshold` pixels,
+    /// for an object at `transform` seen through `camera_matrix`. `pixels_per_unit` is the size
+    /// on screen of one unit at a view depth of one.
+    fn lod(
+        &self,
+        transform: &Matrix4<f32>,
+        camera_matrix: &Matrix4<f32>,
+        pixels_per_unit: f32,
+        threshold: f32,
+    ) -> (&StreamedBuffer<u8>, &StreamedBuffer<u16>) {
+        let full = (&self.vertices, &self.indices);
+        if self.lods.is_empty() {
+            return full;
+        }
+        let depth = (camera_matrix * transform.column(3)).w;
+        if depth <= 0.0 {
+            return full;
+        }
+        let scale = (0..3)
+            .map(|i| Vector3::new(transform[(0, i)], transform[(1, i)], transform[(2, i)]).norm())
+            .fold(0.0, f32::max);
+        let pixels_per_error = scale * pixels_per_unit / depth;
+        self.lods
+            .iter()
+            .rev()
+            .find(|lod| lod.error * pixels_per_error <= threshold)
+            .map(|lod| (&lod.vertices, &lod.indices))
+            .unwrap_or(full)
+    }
+
+    /// Identifies the object in error messages
+    fn describe(&self, id: ObjectId) -> String {
+        match &self.name {
+            Some(name) => format!("Object \"{}\"", name),
+            None => format!("{:?}", id),
+        }
+    }
+
+    /// The object's layer, or the defaults if it isn't in one
+    fn layer(&self, layers: &Arena<LayerId, Layer>) -> Layer {
+        self.layer
+            .and_then(|layer| layers.get(layer))
+            .copied()
+            .unwrap_or_default()
+    }
 }
@@ -0,0 +1,132 @@
+use super::{Engine, MaterialId, ShaderFamilyId, StaleId};
+use crate::pipeline::{DrawType, MaterialOptions, SpecConstant};
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// `constant_id` of the first feature flag; the rest follow in the order of the fields of
+/// `MaterialFeatures`. `shaders/include/engine.glsl` declares them as `FEATURE_*` constants.
+pub const FEATURE_CONSTANT_BASE: u32 = 1000;
+pub(crate) const ALPHA_TESTED_CONSTANT: u32 = FEATURE_CONSTANT_BASE + 3;
+
+/// Cutoff of alpha-tested variants whose family doesn't set one
+const DEFAULT_ALPHA_CUTOFF: f32 = 0.5;
+
+/// Optional features of a material. Each is passed to both shader stages as a boolean
+/// specialization constant, so that one shader pair branching on them serves every combination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct MaterialFeatures {
+    pub textured: bool,
+    pub skinned: bool,
+    pub instanced: bool,
+    pub alpha_tested: bool,
+}
+
+impl MaterialFeatures {
+    fn specialization(self) -> Vec<(u32, SpecConstant)> {
+        let flags = [
+            self.textured,
+            self.skinned,
+            self.instanced,
+            self.alpha_tested,
+        ];
+        (FEATURE_CONSTANT_BASE..)
+            .zip(flags.iter())
+            .map(|(id, &flag)| (id, SpecConstant::Bool(flag)))
+            .collect()
+    }
+}
+
+/// A shader pair and the material variants built from it so far
+pub(crate) struct ShaderFamily {
+    vertex: Vec<u8>,
+    fragment: Vec<u8>,
+    draw_type: DrawType,
+    options: MaterialOptions,
+    variants: HashMap<MaterialFeatures, MaterialId>,
+}
+
+impl Engine {
+    /// Register a shader pair written against the feature constants, from which materials are
+    /// built on demand by `material_variant`. `options` applies to every variant; its
+    /// specialization constants must not use the feature flags' IDs. Its `alpha_cutoff` is
+    /// only used by alpha-tested variants, and defaults to 0.5 for them.
+    pub fn create_shader_family(
+        &mut self,
+        vertex: &[u8],
+        fragment: &[u8],
+        draw_type: DrawType,
+        options: MaterialOptions,
+    ) -> Result<ShaderFamilyId> {
+        let reserved = MaterialFeatures::default().specialization();
+        if let Some((id, _)) = options
+            .specialization
+            .iter()
+            .find(|(id, _)| reserved.iter().any(|(reserved, _)| reserved == id))
+        {
+            anyhow::bail!(
+                "Specialization constant {} is reserved for feature flags",
+                id
+            );
+        }
+        Ok(self.shader_families.insert(ShaderFamily {
+            vertex: vertex.to_vec(),
+            fragment: fragment.to_vec(),
+            draw_type,
+            options,
+            variants: HashMap::new(),
+        }))
+    }
+
+    /// The material for `features` in `family`, built the first time it is asked for and
+    /// returned from the cache afterwards. Variants unloaded with `unload_material` are built
+    /// again.
+    pub fn material_variant(
+        &mut self,
+        family: ShaderFamilyId,
+        features: MaterialFeatures,
+    ) -> Result<MaterialId> {
+        let shaders = self
+            .shader_families
+            .get_mut(family)
+            .ok_or(StaleId::ShaderFamily(family))?;
+        if let Some(&id) = shaders.variants.get(&features) {
+            if self.materials.contains(id) {
+                return Ok(id);
+            }
+            shaders.variants.remove(&features);
+        }
+
+        let mut options = shaders.options.clone();
+        options.specialization.extend(features.specialization());
+        options.alpha_cutoff = if features.alpha_tested {
+            Some(options.alpha_cutoff.unwrap_or(DEFAULT_ALPHA_CUTOFF))
+        } else {
+            None
+        };
+        let (vertex, fragment, draw_type) = (
+            shaders.vertex.clone(),
+            shaders.fragment.clone(),
+            shaders.draw_type,
+        );
+        let id = self.load_material_with_options(&vertex, &fragment, draw_type, options)?;
+
+        log::debug!("Built variant {:?} of {:?}", features, family);
+        let shaders = self.shader_families.get_mut(family).unwrap();
+        shaders.variants.insert(features, id);
+        Ok(id)
+    }
+
+    /// Remove a shader family, unloading every variant built from it that is still loaded
+    pub fn remove_shader_family(&mut self, family: ShaderFamilyId) -> Result<()> {
+        let shaders = self
+            .shader_families
+            .remove(family)
+            .ok_or(StaleId::ShaderFamily(family))?;
+        for &id in shaders.variants.values() {
+            if self.materials.contains(id) {
+                self.unload_material(id)?;
+            }
+        }
+        Ok(())
+    }
+}
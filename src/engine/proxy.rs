@@ -0,0 +1,177 @@
+use super::{Engine, MaterialId, ObjectId};
+use crate::mesh::MeshRetention;
+use crate::pipeline::{DrawType, MaterialOptions};
+use crate::vertex::Vertex;
+use anyhow::Result;
+use nalgebra::Matrix4;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+/// Changes queued by an `EngineProxy`, applied in order
+pub(crate) enum ProxyCommand {
+    LoadMaterial {
+        vertex: Vec<u8>,
+        fragment: Vec<u8>,
+        draw_type: DrawType,
+        options: MaterialOptions,
+        reply: Sender<Result<MaterialId>>,
+    },
+    AddObject {
+        vertices: Vec<Vertex>,
+        indices: Vec<u16>,
+        material: MaterialId,
+        dynamic: bool,
+        retention: MeshRetention,
+        reply: Sender<Result<ObjectId>>,
+    },
+    RemoveObject(ObjectId),
+    SetTransform(ObjectId, Matrix4<f32>),
+}
+
+/// Cloneable, `Send` handle which queues changes to an `Engine` from any thread. Commands are
+/// applied in the order they were sent, at the start of the engine's next frame.
+#[derive(Clone)]
+pub struct EngineProxy {
+    sender: Sender<ProxyCommand>,
+}
+
+/// ID of a resource created through an `EngineProxy`, available once the engine has applied
+/// the command
+pub struct PendingId<T> {
+    receiver: Receiver<Result<T>>,
+}
+
+impl EngineProxy {
+    pub fn load_material(
+        &self,
+        vertex: Vec<u8>,
+        fragment: Vec<u8>,
+        draw_type: DrawType,
+    ) -> PendingId<MaterialId> {
+        self.load_material_with_options(vertex, fragment, draw_type, Default::default())
+    }
+
+    pub fn load_material_with_options(
+        &self,
+        vertex: Vec<u8>,
+        fragment: Vec<u8>,
+        draw_type: DrawType,
+        options: MaterialOptions,
+    ) -> PendingId<MaterialId> {
+        let (reply, receiver) = mpsc::channel();
+        self.send(ProxyCommand::LoadMaterial {
+            vertex,
+            fragment,
+            draw_type,
+            options,
+            reply,
+        });
+        PendingId { receiver }
+    }
+
+    /// Queue `Engine::add_object`. `material` must already exist by the time the command is
+    /// applied, so wait for a pending material before using it here.
+    pub fn add_object(
+        &self,
+        vertices: Vec<Vertex>,
+        indices: Vec<u16>,
+        material: MaterialId,
+        dynamic: bool,
+        retention: MeshRetention,
+    ) -> PendingId<ObjectId> {
+        let (reply, receiver) = mpsc::channel();
+        self.send(ProxyCommand::AddObject {
+            vertices,
+            indices,
+            material,
+            dynamic,
+            retention,
+            reply,
+        });
+        PendingId { receiver }
+    }
+
+    pub fn remove_object(&self, id: ObjectId) {
+        self.send(ProxyCommand::RemoveObject(id));
+    }
+
+    pub fn set_transform(&self, id: ObjectId, transform: Matrix4<f32>) {
+        self.send(ProxyCommand::SetTransform(id, transform));
+    }
+
+    fn send(&self, command: ProxyCommand) {
+        // The engine has been dropped; there is nothing left to change
+        let _ = self.sender.send(command);
+    }
+}
+
+impl<T> PendingId<T> {
+    /// The result of the command if the engine has applied it, without blocking
+    pub fn try_get(&self) -> Option<Result<T>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(engine_dropped())),
+        }
+    }
+
+    /// Block until the engine has applied the command. Must not be called from the thread
+    /// running `next_frame`, which would never get to apply it.
+    pub fn wait(self) -> Result<T> {
+        self.receiver
+            .recv()
+            .unwrap_or_else(|_| Err(engine_dropped()))
+    }
+}
+
+fn engine_dropped() -> anyhow::Error {
+    anyhow::format_err!("Engine was dropped before applying the command")
+}
+
+impl Engine {
+    /// Create a handle for queueing changes from other threads
+    pub fn proxy(&self) -> EngineProxy {
+        EngineProxy {
+            sender: self.proxy_sender.clone(),
+        }
+    }
+
+    /// Apply every command queued by proxies so far
+    pub(crate) fn apply_proxy_commands(&mut self) {
+        while let Ok(command) = self.proxy_commands.try_recv() {
+            match command {
+                ProxyCommand::LoadMaterial {
+                    vertex,
+                    fragment,
+                    draw_type,
+                    options,
+                    reply,
+                } => {
+                    let result =
+                        self.load_material_with_options(&vertex, &fragment, draw_type, options);
+                    let _ = reply.send(result);
+                }
+                ProxyCommand::AddObject {
+                    vertices,
+                    indices,
+                    material,
+                    dynamic,
+                    retention,
+                    reply,
+                } => {
+                    let result = self.add_object(&vertices, &indices, material, dynamic, retention);
+                    let _ = reply.send(result);
+                }
+                ProxyCommand::RemoveObject(id) => {
+                    if let Err(e) = self.remove_object(id) {
+                        log::warn!("Proxied object removal failed: {}", e);
+                    }
+                }
+                ProxyCommand::SetTransform(id, transform) => {
+                    if let Err(e) = self.set_transform(id, transform) {
+                        log::warn!("Proxied transform update failed: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
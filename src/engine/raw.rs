@@ -0,0 +1,56 @@
+//! Escape hatches for interleaving application Vulkan work with the engine. Anything done through
+//! these handles must leave the engine's objects in the state it expects; destroying or
+//! reconfiguring them is undefined behaviour.
+use super::Engine;
+use erupt::{vk1_0 as vk, DeviceLoader, InstanceLoader};
+
+/// Where in a frame's command buffer a `RawRecorder` is called
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawStage {
+    /// After point cloud uploads, before the render pass begins, e.g. for compute dispatches
+    /// whose results are drawn this frame
+    BeforeRenderPass,
+    /// Inside the render pass, after everything the engine draws. Bind your own pipeline and
+    /// descriptor sets; the viewport and scissor cover the whole target.
+    InRenderPass,
+    /// After the render pass has ended and any readback has been recorded
+    AfterRenderPass,
+}
+
+/// Records application commands into each frame's command buffer while the engine records it
+pub type RawRecorder = Box<dyn FnMut(&DeviceLoader, vk::CommandBuffer, RawStage)>;
+
+// Safety requirements are shared, and described above
+#[allow(clippy::missing_safety_doc)]
+impl Engine {
+    pub unsafe fn raw_instance(&self) -> &InstanceLoader {
+        &self.instance
+    }
+
+    pub unsafe fn raw_physical_device(&self) -> vk::PhysicalDevice {
+        self.hardware.physical_device
+    }
+
+    pub unsafe fn raw_device(&self) -> &DeviceLoader {
+        &self.device
+    }
+
+    pub unsafe fn raw_queue(&self) -> vk::Queue {
+        self.queue
+    }
+
+    pub unsafe fn raw_queue_family(&self) -> u32 {
+        self.hardware.queue_family
+    }
+
+    /// Render pass of the current swapchain, if one exists yet
+    pub unsafe fn raw_render_pass(&self) -> Option<vk::RenderPass> {
+        self.swapchain.as_ref().map(|s| s.render_pass)
+    }
+
+    /// Call `recorder` at every `RawStage` of each frame recorded from now on, including test
+    /// frames, with the frame's command buffer. `None` removes it.
+    pub unsafe fn set_raw_recorder(&mut self, recorder: Option<RawRecorder>) {
+        self.raw_recorder = recorder;
+    }
+}
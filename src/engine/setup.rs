@@ -1,28 +1,64 @@
+use crate::deletion_queue::DeletionQueue;
+use crate::descriptors::DescriptorAllocator;
+use crate::frame_pacing::GpuTimer;
 use crate::frame_sync::FrameSync;
 use crate::hardware_query::HardwareSelection;
 use super::{Engine, RealtimeUBO};
 use anyhow::Result;
+use nalgebra::Matrix4;
 use erupt::{
     cstr,
-    extensions::{ext_debug_utils, khr_swapchain},
-    utils::{allocator, surface},
-    vk1_0 as vk, DeviceLoader, EntryLoader, InstanceLoader,
+    extensions::{ext_debug_utils, khr_surface, khr_swapchain},
+    utils::{loading::DefaultEntryLoader, surface},
+    vk1_0 as vk, vk1_1, vk1_2, DeviceLoader, EntryLoader, InstanceLoader,
 };
 use std::{
     ffi::CString,
     os::raw::c_char,
+    sync::mpsc,
 };
 use winit::window::Window;
 use crate::allocated_buffer::AllocatedBuffer;
+use crate::memory::{BlockAllocator, MemoryAllocator};
+use crate::lights::LightsUniform;
+use crate::pipeline::OutlineShaders;
+use crate::streamed_buffer::StreamedBuffer;
 
-const FRAMES_IN_FLIGHT: usize = 2;
+/// Frames the CPU may record ahead of the GPU by default. Each additional frame in flight adds
+/// a frame of input latency, but gives more slack against stalls on runtimes with uneven frame
+/// times.
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
 
 impl Engine {
     pub fn new(window: &Window, app_name: &str) -> Result<Self> {
+        Self::with_frames_in_flight(window, app_name, DEFAULT_FRAMES_IN_FLIGHT)
+    }
+
+    /// Create an engine which records up to `frames_in_flight` frames ahead of the GPU, either 2
+    /// (double buffering, lower latency) or 3 (triple buffering, higher throughput).
+    pub fn with_frames_in_flight(
+        window: &Window,
+        app_name: &str,
+        frames_in_flight: usize,
+    ) -> Result<Self> {
+        anyhow::ensure!(
+            (2..=3).contains(&frames_in_flight),
+            "Frames in flight must be 2 or 3, got {}",
+            frames_in_flight
+        );
+
         // Entry
         let entry = EntryLoader::new()?;
 
-        // Instance
+        // Instance, at the newest version up to 1.2 the loader supports. Features past 1.0 are
+        // only used where both the instance and the device support them.
+        let api_version = if entry.enumerate_instance_version.is_some() {
+            unsafe { entry.enumerate_instance_version() }.result()?
+        } else {
+            // Vulkan 1.0 loaders lack the query
+            vk::make_version(1, 0, 0)
+        }
+        .min(vk::make_version(1, 2, 0));
         let application_name = CString::new(app_name)?;
         let engine_name = CString::new("Prototype engine")?;
         let app_info = vk::ApplicationInfoBuilder::new()
@@ -30,7 +66,7 @@ impl Engine {
             .application_version(vk::make_version(1, 0, 0))
             .engine_name(&engine_name)
             .engine_version(vk::make_version(1, 0, 0))
-            .api_version(vk::make_version(1, 0, 0));
+            .api_version(api_version);
 
         let mut instance_extensions = surface::enumerate_required_extensions(window).result()?;
         if cfg!(debug_assertions) {
@@ -62,23 +98,113 @@ impl Engine {
         let surface = unsafe { surface::create_surface(&mut instance, window, None) }.result()?;
 
         // Hardware selection
-        let hardware = HardwareSelection::query(&instance, surface, &device_extensions)?;
+        let hardware =
+            HardwareSelection::query(&instance, api_version, surface, &device_extensions)?;
 
         // Create logical device and queues
         let create_info = [vk::DeviceQueueCreateInfoBuilder::new()
             .queue_family_index(hardware.queue_family)
             .queue_priorities(&[1.0])];
 
-        let physical_device_features = vk::PhysicalDeviceFeaturesBuilder::new();
-        let create_info = vk::DeviceCreateInfoBuilder::new()
+        let physical_device_features = hardware.capabilities.features();
+        let mut enabled_extensions = device_extensions.to_vec();
+        enabled_extensions.extend(hardware.capabilities.extensions());
+        let mut timeline_features =
+            vk1_2::PhysicalDeviceTimelineSemaphoreFeaturesBuilder::new().timeline_semaphore(true);
+        let mut multiview_features =
+            vk1_1::PhysicalDeviceMultiviewFeaturesBuilder::new().multiview(true);
+        let mut create_info = vk::DeviceCreateInfoBuilder::new()
             .queue_create_infos(&create_info)
             .enabled_features(&physical_device_features)
-            .enabled_extension_names(&device_extensions)
+            .enabled_extension_names(&enabled_extensions)
             .enabled_layer_names(&device_layers);
+        if hardware.capabilities.timeline_semaphores {
+            create_info.p_next =
+                &mut *timeline_features as *mut vk1_2::PhysicalDeviceTimelineSemaphoreFeatures as _;
+        }
+        if hardware.capabilities.multiview {
+            multiview_features.p_next = create_info.p_next as _;
+            create_info.p_next =
+                &mut *multiview_features as *mut vk1_1::PhysicalDeviceMultiviewFeatures as _;
+        }
 
         let device = DeviceLoader::new(&instance, hardware.physical_device, &create_info, None)?;
         let queue = unsafe { device.get_device_queue(hardware.queue_family, 0, None) };
 
+        let mut engine =
+            Self::from_parts(
+                entry,
+                instance,
+                surface,
+                hardware,
+                device,
+                queue,
+                frames_in_flight,
+                true,
+            )?;
+        let size = window.inner_size();
+        engine.resize(size.width, size.height)?;
+        Ok(engine)
+    }
+
+    /// Build an engine on top of Vulkan objects created by the application, e.g. to share a
+    /// device with another renderer. The device must have `VK_KHR_swapchain` enabled, and `queue`
+    /// must support graphics and presentation to `surface`. Optional features and extensions are
+    /// assumed to be enabled wherever `capabilities()` reports them as supported, which takes
+    /// `api_version`, the version `instance` was created with, into account. The device,
+    /// surface and instance stay owned by the application, which must destroy them after
+    /// dropping the engine; everything the engine creates on top of them is freed with it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_raw_vulkan(
+        entry: DefaultEntryLoader,
+        instance: InstanceLoader,
+        api_version: u32,
+        surface: khr_surface::SurfaceKHR,
+        physical_device: vk::PhysicalDevice,
+        device: DeviceLoader,
+        queue_family: u32,
+        queue: vk::Queue,
+    ) -> Result<Self> {
+        let hardware = HardwareSelection::from_device(
+            &instance,
+            api_version,
+            surface,
+            physical_device,
+            queue_family,
+        )?;
+        Self::from_parts(
+            entry,
+            instance,
+            surface,
+            hardware,
+            device,
+            queue,
+            DEFAULT_FRAMES_IN_FLIGHT,
+            false,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(
+        entry: DefaultEntryLoader,
+        instance: InstanceLoader,
+        surface: khr_surface::SurfaceKHR,
+        hardware: HardwareSelection,
+        device: DeviceLoader,
+        queue: vk::Queue,
+        frames_in_flight: usize,
+        owns_vulkan: bool,
+    ) -> Result<Self> {
+        log::info!(
+            "Using {} ({:?}), {} frames in flight",
+            hardware.capabilities.device_name,
+            hardware.physical_device_properties.device_type,
+            frames_in_flight
+        );
+        if !hardware.capabilities.timeline_semaphores {
+            log::info!("Timeline semaphores unsupported, falling back to fences");
+        }
+
         // Command pool
         let create_info =
             vk::CommandPoolCreateInfoBuilder::new()
@@ -91,25 +217,35 @@ impl Engine {
         let allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
             .command_pool(command_pool)
             .level(vk::CommandBufferLevel::PRIMARY)
-            .command_buffer_count(FRAMES_IN_FLIGHT as u32);
+            .command_buffer_count(frames_in_flight as u32);
 
         let command_buffers =
             unsafe { device.allocate_command_buffers(&allocate_info) }.result()?;
 
         // Device memory allocator
-        let mut allocator = allocator::Allocator::new(
-            &instance,
-            hardware.physical_device,
-            allocator::AllocatorCreateInfo::default(),
-        )
-        .result()?;
+        let mut allocator: Box<dyn MemoryAllocator> = Box::new(BlockAllocator::new(
+            hardware.memory_properties,
+            &hardware.physical_device_properties.limits,
+        ));
 
-        // Create descriptor layout
-        let bindings = [vk::DescriptorSetLayoutBindingBuilder::new()
-            .binding(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-            .descriptor_count(1)
-            .stage_flags(vk::ShaderStageFlags::VERTEX)];
+        // Create descriptor layout: the realtime UBO, the optional user uniform, then lights
+        let bindings = [
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(2)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+        ];
 
         let descriptor_set_layout_ci =
             vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
@@ -118,35 +254,39 @@ impl Engine {
             unsafe { device.create_descriptor_set_layout(&descriptor_set_layout_ci, None, None) }
                 .result()?;
 
-        // Create descriptor pool
-        let pool_sizes = [
-            vk::DescriptorPoolSizeBuilder::new()
-                ._type(vk::DescriptorType::UNIFORM_BUFFER)
-                .descriptor_count(FRAMES_IN_FLIGHT as u32)
+        // Point and splat cloud storage buffers, bound as set 1
+        let bindings = [
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX),
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX),
         ];
-        let create_info = vk::DescriptorPoolCreateInfoBuilder::new()
-            .pool_sizes(&pool_sizes)
-            .max_sets(FRAMES_IN_FLIGHT as u32);
-        let descriptor_pool = unsafe {
-            device.create_descriptor_pool(&create_info, None, None)
-        }.result()?;
-        
-        // Create descriptor sets
-        let layouts = vec![descriptor_set_layout; FRAMES_IN_FLIGHT];
-        let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
-            .descriptor_pool(descriptor_pool)
-            .set_layouts(&layouts);
 
-        let descriptor_sets = unsafe {
-            device.allocate_descriptor_sets(&create_info)
-        }.result()?;
+        let descriptor_set_layout_ci =
+            vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+
+        let point_cloud_set_layout =
+            unsafe { device.create_descriptor_set_layout(&descriptor_set_layout_ci, None, None) }
+                .result()?;
+
+        // Create descriptor sets
+        let mut descriptor_allocator = DescriptorAllocator::default();
+        let descriptor_sets = (0..frames_in_flight)
+            .map(|_| descriptor_allocator.allocate(&device, descriptor_set_layout))
+            .collect::<Result<Vec<_>>>()?;
 
         // Camera's UBOs
         let create_info = vk::BufferCreateInfoBuilder::new()
             .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
-        let realtime_ubos = (0..FRAMES_IN_FLIGHT).map(|_| 
-            AllocatedBuffer::new(1, create_info.clone(), &mut allocator, &device)).collect::<Result<Vec<_>>>()?;
+        let realtime_ubos = (0..frames_in_flight).map(|_| 
+            AllocatedBuffer::new(1, create_info.clone(), &mut *allocator, &device)).collect::<Result<Vec<_>>>()?;
 
         // Bind buffers to descriptors
         for (alloc, descriptor) in realtime_ubos.iter().zip(descriptor_sets.iter()) {
@@ -158,7 +298,7 @@ impl Engine {
             let writes = [vk::WriteDescriptorSetBuilder::new()
                 .buffer_info(&buffer_infos)
                 .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                .dst_set(*descriptor)
+                .dst_set(descriptor.set)
                 .dst_binding(0)
                 .dst_array_element(0)];
 
@@ -167,29 +307,114 @@ impl Engine {
             }
         }
 
+        // Lights, rewritten whenever they change
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let lights_uniform = StreamedBuffer::new_dynamic(
+            &[LightsUniform::new(std::iter::empty())],
+            frames_in_flight,
+            create_info,
+            &mut *allocator,
+            &device,
+        )?;
+        for (frame, descriptor) in descriptor_sets.iter().enumerate() {
+            let buffer_infos = [vk::DescriptorBufferInfoBuilder::new()
+                .buffer(lights_uniform.buffer(frame))
+                .offset(0)
+                .range(vk::WHOLE_SIZE)];
+            let writes = [vk::WriteDescriptorSetBuilder::new()
+                .buffer_info(&buffer_infos)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .dst_set(descriptor.set)
+                .dst_binding(2)
+                .dst_array_element(0)];
+            unsafe {
+                device.update_descriptor_sets(&writes, &[]);
+            }
+        }
+
+        // Shared by every pipeline, so recreating pipelines with the swapchain is cheaper
+        let create_info = vk::PipelineCacheCreateInfoBuilder::new();
+        let pipeline_cache =
+            unsafe { device.create_pipeline_cache(&create_info, None, None) }.result()?;
+        let outline_shaders = OutlineShaders::new(&device)?;
+
         // Frame synchronization
-        let frame_sync = FrameSync::new(&device, FRAMES_IN_FLIGHT)?;
+        let frame_sync = FrameSync::new(
+            &device,
+            frames_in_flight,
+            hardware.capabilities.timeline_semaphores,
+        )?;
+        let deletion_queue = DeletionQueue::new(frames_in_flight);
+
+        // GPU frame timing, where the graphics queue supports timestamps
+        let limits = &hardware.physical_device_properties.limits;
+        let gpu_timer = if limits.timestamp_compute_and_graphics != vk::FALSE {
+            Some(GpuTimer::new(&device, frames_in_flight, limits.timestamp_period)?)
+        } else {
+            None
+        };
+
+        let (proxy_sender, proxy_commands) = mpsc::channel();
 
         Ok(Self {
             _entry: entry,
             realtime_ubo: realtime_ubos,
             descriptor_set_layout,
-            descriptor_pool,
+            point_cloud_set_layout,
+            descriptor_allocator,
+            pipeline_cache,
+            outline_shaders,
             descriptor_sets,
+            material_descriptors: Default::default(),
+            user_uniform: None,
+            lights: Default::default(),
+            lights_uniform,
             instance,
             surface,
             hardware,
             device,
             queue,
+            owns_vulkan,
             command_pool,
             frame_sync,
             allocator,
+            deletion_queue,
             command_buffers,
             swapchain: None,
+            fog: Default::default(),
+            color_scale: [1.0; 4],
+            color_bias: [0.0; 4],
+            clock: Default::default(),
+            floating_origin: Default::default(),
+            world_transform: Matrix4::identity(),
+            world_scale: 1.0,
+            lod_threshold: 1.0,
+            lod_bias: 0.0,
+            quality_governor: None,
+            frames_since_quality_update: 0,
+            listener: Default::default(),
+            frame_pacer: Default::default(),
+            gpu_timer,
+            draw_list: Default::default(),
+            frame_stats: Default::default(),
+            raw_recorder: None,
+            swapchain_image_count: None,
+            window_extent: None,
+            proxy_sender,
+            proxy_commands,
             materials: Default::default(),
+            shader_families: Default::default(),
             objects: Default::default(),
-            next_material_id: 0,
-            next_object_id: 0,
+            transforms: Default::default(),
+            chunk_pools: Default::default(),
+            terrains: Default::default(),
+            point_clouds: Default::default(),
+            splat_clouds: Default::default(),
+            storage_buffers: Default::default(),
+            layers: Default::default(),
+            animations: Default::default(),
         })
     }
 }
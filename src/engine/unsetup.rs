@@ -3,27 +3,60 @@ use crate::Engine;
 impl Drop for Engine {
     fn drop(&mut self) {
         unsafe {
-            let ids = self.objects.keys().copied().collect::<Vec<_>>();
+            self.device.device_wait_idle().result().unwrap();
+            let ids = self.objects.keys().collect::<Vec<_>>();
+            self.remove_objects(&ids).unwrap();
+            for (_, descriptors) in self.material_descriptors.drain() {
+                descriptors
+                    .free(&self.device, &mut self.descriptor_allocator, &mut self.deletion_queue)
+                    .unwrap();
+            }
+            for buffer in self.storage_buffers.values_mut() {
+                buffer.retire(&mut self.deletion_queue);
+            }
+            if let Some(uniform) = &mut self.user_uniform {
+                uniform.retire(&mut self.deletion_queue);
+            }
+            self.lights_uniform.retire(&mut self.deletion_queue);
+            let ids = self.chunk_pools.keys().collect::<Vec<_>>();
+            for id in ids {
+                self.remove_chunk_pool(id).unwrap();
+            }
+            let ids = self.point_clouds.keys().collect::<Vec<_>>();
             for id in ids {
-                self.remove_object(id).unwrap();
+                self.remove_point_cloud(id).unwrap();
             }
+            let ids = self.splat_clouds.keys().collect::<Vec<_>>();
+            for id in ids {
+                self.remove_splats(id).unwrap();
+            }
+            self.deletion_queue.free(&self.device, &mut *self.allocator);
             for material in self.materials.values_mut() {
                 material.free(&self.device);
             }
             if let Some(swapchain) = &mut self.swapchain {
-                swapchain.free(&self.device, &mut self.allocator).unwrap();
+                swapchain.free(&self.device, &mut *self.allocator).unwrap();
             }
             for ubo in &mut self.realtime_ubo {
-                ubo.free(&self.device, &mut self.allocator).unwrap();
+                ubo.free(&self.device, &mut *self.allocator).unwrap();
             }
             self.frame_sync.free(&self.device);
+            if let Some(timer) = &mut self.gpu_timer {
+                timer.free(&self.device);
+            }
             self.device.destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
-            self.device.destroy_descriptor_pool(Some(self.descriptor_pool), None);
+            self.device.destroy_descriptor_set_layout(Some(self.point_cloud_set_layout), None);
+            self.descriptor_allocator.free_all(&self.device);
+            self.device.destroy_pipeline_cache(Some(self.pipeline_cache), None);
+            self.outline_shaders.free(&self.device);
             self.device.free_command_buffers(self.command_pool, &self.command_buffers);
             self.device.destroy_command_pool(Some(self.command_pool), None);
-            self.device.destroy_device(None);
-            self.instance.destroy_surface_khr(Some(self.surface), None);
-            self.instance.destroy_instance(None);
+            self.allocator.free_all(&self.device);
+            if self.owns_vulkan {
+                self.device.destroy_device(None);
+                self.instance.destroy_surface_khr(Some(self.surface), None);
+                self.instance.destroy_instance(None);
+            }
         }
     }
 }
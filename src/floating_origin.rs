@@ -0,0 +1,56 @@
+use nalgebra::{Point3, Vector3};
+
+/// Offset between world coordinates, kept in `f64` by the application, and the `f32`
+/// coordinates the engine renders with. Moving the origin along with the camera keeps rendered
+/// coordinates small, and so precise, however far the camera travels.
+#[derive(Debug, Clone, Copy)]
+pub struct FloatingOrigin {
+    origin: Vector3<f64>,
+    rebase_distance: Option<f32>,
+}
+
+impl FloatingOrigin {
+    pub fn new() -> Self {
+        Self {
+            origin: Vector3::zeros(),
+            rebase_distance: None,
+        }
+    }
+
+    /// World position of the engine's local origin
+    pub fn origin(&self) -> Point3<f64> {
+        Point3::from(self.origin)
+    }
+
+    /// Distance from the local origin past which `Engine::rebase` moves the origin to the
+    /// camera. `None` (the default) never rebases automatically.
+    pub fn rebase_distance(&self) -> Option<f32> {
+        self.rebase_distance
+    }
+
+    pub fn to_local(&self, world: &Point3<f64>) -> Point3<f32> {
+        Point3::from((world.coords - self.origin).map(|c| c as f32))
+    }
+
+    pub fn to_world(&self, local: &Point3<f32>) -> Point3<f64> {
+        Point3::from(local.coords.map(f64::from) + self.origin)
+    }
+
+    pub(crate) fn set_rebase_distance(&mut self, distance: Option<f32>) {
+        self.rebase_distance = distance;
+    }
+
+    /// Move the origin to `origin`. Returns how far local coordinates moved: subtract it from
+    /// anything positioned in local coordinates.
+    pub(crate) fn shift_to(&mut self, origin: &Point3<f64>) -> Vector3<f32> {
+        let shift = origin.coords - self.origin;
+        self.origin = origin.coords;
+        shift.map(|c| c as f32)
+    }
+}
+
+impl Default for FloatingOrigin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
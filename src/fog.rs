@@ -0,0 +1,35 @@
+/// Distance fog applied by the built-in shading path
+#[derive(Debug, Clone, Copy)]
+pub struct Fog {
+    pub color: [f32; 3],
+    pub mode: FogMode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FogMode {
+    Disabled,
+    /// Fog ramps linearly from `start` to `end` (view-space distance)
+    Linear { start: f32, end: f32 },
+    /// Fog factor is `1 - exp(-density * distance)`
+    Exponential { density: f32 },
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            color: [0.0; 3],
+            mode: FogMode::Disabled,
+        }
+    }
+}
+
+impl Fog {
+    /// Mode index and (start, end, density) as laid out in the realtime UBO
+    pub(crate) fn params(&self) -> (u32, [f32; 3]) {
+        match self.mode {
+            FogMode::Disabled => (0, [0.0; 3]),
+            FogMode::Linear { start, end } => (1, [start, end, 0.0]),
+            FogMode::Exponential { density } => (2, [0.0, 0.0, density]),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::Result;
+use erupt::{vk1_0 as vk, DeviceLoader};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Number of frames kept for pacing statistics
+pub const PACING_WINDOW: usize = 120;
+
+/// Timings of a single frame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTimings {
+    /// Time spent waiting on the frame fence and for a swapchain image
+    pub wait: Duration,
+    /// Time spent recording, submitting and presenting
+    pub cpu: Duration,
+    /// GPU execution time. Measured with timestamp queries, so it trails the CPU timings by the
+    /// number of frames in flight; `None` where timestamps are unsupported.
+    pub gpu: Option<Duration>,
+}
+
+/// Distribution of one timing over the pacing window
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FramePacingReport {
+    pub frames: usize,
+    pub wait: Percentiles,
+    pub cpu: Percentiles,
+    pub gpu: Option<Percentiles>,
+    /// Frames whose wait + cpu time exceeded the target frame time
+    pub missed_frames: usize,
+}
+
+/// Moving window of frame timings
+#[derive(Default)]
+pub struct FramePacer {
+    window: VecDeque<FrameTimings>,
+}
+
+impl FramePacer {
+    pub fn push(&mut self, timings: FrameTimings) {
+        if self.window.len() == PACING_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(timings);
+    }
+
+    pub fn report(&self, target: Duration) -> FramePacingReport {
+        let gpu = self.window.iter().filter_map(|t| t.gpu).collect::<Vec<_>>();
+        FramePacingReport {
+            frames: self.window.len(),
+            wait: Percentiles::new(self.window.iter().map(|t| t.wait).collect()),
+            cpu: Percentiles::new(self.window.iter().map(|t| t.cpu).collect()),
+            gpu: if gpu.is_empty() {
+                None
+            } else {
+                Some(Percentiles::new(gpu))
+            },
+            missed_frames: self
+                .window
+                .iter()
+                .filter(|t| t.wait + t.cpu > target)
+                .count(),
+        }
+    }
+}
+
+impl Percentiles {
+    fn new(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort();
+        let at = |p: f32| samples[((samples.len() - 1) as f32 * p).round() as usize];
+        Self {
+            p50: at(0.5),
+            p95: at(0.95),
+            p99: at(0.99),
+            max: *samples.last().unwrap(),
+        }
+    }
+}
+
+/// Timestamp queries bracketing each frame's command buffer
+pub struct GpuTimer {
+    query_pool: vk::QueryPool,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+    written: Vec<bool>,
+    freed: bool,
+}
+
+impl GpuTimer {
+    pub fn new(device: &DeviceLoader, frames_in_flight: usize, period: f32) -> Result<Self> {
+        let create_info = vk::QueryPoolCreateInfoBuilder::new()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(frames_in_flight as u32 * 2);
+        let query_pool = unsafe { device.create_query_pool(&create_info, None, None) }.result()?;
+        Ok(Self {
+            query_pool,
+            period,
+            written: vec![false; frames_in_flight],
+            freed: false,
+        })
+    }
+
+    /// Record the start timestamp. Must be called outside of a render pass.
+    pub fn begin(
+        &mut self,
+        device: &DeviceLoader,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+    ) {
+        let first = frame as u32 * 2;
+        unsafe {
+            device.cmd_reset_query_pool(command_buffer, self.query_pool, first, 2);
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlagBits::TOP_OF_PIPE,
+                self.query_pool,
+                first,
+            );
+        }
+    }
+
+    pub fn end(&mut self, device: &DeviceLoader, command_buffer: vk::CommandBuffer, frame: usize) {
+        unsafe {
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlagBits::BOTTOM_OF_PIPE,
+                self.query_pool,
+                frame as u32 * 2 + 1,
+            );
+        }
+        self.written[frame] = true;
+    }
+
+    /// GPU time of the last submission for this frame slot. Only call once its fence signaled.
+    pub fn read(&self, device: &DeviceLoader, frame: usize) -> Result<Option<Duration>> {
+        if !self.written[frame] {
+            return Ok(None);
+        }
+        let mut data = [0u64; 2];
+        unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                frame as u32 * 2,
+                2,
+                std::mem::size_of_val(&data),
+                data.as_mut_ptr() as _,
+                std::mem::size_of::<u64>() as u64,
+                Some(vk::QueryResultFlags::_64),
+            )
+        }
+        .result()?;
+        let ticks = data[1].saturating_sub(data[0]);
+        Ok(Some(Duration::from_nanos(
+            (ticks as f64 * self.period as f64) as u64,
+        )))
+    }
+
+    pub fn free(&mut self, device: &DeviceLoader) {
+        unsafe {
+            device.destroy_query_pool(Some(self.query_pool), None);
+        }
+        self.freed = true;
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        if !self.freed {
+            panic!("GpuTimer dropped before it was freed");
+        }
+    }
+}
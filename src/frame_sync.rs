@@ -1,10 +1,15 @@
 use anyhow::Result;
-use erupt::{vk1_0 as vk, DeviceLoader};
+use erupt::{vk1_0 as vk, vk1_2, DeviceLoader};
 
-/// Manages fences and semaphores for every given frame
+/// Manages fences and semaphores for every given frame. Submitted frames are numbered from 1;
+/// where timeline semaphores are supported a single timeline semaphore is signaled with each
+/// frame's number, otherwise each frame slot falls back to a fence.
 pub struct FrameSync {
     frames: Vec<Frame>,
     frame_idx: usize,
+    timeline: Option<vk::Semaphore>,
+    /// Number of the most recently submitted frame
+    submitted: u64,
     freed: bool,
 }
 
@@ -13,37 +18,177 @@ pub struct Frame {
     pub image_available: vk::Semaphore,
     /// Whether or not rendering has finished
     pub render_finished: vk::Semaphore,
-    /// Whether or not this frame is in flight
-    pub in_flight_fence: vk::Fence,
+    /// Whether or not this frame is in flight. Null when using the timeline semaphore.
+    in_flight_fence: vk::Fence,
+    /// Number of the frame last submitted from this slot, zero if none
+    number: u64,
 }
 
 impl FrameSync {
-    pub fn new(device: &DeviceLoader, frames_in_flight: usize) -> Result<Self> {
+    pub fn new(device: &DeviceLoader, frames_in_flight: usize, timeline: bool) -> Result<Self> {
         let frames = (0..frames_in_flight)
-            .map(|_| Frame::new(device))
+            .map(|_| Frame::new(device, !timeline))
             .collect::<Result<_>>()?;
 
+        let timeline = if timeline {
+            let mut type_info = vk1_2::SemaphoreTypeCreateInfoBuilder::new()
+                .semaphore_type(vk1_2::SemaphoreType::TIMELINE)
+                .initial_value(0);
+            let mut create_info = vk::SemaphoreCreateInfoBuilder::new();
+            create_info.p_next = &mut *type_info as *mut vk1_2::SemaphoreTypeCreateInfo as _;
+            Some(unsafe { device.create_semaphore(&create_info, None, None) }.result()?)
+        } else {
+            None
+        };
+
         Ok(Self {
             frames,
             freed: false,
             frame_idx: 0,
+            timeline,
+            submitted: 0,
         })
     }
 
-    pub fn next_frame(&mut self, device: &DeviceLoader) -> Result<(usize, &mut Frame)> {
+    /// Advance to the next frame slot, waiting for its previous submission to finish
+    pub fn next_frame(&mut self, device: &DeviceLoader) -> Result<usize> {
         self.frame_idx = (self.frame_idx + 1) % self.frames.len();
-        let frame = &mut self.frames[self.frame_idx];
-        unsafe {
-            device
-                .wait_for_fences(&[frame.in_flight_fence], true, u64::MAX).result()?;
+        self.wait(device, self.frames[self.frame_idx].number, u64::MAX)?;
+        Ok(self.frame_idx)
+    }
+
+    pub fn frame(&self, frame_idx: usize) -> &Frame {
+        &self.frames[frame_idx]
+    }
+
+    /// Number of the most recently submitted frame
+    pub fn submitted(&self) -> u64 {
+        self.submitted
+    }
+
+    /// Number of the most recent frame the GPU has finished
+    pub fn completed(&self, device: &DeviceLoader) -> Result<u64> {
+        if let Some(timeline) = self.timeline {
+            return Ok(unsafe { device.get_semaphore_counter_value(timeline, None) }.result()?);
+        }
+
+        // Submissions to the queue complete in order, so the frame before the oldest unfinished
+        // one is the most recent finished frame
+        let mut completed = self.submitted;
+        for frame in &self.frames {
+            if frame.number == 0 {
+                continue;
+            }
+            let status = unsafe { device.get_fence_status(frame.in_flight_fence) };
+            if status.raw == vk::Result::NOT_READY {
+                completed = completed.min(frame.number - 1);
+            } else {
+                status.result()?;
+            }
+        }
+        Ok(completed)
+    }
+
+    /// Wait up to `timeout` nanoseconds for frame `number` to finish. Returns false on timeout.
+    pub fn wait(&self, device: &DeviceLoader, number: u64, timeout: u64) -> Result<bool> {
+        anyhow::ensure!(
+            number <= self.submitted,
+            "Frame {} has not been submitted",
+            number
+        );
+        if number == 0 {
+            return Ok(true);
+        }
+
+        let result = if let Some(timeline) = self.timeline {
+            let semaphores = [timeline];
+            let values = [number];
+            let wait_info = vk1_2::SemaphoreWaitInfoBuilder::new()
+                .semaphores(&semaphores)
+                .values(&values);
+            unsafe { device.wait_semaphores(&wait_info, timeout) }
+        } else {
+            // The slot may have been reused by a later frame since, which finishes after it
+            let fence = match self
+                .frames
+                .iter()
+                .filter(|frame| frame.number >= number)
+                .min_by_key(|frame| frame.number)
+            {
+                Some(frame) => frame.in_flight_fence,
+                None => return Ok(true),
+            };
+            unsafe { device.wait_for_fences(&[fence], true, timeout) }
+        };
+
+        if result.raw == vk::Result::TIMEOUT {
+            return Ok(false);
+        }
+        result.result()?;
+        Ok(true)
+    }
+
+    /// Submit `command_buffer` as the frame in slot `frame_idx`, returning its number. Frames
+    /// drawn to a swapchain image wait for it to be acquired and signal `render_finished`.
+    pub fn submit(
+        &mut self,
+        device: &DeviceLoader,
+        queue: vk::Queue,
+        frame_idx: usize,
+        command_buffer: vk::CommandBuffer,
+        swapchain: bool,
+    ) -> Result<u64> {
+        let number = self.submitted + 1;
+        let frame = &mut self.frames[frame_idx];
+
+        let mut wait_semaphores = Vec::new();
+        let mut signal_semaphores = Vec::new();
+        let mut signal_values = Vec::new();
+        if swapchain {
+            wait_semaphores.push(frame.image_available);
+            signal_semaphores.push(frame.render_finished);
+            // The value for the binary semaphore is ignored
+            signal_values.push(0);
+        }
+        if let Some(timeline) = self.timeline {
+            signal_semaphores.push(timeline);
+            signal_values.push(number);
         }
-        Ok((self.frame_idx, frame))
+        let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        let command_buffers = [command_buffer];
+
+        let mut timeline_info = vk1_2::TimelineSemaphoreSubmitInfoBuilder::new()
+            .signal_semaphore_values(&signal_values);
+        let mut submit_info = vk::SubmitInfoBuilder::new()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages[..wait_semaphores.len()])
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+
+        let fence = if self.timeline.is_some() {
+            submit_info.p_next =
+                &mut *timeline_info as *mut vk1_2::TimelineSemaphoreSubmitInfo as _;
+            None
+        } else {
+            unsafe { device.reset_fences(&[frame.in_flight_fence]) }.result()?;
+            Some(frame.in_flight_fence)
+        };
+
+        unsafe { device.queue_submit(queue, &[submit_info], fence) }.result()?;
+        frame.number = number;
+        self.submitted = number;
+        Ok(number)
     }
 
     pub fn free(&mut self, device: &DeviceLoader) {
         for frame in &mut self.frames {
             frame.free(device);
         }
+        if let Some(timeline) = self.timeline {
+            unsafe {
+                device.destroy_semaphore(Some(timeline), None);
+            }
+        }
         self.freed = true;
     }
 }
@@ -57,19 +202,24 @@ impl Drop for FrameSync {
 }
 
 impl Frame {
-    pub fn new(device: &DeviceLoader) -> Result<Self> {
+    pub fn new(device: &DeviceLoader, fence: bool) -> Result<Self> {
         unsafe {
             let create_info = vk::SemaphoreCreateInfoBuilder::new();
             let image_available = device.create_semaphore(&create_info, None, None).result()?;
             let render_finished = device.create_semaphore(&create_info, None, None).result()?;
 
-            let create_info =
-                vk::FenceCreateInfoBuilder::new().flags(vk::FenceCreateFlags::SIGNALED);
-            let in_flight_fence = device.create_fence(&create_info, None, None).result()?;
+            let in_flight_fence = if fence {
+                let create_info =
+                    vk::FenceCreateInfoBuilder::new().flags(vk::FenceCreateFlags::SIGNALED);
+                device.create_fence(&create_info, None, None).result()?
+            } else {
+                vk::Fence::null()
+            };
             Ok(Self {
                 in_flight_fence,
                 image_available,
                 render_finished,
+                number: 0,
             })
         }
     }
@@ -1,7 +1,8 @@
+use crate::pipeline::MaterialOptions;
 use anyhow::Result;
 use erupt::{
-    extensions::khr_surface,
-    vk1_0 as vk, InstanceLoader,
+    extensions::{ext_memory_budget, khr_surface},
+    vk1_0 as vk, vk1_1, vk1_2, InstanceLoader,
 };
 use std::{
     ffi::CStr,
@@ -12,15 +13,168 @@ use std::{
 pub struct HardwareSelection {
     pub physical_device: vk::PhysicalDevice,
     pub physical_device_properties: vk::PhysicalDeviceProperties,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
     pub queue_family: u32,
     pub format: khr_surface::SurfaceFormatKHR,
     pub present_mode: khr_surface::PresentModeKHR,
+    /// Every present mode the surface supports
+    pub present_modes: Vec<khr_surface::PresentModeKHR>,
+    /// Depth format with a stencil component
+    pub depth_format: vk::Format,
+    pub capabilities: Capabilities,
+}
+
+/// What the selected device supports, beyond what the engine requires. Optional features are
+/// enabled wherever they are supported.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub device_name: String,
+    /// Vulkan version usable with the device: the lower of the instance's and the device's
+    pub api_version: u32,
+    pub max_push_constants_size: u32,
+    pub max_uniform_buffer_range: u32,
+    /// Multiview rendering, core in Vulkan 1.1, is supported and enabled on the device
+    pub multiview: bool,
+    /// Line widths other than 1
+    pub wide_lines: bool,
+    pub line_width_range: [f32; 2],
+    /// Point sizes other than 1
+    pub large_points: bool,
+    pub point_size_range: [f32; 2],
+    pub sampler_anisotropy: bool,
+    pub max_sampler_anisotropy: f32,
+    /// Highest sample count supported by both color and depth attachments
+    pub max_msaa_samples: u32,
+    /// Frames are tracked with a timeline semaphore instead of a fence per frame in flight
+    pub timeline_semaphores: bool,
+    /// `VK_EXT_memory_budget` is available, so `memory_stats()` reports the driver's budget and
+    /// usage instead of heap sizes
+    pub memory_budget: bool,
+}
+
+impl Capabilities {
+    unsafe fn query(
+        instance: &InstanceLoader,
+        api_version: u32,
+        physical_device: vk::PhysicalDevice,
+    ) -> Self {
+        let properties = instance.get_physical_device_properties(physical_device, None);
+        let api_version = api_version.min(properties.api_version);
+        let features = instance.get_physical_device_features(physical_device, None);
+        let limits = &properties.limits;
+
+        let extensions = instance
+            .enumerate_device_extension_properties(physical_device, None, None)
+            .unwrap();
+        let has_extension = |name: *const c_char| {
+            extensions.iter().any(|properties| {
+                CStr::from_ptr(properties.extension_name.as_ptr()) == CStr::from_ptr(name)
+            })
+        };
+
+        // Multiview is core in Vulkan 1.1, and its feature is read through the 1.1 features
+        // query
+        let multiview = api_version >= vk::make_version(1, 1, 0)
+            && instance.get_physical_device_features2.is_some()
+            && {
+                let mut multiview = vk1_1::PhysicalDeviceMultiviewFeaturesBuilder::new();
+                let mut features2 = vk1_1::PhysicalDeviceFeatures2Builder::new();
+                features2.p_next =
+                    &mut *multiview as *mut vk1_1::PhysicalDeviceMultiviewFeatures as _;
+                instance.get_physical_device_features2(physical_device, Some(features2.build()));
+                multiview.multiview != vk::FALSE
+            };
+
+        // The budget is read through the 1.1 memory properties query
+        let memory_budget = api_version >= vk::make_version(1, 1, 0)
+            && instance.get_physical_device_memory_properties2.is_some()
+            && has_extension(ext_memory_budget::EXT_MEMORY_BUDGET_EXTENSION_NAME);
+
+        // Timeline semaphores are core in Vulkan 1.2, and need the 1.1 features query
+        let timeline_semaphores = api_version >= vk::make_version(1, 2, 0)
+            && instance.get_physical_device_features2.is_some()
+            && {
+                let mut timeline = vk1_2::PhysicalDeviceTimelineSemaphoreFeaturesBuilder::new();
+                let mut features2 = vk1_1::PhysicalDeviceFeatures2Builder::new();
+                features2.p_next =
+                    &mut *timeline as *mut vk1_2::PhysicalDeviceTimelineSemaphoreFeatures as _;
+                instance.get_physical_device_features2(physical_device, Some(features2.build()));
+                timeline.timeline_semaphore != vk::FALSE
+            };
+
+        let sample_counts =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        let max_msaa_samples = [64, 32, 16, 8, 4, 2]
+            .iter()
+            .copied()
+            .find(|&count| sample_counts.bits() & count != 0)
+            .unwrap_or(1);
+
+        Self {
+            device_name: CStr::from_ptr(properties.device_name.as_ptr())
+                .to_string_lossy()
+                .into_owned(),
+            api_version,
+            max_push_constants_size: limits.max_push_constants_size,
+            max_uniform_buffer_range: limits.max_uniform_buffer_range,
+            multiview,
+            wide_lines: features.wide_lines != vk::FALSE,
+            line_width_range: limits.line_width_range,
+            large_points: features.large_points != vk::FALSE,
+            point_size_range: limits.point_size_range,
+            sampler_anisotropy: features.sampler_anisotropy != vk::FALSE,
+            max_sampler_anisotropy: limits.max_sampler_anisotropy,
+            max_msaa_samples,
+            timeline_semaphores,
+            memory_budget,
+        }
+    }
+
+    /// Optional device extensions to enable
+    pub(crate) fn extensions(&self) -> Vec<*const c_char> {
+        let mut extensions = Vec::new();
+        if self.memory_budget {
+            extensions.push(ext_memory_budget::EXT_MEMORY_BUDGET_EXTENSION_NAME);
+        }
+        extensions
+    }
+
+    /// Optional device features to enable
+    pub(crate) fn features(&self) -> vk::PhysicalDeviceFeaturesBuilder<'static> {
+        vk::PhysicalDeviceFeaturesBuilder::new()
+            .large_points(self.large_points)
+            .wide_lines(self.wide_lines)
+            .sampler_anisotropy(self.sampler_anisotropy)
+    }
+
+    /// Bring material options within what the device supports, so unsupported settings degrade
+    /// instead of failing pipeline creation
+    pub(crate) fn clamp_options(&self, options: &mut MaterialOptions) -> Result<()> {
+        if let Some(width) = &mut options.line_width {
+            *width = if self.wide_lines {
+                width
+                    .max(self.line_width_range[0])
+                    .min(self.line_width_range[1])
+            } else {
+                1.0
+            };
+        }
+        anyhow::ensure!(
+            options.uniform_bytes <= self.max_uniform_buffer_range as usize,
+            "Material uniform block of {} bytes exceeds the device limit of {}",
+            options.uniform_bytes,
+            self.max_uniform_buffer_range
+        );
+        Ok(())
+    }
 }
 
 // TODO: Flatten this and replace .unwrap() with .result()?
 impl HardwareSelection {
+    /// Pick a device for `surface`. `api_version` is the version `instance` was created with.
     pub fn query(
         instance: &InstanceLoader,
+        api_version: u32,
         surface: khr_surface::SurfaceKHR,
         device_extensions: &[*const c_char],
     ) -> Result<Self> {
@@ -48,29 +202,6 @@ impl HardwareSelection {
                     None => return None,
                 };
 
-                let formats = instance
-                    .get_physical_device_surface_formats_khr(physical_device, surface, None)
-                    .unwrap();
-                let format = match formats
-                    .iter()
-                    .find(|surface_format| {
-                        surface_format.format == vk::Format::B8G8R8A8_SRGB
-                            && surface_format.color_space
-                                == khr_surface::ColorSpaceKHR::SRGB_NONLINEAR_KHR
-                    })
-                    .or_else(|| formats.get(0))
-                {
-                    Some(surface_format) => surface_format.clone(),
-                    None => return None,
-                };
-
-                let present_mode = instance
-                    .get_physical_device_surface_present_modes_khr(physical_device, surface, None)
-                    .unwrap()
-                    .into_iter()
-                    .find(|present_mode| present_mode == &khr_surface::PresentModeKHR::MAILBOX_KHR)
-                    .unwrap_or(khr_surface::PresentModeKHR::FIFO_KHR);
-
                 let supported_extensions = instance
                     .enumerate_device_extension_properties(physical_device, None, None)
                     .unwrap();
@@ -84,15 +215,13 @@ impl HardwareSelection {
                     return None;
                 }
 
-                let physical_device_properties =
-                    instance.get_physical_device_properties(physical_device, None);
-                Some(Self {
+                Self::describe(
+                    instance,
+                    api_version,
+                    surface,
                     physical_device,
                     queue_family,
-                    format,
-                    present_mode,
-                    physical_device_properties,
-                })
+                )
             })
             .max_by_key(|query| match query.physical_device_properties.device_type {
                 vk::PhysicalDeviceType::DISCRETE_GPU => 2,
@@ -101,4 +230,82 @@ impl HardwareSelection {
             })
             .ok_or(anyhow::format_err!("No suitable hardware found for this configuration"))
     }
+
+    /// Describe a device chosen by the application
+    pub fn from_device(
+        instance: &InstanceLoader,
+        api_version: u32,
+        surface: khr_surface::SurfaceKHR,
+        physical_device: vk::PhysicalDevice,
+        queue_family: u32,
+    ) -> Result<Self> {
+        unsafe { Self::describe(instance, api_version, surface, physical_device, queue_family) }
+            .ok_or(anyhow::format_err!("Device does not support this surface or a depth format"))
+    }
+
+    unsafe fn describe(
+        instance: &InstanceLoader,
+        api_version: u32,
+        surface: khr_surface::SurfaceKHR,
+        physical_device: vk::PhysicalDevice,
+        queue_family: u32,
+    ) -> Option<Self> {
+        let formats = instance
+            .get_physical_device_surface_formats_khr(physical_device, surface, None)
+            .unwrap();
+        let format = match formats
+            .iter()
+            .find(|surface_format| {
+                surface_format.format == vk::Format::B8G8R8A8_SRGB
+                    && surface_format.color_space
+                        == khr_surface::ColorSpaceKHR::SRGB_NONLINEAR_KHR
+            })
+            .or_else(|| formats.get(0))
+        {
+            Some(surface_format) => surface_format.clone(),
+            None => return None,
+        };
+
+        let present_modes = instance
+            .get_physical_device_surface_present_modes_khr(physical_device, surface, None)
+            .unwrap();
+        let present_mode = present_modes
+            .iter()
+            .copied()
+            .find(|present_mode| present_mode == &khr_surface::PresentModeKHR::MAILBOX_KHR)
+            .unwrap_or(khr_surface::PresentModeKHR::FIFO_KHR);
+
+        let depth_format = match [
+            vk::Format::D32_SFLOAT_S8_UINT,
+            vk::Format::D24_UNORM_S8_UINT,
+        ]
+        .iter()
+        .copied()
+        .find(|&format| {
+            instance
+                .get_physical_device_format_properties(physical_device, format, None)
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        }) {
+            Some(format) => format,
+            None => return None,
+        };
+
+        let physical_device_properties =
+            instance.get_physical_device_properties(physical_device, None);
+        let memory_properties =
+            instance.get_physical_device_memory_properties(physical_device, None);
+        let capabilities = Capabilities::query(instance, api_version, physical_device);
+        Some(Self {
+            physical_device,
+            queue_family,
+            format,
+            present_mode,
+            present_modes,
+            depth_format,
+            physical_device_properties,
+            memory_properties,
+            capabilities,
+        })
+    }
 }
@@ -0,0 +1,113 @@
+use crate::{Engine, MaterialId, MeshData, ObjectId};
+use anyhow::Result;
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+/// Parses a mesh file's contents
+pub type MeshLoader = Box<dyn FnMut(&[u8]) -> Result<MeshData>>;
+
+/// Watches an asset directory and reloads registered shaders and meshes into their existing
+/// materials and objects. The engine has no textures, so there are none to reload.
+pub struct AssetWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    materials: HashMap<MaterialId, (PathBuf, PathBuf)>,
+    meshes: HashMap<ObjectId, (PathBuf, MeshLoader)>,
+}
+
+impl AssetWatcher {
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        let (tx, events) = channel();
+        let mut watcher = watcher(tx, Duration::from_millis(200))?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            materials: HashMap::new(),
+            meshes: HashMap::new(),
+        })
+    }
+
+    /// Reload `material` whenever either of its SPIR-V files changes
+    pub fn watch_material(
+        &mut self,
+        material: MaterialId,
+        vertex: impl AsRef<Path>,
+        fragment: impl AsRef<Path>,
+    ) -> Result<()> {
+        let vertex = fs::canonicalize(vertex)?;
+        let fragment = fs::canonicalize(fragment)?;
+        self.materials.insert(material, (vertex, fragment));
+        Ok(())
+    }
+
+    pub fn unwatch_material(&mut self, material: MaterialId) {
+        self.materials.remove(&material);
+    }
+
+    /// Replace `object`'s geometry whenever `path` changes, parsing the file with `load`
+    pub fn watch_mesh(
+        &mut self,
+        object: ObjectId,
+        path: impl AsRef<Path>,
+        load: impl FnMut(&[u8]) -> Result<MeshData> + 'static,
+    ) -> Result<()> {
+        let path = fs::canonicalize(path)?;
+        self.meshes.insert(object, (path, Box::new(load)));
+        Ok(())
+    }
+
+    pub fn unwatch_mesh(&mut self, object: ObjectId) {
+        self.meshes.remove(&object);
+    }
+
+    /// Apply pending changes. Call between frames, on the render thread. Shaders and meshes which
+    /// fail to load are reported and skipped, leaving the previous version in place.
+    pub fn update(&mut self, engine: &mut Engine) -> Result<()> {
+        let mut changed = Vec::new();
+        for event in self.events.try_iter() {
+            match event {
+                DebouncedEvent::Write(path)
+                | DebouncedEvent::Create(path)
+                | DebouncedEvent::Rename(_, path) => {
+                    changed.push(fs::canonicalize(&path).unwrap_or(path));
+                }
+                _ => (),
+            }
+        }
+
+        for (material, (vertex, fragment)) in &self.materials {
+            if !changed.iter().any(|p| p == vertex || p == fragment) {
+                continue;
+            }
+            let result = fs::read(vertex)
+                .and_then(|v| fs::read(fragment).map(|f| (v, f)))
+                .map_err(anyhow::Error::from)
+                .and_then(|(v, f)| engine.reload_material(*material, &v, &f));
+            match result {
+                Ok(()) => log::info!("Reloaded {:?}", material),
+                Err(e) => log::error!("Failed to reload {:?}: {:#}", material, e),
+            }
+        }
+
+        for (object, (path, load)) in &mut self.meshes {
+            if !changed.iter().any(|p| p == path) {
+                continue;
+            }
+            let result = fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| load(&bytes))
+                .and_then(|mesh| engine.replace_mesh(*object, &mesh.vertices, &mesh.indices));
+            match result {
+                Ok(_) => log::info!("Reloaded {:?}", object),
+                Err(e) => log::error!("Failed to reload {:?}: {:#}", object, e),
+            }
+        }
+
+        Ok(())
+    }
+}
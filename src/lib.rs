@@ -1,12 +1,79 @@
+mod arena;
 mod engine;
 mod hardware_query;
 mod frame_sync;
 mod swapchain;
 mod pipeline;
+mod shader_interface;
 mod vertex;
 mod camera;
 mod allocated_buffer;
+mod streamed_buffer;
+mod deletion_queue;
+mod debug_name;
+mod draw_list;
+mod descriptors;
+mod fog;
+mod lights;
+mod batch;
+mod memory_stats;
+mod memory;
+mod mesh;
+mod meshlet;
+mod audio;
+mod frame_pacing;
+mod clock;
+mod floating_origin;
+mod culling;
+mod chunks;
+mod terrain;
+mod point_cloud;
+mod splats;
+mod snapshot;
+mod animation;
+mod transforms;
+mod pose_recording;
+mod offscreen;
+pub mod locomotion;
+pub mod ui;
+pub mod quality;
+pub mod mesh_optimize;
+#[cfg(feature = "hecs")]
+pub mod ecs;
+#[cfg(feature = "scene")]
+pub mod scene;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+#[cfg(all(feature = "renderdoc", target_os = "linux"))]
+pub mod renderdoc;
+#[cfg(feature = "builtin-shaders")]
+pub mod builtin_shaders;
 pub use engine::*;
-pub use pipeline::DrawType;
-pub use vertex::Vertex;
+pub use pipeline::{
+    CullMode, DepthBias, DrawType, FrontFace, MaterialOptions, SpecConstant, ALPHA_CUTOFF_CONSTANT,
+};
+pub use vertex::{CompactVertex, Vertex, VertexFormat};
+pub use point_cloud::Point;
+pub use splats::Splat;
+pub use mesh::{Handedness, ImportTransform, MeshData, MeshRetention, UpAxis};
+pub use meshlet::{build_meshlets, Meshlet};
 pub use camera::Camera;
+pub use pose_recording::{PoseRecording, PoseSample};
+pub use culling::Aabb;
+pub use terrain::{Heightmap, TERRAIN_CHUNK_QUADS};
+pub use snapshot::{ObjectPose, TransformSnapshot};
+pub use animation::{Easing, Keyframe, Track};
+pub use audio::Listener;
+pub use clock::{Clock, SHADER_TIME_WRAP};
+pub use floating_origin::FloatingOrigin;
+pub use frame_pacing::{FramePacingReport, FrameTimings, Percentiles};
+pub use fog::{Fog, FogMode};
+pub use lights::{Light, LightKind, MAX_LIGHTS};
+pub use batch::{merge_static_meshes, StaticMesh};
+pub use memory_stats::MemoryStats;
+pub use hardware_query::Capabilities;
+pub use swapchain::PresentMode;
+pub use memory::{
+    Allocation, BlockAllocator, DedicatedAllocator, MemoryAllocator, MemoryLocation,
+    DEFAULT_BLOCK_SIZE,
+};
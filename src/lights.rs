@@ -0,0 +1,127 @@
+use nalgebra::{Point3, Vector3};
+
+/// Most lights the engine passes to shaders at once
+pub const MAX_LIGHTS: usize = 64;
+
+/// A light source, passed to every shader at set 0, binding 2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Passed on to shaders; the engine renders no shadow maps itself
+    pub cast_shadows: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far away, e.g. the sun, shining along `direction`
+    Directional { direction: Vector3<f32> },
+    /// Shining in every direction, fading out at `range`
+    Point { position: Point3<f32>, range: f32 },
+    /// A cone along `direction`, at full intensity within `inner_angle` of its axis and fading
+    /// out towards `outer_angle` (radians)
+    Spot {
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+impl Light {
+    /// Move a positioned light by `offset`, e.g. when the floating origin shifts
+    pub(crate) fn translate(&mut self, offset: &Vector3<f32>) {
+        match &mut self.kind {
+            LightKind::Directional { .. } => (),
+            LightKind::Point { position, .. } | LightKind::Spot { position, .. } => {
+                *position += offset;
+            }
+        }
+    }
+}
+
+/// One light as laid out for shaders (std140). `kind` is 0 for directional, 1 for point and 2
+/// for spot lights; cone angles are given as cosines.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub(crate) struct GpuLight {
+    position: [f32; 3],
+    kind: u32,
+    direction: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+    cos_inner_angle: f32,
+    cos_outer_angle: f32,
+    cast_shadows: u32,
+    _pad: u32,
+}
+
+unsafe impl bytemuck::Zeroable for GpuLight {}
+unsafe impl bytemuck::Pod for GpuLight {}
+
+/// Contents of the light uniform block: the number of lights, then that many lights
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct LightsUniform {
+    count: u32,
+    _pad: [u32; 3],
+    lights: [GpuLight; MAX_LIGHTS],
+}
+
+unsafe impl bytemuck::Zeroable for LightsUniform {}
+unsafe impl bytemuck::Pod for LightsUniform {}
+
+impl LightsUniform {
+    /// At most `MAX_LIGHTS` lights are taken from `lights`
+    pub fn new<'a>(lights: impl Iterator<Item = &'a Light>) -> Self {
+        let mut uniform: Self = bytemuck::Zeroable::zeroed();
+        for (gpu, light) in uniform.lights.iter_mut().zip(lights) {
+            *gpu = GpuLight::new(light);
+            uniform.count += 1;
+        }
+        uniform
+    }
+}
+
+impl GpuLight {
+    fn new(light: &Light) -> Self {
+        let base = Self {
+            color: light.color,
+            intensity: light.intensity,
+            cast_shadows: light.cast_shadows as u32,
+            ..Default::default()
+        };
+        match light.kind {
+            LightKind::Directional { direction } => Self {
+                kind: 0,
+                direction: *direction.normalize().as_ref(),
+                ..base
+            },
+            LightKind::Point { position, range } => Self {
+                kind: 1,
+                position: *position.coords.as_ref(),
+                range,
+                ..base
+            },
+            LightKind::Spot {
+                position,
+                direction,
+                range,
+                inner_angle,
+                outer_angle,
+            } => Self {
+                kind: 2,
+                position: *position.coords.as_ref(),
+                direction: *direction.normalize().as_ref(),
+                range,
+                cos_inner_angle: inner_angle.cos(),
+                cos_outer_angle: outer_angle.cos(),
+                ..base
+            },
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::camera::Camera;
+use crate::vertex::Vertex;
+use nalgebra::{Matrix4, Point3, Unit, Vector2, Vector3};
+
+/// Plane teleport targets are tested against, e.g. the floor
+#[derive(Debug, Clone, Copy)]
+pub struct Plane {
+    pub point: Point3<f32>,
+    pub normal: Unit<Vector3<f32>>,
+}
+
+/// Ballistic arc used to pick a teleport destination
+#[derive(Debug, Clone)]
+pub struct TeleportArc {
+    /// Sampled points along the arc, ending at the hit point if there was one
+    pub points: Vec<Point3<f32>>,
+    pub target: Option<Point3<f32>>,
+}
+
+/// Parameters for teleport arc sampling
+#[derive(Debug, Clone, Copy)]
+pub struct TeleportSettings {
+    /// Launch speed along the aim direction
+    pub speed: f32,
+    /// Gravity, pointing down the world Y axis
+    pub gravity: f32,
+    /// Time between samples
+    pub step: f32,
+    pub max_steps: usize,
+    /// Steepest surface (angle from vertical, radians) that counts as a valid target
+    pub max_slope: f32,
+}
+
+impl Default for TeleportSettings {
+    fn default() -> Self {
+        Self {
+            speed: 8.0,
+            gravity: 9.8,
+            step: 0.02,
+            max_steps: 200,
+            max_slope: 30f32.to_radians(),
+        }
+    }
+}
+
+/// Trace a teleport arc from `origin` along `direction` until it crosses `floor`
+pub fn teleport_arc(
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    floor: &Plane,
+    settings: &TeleportSettings,
+) -> TeleportArc {
+    let velocity = direction.normalize() * settings.speed;
+    let gravity = Vector3::new(0.0, -settings.gravity, 0.0);
+    let height = |p: &Point3<f32>| (p - floor.point).dot(&floor.normal);
+
+    let mut points = vec![origin];
+    for i in 1..=settings.max_steps {
+        let t = i as f32 * settings.step;
+        let next = origin + velocity * t + gravity * (0.5 * t * t);
+        let prev = *points.last().unwrap();
+
+        let (h0, h1) = (height(&prev), height(&next));
+        if h0 > 0.0 && h1 <= 0.0 {
+            let hit = prev + (next - prev) * (h0 / (h0 - h1));
+            points.push(hit);
+            let slope = floor.normal.y.max(-1.0).min(1.0).acos();
+            let valid = slope <= settings.max_slope;
+            return TeleportArc {
+                points,
+                target: if valid { Some(hit) } else { None },
+            };
+        }
+        points.push(next);
+    }
+
+    TeleportArc {
+        points,
+        target: None,
+    }
+}
+
+/// Move the camera to stand over `target`, keeping its height above the floor and view direction
+pub fn teleport_camera(camera: &mut Camera, floor_height: f32, target: Point3<f32>) {
+    let eye_height = camera.eye.y - floor_height;
+    let offset = (target + Vector3::y() * eye_height) - camera.eye;
+    camera.eye += offset;
+    camera.at += offset;
+}
+
+/// Move the camera along the ground plane. `input.y` is forward/back and `input.x` is strafing,
+/// each in -1..=1, scaled by `speed` (units/second) and `dt` (seconds).
+pub fn smooth_locomotion(camera: &mut Camera, input: Vector2<f32>, speed: f32, dt: f32) {
+    let forward = camera.at - camera.eye;
+    let forward = Vector3::new(forward.x, 0.0, forward.z);
+    if forward.norm_squared() == 0.0 {
+        return;
+    }
+    let forward = forward.normalize();
+    let right = forward.cross(&Vector3::y());
+    let offset = (forward * input.y + right * input.x) * speed * dt;
+    camera.eye += offset;
+    camera.at += offset;
+}
+
+/// Turn the player by `angle` radians about the vertical axis through `pivot`, usually the
+/// stage space head position, returning the new `Engine::world_transform`.
+pub fn snap_turn(world_transform: &Matrix4<f32>, pivot: &Point3<f32>, angle: f32) -> Matrix4<f32> {
+    let pivot = world_transform.transform_point(pivot).coords;
+    let rotation = Matrix4::new_rotation(Vector3::y() * angle);
+    Matrix4::new_translation(&pivot)
+        * rotation
+        * Matrix4::new_translation(&-pivot)
+        * world_transform
+}
+
+/// Raise or lower the stage so that a head at `eye_height` above the stage floor appears at
+/// `target_height`, e.g. to let a seated user see from a standing height. Returns the new
+/// `Engine::world_transform`.
+pub fn calibrate_height(
+    world_transform: &Matrix4<f32>,
+    eye_height: f32,
+    target_height: f32,
+) -> Matrix4<f32> {
+    Matrix4::new_translation(&(Vector3::y() * (target_height - eye_height))) * world_transform
+}
+
+/// Line list mesh of a grid covering a `size.x` by `size.y` play area on the floor, centered on
+/// the origin, with lines every `spacing` units and the outer edge always drawn. Draw it with a
+/// `DrawType::Lines` material to show the user where they can walk.
+pub fn play_area_grid(
+    size: Vector2<f32>,
+    spacing: f32,
+    color: [f32; 3],
+) -> (Vec<Vertex>, Vec<u16>) {
+    assert!(spacing > 0.0, "Grid spacing must be positive");
+    let half = size / 2.0;
+    let mut vertices = Vec::new();
+
+    // Offsets of each line from the minimum edge, ending exactly on the maximum edge
+    let offsets = |length: f32| {
+        // Tolerate rounding so that a length divisible by the spacing has no doubled edge
+        let n = (length / spacing - 1e-3).ceil().max(1.0) as usize;
+        (0..=n).map(move |i| (i as f32 * spacing).min(length))
+    };
+
+    for x in offsets(size.x) {
+        let x = x - half.x;
+        vertices.push(Vertex {
+            pos: [x, 0.0, -half.y],
+            color,
+        });
+        vertices.push(Vertex {
+            pos: [x, 0.0, half.y],
+            color,
+        });
+    }
+    for z in offsets(size.y) {
+        let z = z - half.y;
+        vertices.push(Vertex {
+            pos: [-half.x, 0.0, z],
+            color,
+        });
+        vertices.push(Vertex {
+            pos: [half.x, 0.0, z],
+            color,
+        });
+    }
+
+    assert!(vertices.len() <= u16::MAX as usize, "Too many grid lines");
+    let indices = (0..vertices.len() as u16).collect();
+    (vertices, indices)
+}
@@ -3,7 +3,7 @@ use nalgebra::{Matrix4, Point3, Vector3};
 use std::fs;
 use std::io::Write;
 use std::time::Duration;
-use the_hard_way::{Camera, DrawType, Engine, Vertex};
+use the_hard_way::{Camera, DrawType, Engine, Fog, FogMode, MeshRetention, Vertex};
 use winit::{
     event::{Event, StartCause, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
@@ -26,6 +26,14 @@ fn main() -> Result<()> {
     let fragment = fs::read("shaders/triangle.frag.spv")?;
     let material = engine.load_material(&vertex, &fragment, DrawType::Triangles)?;
 
+    engine.set_fog(Fog {
+        color: [0.1, 0.1, 0.15],
+        mode: FogMode::Linear {
+            start: 5.0,
+            end: 15.0,
+        },
+    });
+
     let mut vertices = [
         Vertex {
             pos: [-1.0, -1.0, -1.0],
@@ -66,8 +74,20 @@ fn main() -> Result<()> {
         4, 5, 0, 0, 5, 1,
     ];
 
-    let mesh = engine.add_object(&vertices[..], &indices[..], material, true)?;
-    let mesh2 = engine.add_object(&vertices[..], &indices[..], material, false)?;
+    let mesh = engine.add_object(
+        &vertices[..],
+        &indices[..],
+        material,
+        true,
+        MeshRetention::Discard,
+    )?;
+    let mesh2 = engine.add_object(
+        &vertices[..],
+        &indices[..],
+        material,
+        false,
+        MeshRetention::Discard,
+    )?;
 
     let mut camera = Camera {
         eye: Point3::new(-4.0, 4.0, -4.0),
@@ -87,15 +107,22 @@ fn main() -> Result<()> {
         }
         Event::WindowEvent { event, .. } => match event {
             WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::Resized(size) => {
+                engine
+                    .resize(size.width, size.height)
+                    .expect("Failed to resize");
+            }
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                engine
+                    .resize(new_inner_size.width, new_inner_size.height)
+                    .expect("Failed to resize");
+            }
             _ => (),
         },
         Event::MainEventsCleared => {
             let frame_start_time = std::time::Instant::now();
-            let time_var = (frame_start_time - start_time).as_millis() as f32 / 1000.0;
 
-            engine
-                .next_frame(&camera, time_var)
-                .expect("Frame failed to render");
+            engine.next_frame(&camera).expect("Frame failed to render");
             let frame_end_time = std::time::Instant::now();
             frame_count += 1;
 
@@ -114,13 +141,12 @@ fn main() -> Result<()> {
             std::io::stdout().lock().flush().unwrap();
             */
 
-            let transform = Matrix4::from_euler_angles(0.0, time_var, 0.0);
-            engine.set_transform(mesh, transform);
+            let angle = start_time.elapsed().as_secs_f32();
+            let transform = Matrix4::from_euler_angles(0.0, angle, 0.0);
+            engine.set_transform(mesh, transform).unwrap();
 
             let transform = Matrix4::new_translation(&Vector3::new(0.5, 0.5, 0.5));
-            engine.set_transform(mesh2, transform);
-            //camera.eye[0] = time_var.cos();
-            //camera.eye[2] = time_var.sin();
+            engine.set_transform(mesh2, transform).unwrap();
 
             if frame_duration < target_frame_time {
                 std::thread::sleep(target_frame_time - frame_duration);
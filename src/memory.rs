@@ -0,0 +1,403 @@
+use anyhow::Result;
+use erupt::{vk1_0 as vk, DeviceLoader};
+use std::collections::HashMap;
+
+/// Size of each block of device memory `BlockAllocator` suballocates from
+pub const DEFAULT_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Where an allocation should live
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// Host-visible and coherent, written directly by the CPU
+    CpuToGpu,
+    /// Device-local, only reachable through transfers
+    GpuOnly,
+}
+
+/// A region of device memory handed out by a `MemoryAllocator`
+pub struct Allocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: u64,
+    pub size: u64,
+    pub location: MemoryLocation,
+}
+
+/// Strategy for handing out device memory. The engine only talks to this trait, so a
+/// suballocating or defragmenting allocator can be swapped in without touching resource code.
+pub trait MemoryAllocator {
+    fn allocate(
+        &mut self,
+        device: &DeviceLoader,
+        requirements: vk::MemoryRequirements,
+        location: MemoryLocation,
+    ) -> Result<Allocation>;
+
+    fn free(&mut self, device: &DeviceLoader, allocation: Allocation);
+
+    /// Release memory the allocator holds on to itself. Called when the engine is dropped,
+    /// after every allocation has been freed and before the device is destroyed.
+    fn free_all(&mut self, _device: &DeviceLoader) {}
+}
+
+impl Allocation {
+    /// Copy `data` into the start of this allocation. Only valid for `CpuToGpu` memory.
+    pub fn write(&self, device: &DeviceLoader, data: &[u8]) -> Result<()> {
+        self.write_at(device, 0, data)
+    }
+
+    /// Copy `data` into this allocation, `offset` bytes from its start. Only valid for `CpuToGpu`
+    /// memory.
+    pub fn write_at(&self, device: &DeviceLoader, offset: u64, data: &[u8]) -> Result<()> {
+        anyhow::ensure!(
+            self.location == MemoryLocation::CpuToGpu,
+            "Cannot map gpu-only memory"
+        );
+        anyhow::ensure!(
+            offset + data.len() as u64 <= self.size,
+            "Write exceeds allocation"
+        );
+        unsafe {
+            let ptr = device
+                .map_memory(
+                    self.memory,
+                    self.offset + offset,
+                    data.len() as u64,
+                    None,
+                    None,
+                )
+                .result()?;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len());
+            device.unmap_memory(self.memory);
+        }
+        Ok(())
+    }
+
+    /// Copy the whole allocation out. Only valid for `CpuToGpu` memory.
+    pub fn read(&self, device: &DeviceLoader) -> Result<Vec<u8>> {
+        anyhow::ensure!(
+            self.location == MemoryLocation::CpuToGpu,
+            "Cannot map gpu-only memory"
+        );
+        let mut data = vec![0; self.size as usize];
+        unsafe {
+            let ptr = device
+                .map_memory(self.memory, self.offset, self.size, None, None)
+                .result()?;
+            std::ptr::copy_nonoverlapping(ptr as *const u8, data.as_mut_ptr(), data.len());
+            device.unmap_memory(self.memory);
+        }
+        Ok(data)
+    }
+}
+
+fn find_memory_type(
+    props: &vk::PhysicalDeviceMemoryProperties,
+    type_bits: u32,
+    location: MemoryLocation,
+) -> Result<u32> {
+    let flags = match location {
+        MemoryLocation::CpuToGpu => {
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+        }
+        MemoryLocation::GpuOnly => vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    };
+    props.memory_types[..props.memory_type_count as usize]
+        .iter()
+        .enumerate()
+        .find(|(i, ty)| type_bits & (1 << i) != 0 && ty.property_flags.contains(flags))
+        .map(|(i, _)| i as u32)
+        .ok_or_else(|| anyhow::format_err!("No memory type suitable for {:?}", location))
+}
+
+fn allocate_memory(device: &DeviceLoader, size: u64, memory_type: u32) -> Result<vk::DeviceMemory> {
+    let allocate_info = vk::MemoryAllocateInfoBuilder::new()
+        .allocation_size(size)
+        .memory_type_index(memory_type);
+    Ok(unsafe { device.allocate_memory(&allocate_info, None, None) }.result()?)
+}
+
+/// Allocate a buffer's memory and bind it
+pub fn allocate_buffer(
+    allocator: &mut dyn MemoryAllocator,
+    device: &DeviceLoader,
+    buffer: vk::Buffer,
+    location: MemoryLocation,
+) -> Result<Allocation> {
+    let requirements = unsafe { device.get_buffer_memory_requirements(buffer, None) };
+    let allocation = allocator.allocate(device, requirements, location)?;
+    unsafe { device.bind_buffer_memory(buffer, allocation.memory, allocation.offset) }.result()?;
+    Ok(allocation)
+}
+
+/// Allocate an image's memory and bind it
+pub fn allocate_image(
+    allocator: &mut dyn MemoryAllocator,
+    device: &DeviceLoader,
+    image: vk::Image,
+    location: MemoryLocation,
+) -> Result<Allocation> {
+    let requirements = unsafe { device.get_image_memory_requirements(image, None) };
+    let allocation = allocator.allocate(device, requirements, location)?;
+    unsafe { device.bind_image_memory(image, allocation.memory, allocation.offset) }.result()?;
+    Ok(allocation)
+}
+
+/// Gives every resource its own device memory allocation. Simple and always correct, at the cost
+/// of counting against maxMemoryAllocationCount for each resource.
+pub struct DedicatedAllocator {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+}
+
+impl DedicatedAllocator {
+    pub fn new(memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+        Self { memory_properties }
+    }
+}
+
+impl MemoryAllocator for DedicatedAllocator {
+    fn allocate(
+        &mut self,
+        device: &DeviceLoader,
+        requirements: vk::MemoryRequirements,
+        location: MemoryLocation,
+    ) -> Result<Allocation> {
+        let memory_type = find_memory_type(
+            &self.memory_properties,
+            requirements.memory_type_bits,
+            location,
+        )?;
+        let memory = allocate_memory(device, requirements.size, memory_type)?;
+        Ok(Allocation {
+            memory,
+            offset: 0,
+            size: requirements.size,
+            location,
+        })
+    }
+
+    fn free(&mut self, device: &DeviceLoader, allocation: Allocation) {
+        unsafe {
+            device.free_memory(Some(allocation.memory), None);
+        }
+    }
+}
+
+/// Suballocates resources from large blocks of device memory, one list of blocks per memory
+/// type, so that the number of live `vkAllocateMemory` allocations stays small. Resources larger
+/// than half a block get a dedicated allocation instead. Each block keeps a first-fit free list,
+/// merging neighbouring ranges as they are freed; a block left empty is released, except for the
+/// last one of its memory type.
+///
+/// Every range is aligned to `bufferImageGranularity` as well as the resource's own alignment, so
+/// buffers and optimally tiled images may share a block. Host-visible blocks are mapped only for
+/// the duration of each write, so allocations sharing a block must not be written concurrently.
+pub struct BlockAllocator {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    granularity: u64,
+    block_size: u64,
+    /// Blocks of each memory type
+    blocks: HashMap<u32, Vec<Block>>,
+    /// Dedicated allocations, and their memory type
+    dedicated: HashMap<vk::DeviceMemory, u32>,
+}
+
+struct Block {
+    memory: vk::DeviceMemory,
+    size: u64,
+    /// Unused ranges as (offset, size), sorted by offset
+    free: Vec<(u64, u64)>,
+    /// Range taken by each allocation, including its alignment padding, by its offset
+    used: HashMap<u64, (u64, u64)>,
+}
+
+impl BlockAllocator {
+    pub fn new(
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        limits: &vk::PhysicalDeviceLimits,
+    ) -> Self {
+        Self::with_block_size(memory_properties, limits, DEFAULT_BLOCK_SIZE)
+    }
+
+    pub fn with_block_size(
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        limits: &vk::PhysicalDeviceLimits,
+        block_size: u64,
+    ) -> Self {
+        Self {
+            memory_properties,
+            granularity: limits.buffer_image_granularity.max(1),
+            block_size,
+            blocks: HashMap::new(),
+            dedicated: HashMap::new(),
+        }
+    }
+
+    /// Device memory allocations currently held, in blocks and dedicated allocations
+    pub fn allocation_count(&self) -> usize {
+        self.blocks.values().map(Vec::len).sum::<usize>() + self.dedicated.len()
+    }
+}
+
+impl MemoryAllocator for BlockAllocator {
+    fn allocate(
+        &mut self,
+        device: &DeviceLoader,
+        requirements: vk::MemoryRequirements,
+        location: MemoryLocation,
+    ) -> Result<Allocation> {
+        let memory_type = find_memory_type(
+            &self.memory_properties,
+            requirements.memory_type_bits,
+            location,
+        )?;
+
+        if requirements.size > self.block_size / 2 {
+            let memory = allocate_memory(device, requirements.size, memory_type)?;
+            self.dedicated.insert(memory, memory_type);
+            return Ok(Allocation {
+                memory,
+                offset: 0,
+                size: requirements.size,
+                location,
+            });
+        }
+
+        let alignment = requirements.alignment.max(self.granularity);
+        let size = align_up(requirements.size, self.granularity);
+        let blocks = self.blocks.entry(memory_type).or_default();
+        let found = blocks
+            .iter_mut()
+            .find_map(|block| Some((block.memory, block.take(size, alignment)?)));
+        let (memory, offset) = match found {
+            Some(found) => found,
+            None => {
+                let memory = allocate_memory(device, self.block_size, memory_type)?;
+                let mut block = Block::new(memory, self.block_size);
+                let offset = block
+                    .take(size, alignment)
+                    .expect("Allocation fits in an empty block");
+                blocks.push(block);
+                (memory, offset)
+            }
+        };
+
+        Ok(Allocation {
+            memory,
+            offset,
+            size: requirements.size,
+            location,
+        })
+    }
+
+    fn free(&mut self, device: &DeviceLoader, allocation: Allocation) {
+        if self.dedicated.remove(&allocation.memory).is_some() {
+            unsafe {
+                device.free_memory(Some(allocation.memory), None);
+            }
+            return;
+        }
+
+        for blocks in self.blocks.values_mut() {
+            let index = match blocks.iter().position(|b| b.memory == allocation.memory) {
+                Some(index) => index,
+                None => continue,
+            };
+            blocks[index].give_back(allocation.offset);
+            if blocks[index].is_empty() && blocks.len() > 1 {
+                let block = blocks.remove(index);
+                unsafe {
+                    device.free_memory(Some(block.memory), None);
+                }
+            }
+            return;
+        }
+        panic!("Freed an allocation this allocator does not own");
+    }
+
+    fn free_all(&mut self, device: &DeviceLoader) {
+        for (_, blocks) in self.blocks.drain() {
+            for block in blocks {
+                if !block.is_empty() {
+                    log::warn!("Freeing a memory block with live allocations");
+                }
+                unsafe {
+                    device.free_memory(Some(block.memory), None);
+                }
+            }
+        }
+        for (memory, _) in self.dedicated.drain() {
+            unsafe {
+                device.free_memory(Some(memory), None);
+            }
+        }
+    }
+}
+
+impl Block {
+    fn new(memory: vk::DeviceMemory, size: u64) -> Self {
+        Self {
+            memory,
+            size,
+            free: vec![(0, size)],
+            used: HashMap::new(),
+        }
+    }
+
+    /// Take the first free range `size` fits in at `alignment`, returning its aligned offset
+    fn take(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let (index, offset) = self
+            .free
+            .iter()
+            .enumerate()
+            .map(|(i, &(start, _))| (i, align_up(start, alignment)))
+            .find(|&(i, offset)| {
+                let (start, len) = self.free[i];
+                offset + size <= start + len
+            })?;
+        let (start, len) = self.free[index];
+        let end = offset + size;
+        if end == start + len {
+            self.free.remove(index);
+        } else {
+            self.free[index] = (end, start + len - end);
+        }
+        self.used.insert(offset, (start, end - start));
+        Some(offset)
+    }
+
+    /// Return the range of the allocation at `offset` to the free list
+    fn give_back(&mut self, offset: u64) {
+        let (start, len) = self
+            .used
+            .remove(&offset)
+            .expect("Freed an allocation twice");
+        let index = match self.free.binary_search_by_key(&start, |&(free, _)| free) {
+            Ok(index) | Err(index) => index,
+        };
+        self.free.insert(index, (start, len));
+
+        // Merge with the following range, then the preceding one
+        if index + 1 < self.free.len() {
+            let (next, next_len) = self.free[index + 1];
+            if start + len == next {
+                self.free[index].1 += next_len;
+                self.free.remove(index + 1);
+            }
+        }
+        if index > 0 {
+            let (prev, prev_len) = self.free[index - 1];
+            if prev + prev_len == start {
+                self.free[index - 1].1 += self.free[index].1;
+                self.free.remove(index);
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.free == [(0, self.size)]
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) / alignment * alignment
+}
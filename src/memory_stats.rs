@@ -0,0 +1,80 @@
+use crate::hardware_query::HardwareSelection;
+use erupt::{extensions::ext_memory_budget, vk1_0 as vk, vk1_1, InstanceLoader};
+
+/// Fraction of the device-local budget above which the engine warns about memory usage
+pub const BUDGET_WARNING_FRACTION: f32 = 0.9;
+
+/// GPU memory held by the engine, broken down by usage
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemoryStats {
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+    pub uniform_bytes: u64,
+    pub storage_bytes: u64,
+    pub image_bytes: u64,
+    /// Device-local memory this process may use, as estimated by the driver with
+    /// `VK_EXT_memory_budget`, or the combined size of all device-local heaps without it
+    pub device_local_budget: u64,
+    /// Device-local memory in use by this process as reported by `VK_EXT_memory_budget`,
+    /// including allocations made outside the engine. `None` without the extension.
+    pub device_local_usage: Option<u64>,
+}
+
+impl MemoryStats {
+    pub(crate) fn new(instance: &InstanceLoader, hardware: &HardwareSelection) -> Self {
+        let props = &hardware.memory_properties;
+        let device_local = |index: usize| {
+            props.memory_heaps[index]
+                .flags
+                .contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+        };
+        let heaps = (0..props.memory_heap_count as usize).filter(|&i| device_local(i));
+
+        if hardware.capabilities.memory_budget {
+            let mut budget =
+                ext_memory_budget::PhysicalDeviceMemoryBudgetPropertiesEXTBuilder::new();
+            let mut props2 = vk1_1::PhysicalDeviceMemoryProperties2Builder::new();
+            props2.p_next = &mut *budget
+                as *mut ext_memory_budget::PhysicalDeviceMemoryBudgetPropertiesEXT
+                as _;
+            unsafe {
+                instance.get_physical_device_memory_properties2(
+                    hardware.physical_device,
+                    Some(props2.build()),
+                );
+            }
+            Self {
+                device_local_budget: heaps.clone().map(|i| budget.heap_budget[i]).sum(),
+                device_local_usage: Some(heaps.map(|i| budget.heap_usage[i]).sum()),
+                ..Default::default()
+            }
+        } else {
+            Self {
+                device_local_budget: heaps.map(|i| props.memory_heaps[i].size).sum(),
+                ..Default::default()
+            }
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.vertex_bytes
+            + self.index_bytes
+            + self.uniform_bytes
+            + self.storage_bytes
+            + self.image_bytes
+    }
+
+    /// Fraction of the device-local budget currently in use, by the driver's account where
+    /// available and the engine's otherwise
+    pub fn budget_fraction(&self) -> f32 {
+        if self.device_local_budget == 0 {
+            return 0.0;
+        }
+        let usage = self.device_local_usage.unwrap_or_else(|| self.total());
+        usage as f32 / self.device_local_budget as f32
+    }
+
+    pub fn near_budget(&self) -> bool {
+        self.budget_fraction() > BUDGET_WARNING_FRACTION
+    }
+}
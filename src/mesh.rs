@@ -0,0 +1,142 @@
+use crate::mesh_optimize::{
+    optimize_vertex_cache, optimize_vertex_fetch, simplify, VERTEX_CACHE_SIZE,
+};
+use crate::meshlet::{build_meshlets, Meshlet};
+use crate::vertex::Vertex;
+use anyhow::Result;
+use nalgebra::{Matrix3, Point3, Vector3};
+
+/// Whether the engine keeps a host-side copy of an object's geometry after uploading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshRetention {
+    /// Only the GPU buffers hold the geometry
+    Discard,
+    /// A copy is kept, readable through `Engine::mesh_data()` and updated along with the buffers
+    Retain,
+}
+
+impl Default for MeshRetention {
+    fn default() -> Self {
+        MeshRetention::Discard
+    }
+}
+
+/// Host-side copy of an object's geometry
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+impl MeshData {
+    pub fn new(vertices: &[Vertex], indices: &[u16]) -> Self {
+        Self {
+            vertices: vertices.to_vec(),
+            indices: indices.to_vec(),
+        }
+    }
+
+    /// Split this mesh into meshlets for a mesh shader pipeline
+    pub fn meshlets(&self) -> Vec<Meshlet> {
+        build_meshlets(&self.indices)
+    }
+
+    /// Reorder triangles for the GPU's vertex cache, then vertices in the order they are used.
+    /// Renders the same triangles, faster for large meshes. Unused vertices are dropped. Fails
+    /// if an index is out of range.
+    pub fn optimized(&self) -> Result<Self> {
+        let indices = optimize_vertex_cache(&self.indices, self.vertices.len(), VERTEX_CACHE_SIZE)?;
+        let (vertices, indices) = optimize_vertex_fetch(&self.vertices, &indices)?;
+        Ok(Self { vertices, indices })
+    }
+
+    /// A coarser version of this triangle list, with vertices within each `cell_size` grid
+    /// cell merged. See `mesh_optimize::simplify`.
+    pub fn simplified(&self, cell_size: f32) -> Result<Self> {
+        let (vertices, indices) = simplify(&self.vertices, &self.indices, cell_size)?;
+        Self { vertices, indices }.optimized()
+    }
+
+    /// Convert a triangle list authored in another tool's conventions into the engine's. When
+    /// the handedness changes, triangle winding is reversed too so that front faces stay front
+    /// faces.
+    pub fn with_import_transform(mut self, transform: &ImportTransform) -> Self {
+        let matrix = transform.matrix();
+        for vertex in &mut self.vertices {
+            let pos = matrix * Point3::from(vertex.pos);
+            vertex.pos = [pos.x, pos.y, pos.z];
+        }
+        if matrix.determinant() < 0.0 {
+            for triangle in self.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+        self
+    }
+}
+
+/// Axis pointing up in an asset's source coordinate system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handedness {
+    Right,
+    Left,
+}
+
+/// Coordinate conventions of imported geometry, converted to the engine's right-handed, Y-up
+/// space by `MeshData::with_import_transform`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImportTransform {
+    pub up_axis: UpAxis,
+    pub handedness: Handedness,
+    /// Engine units per source unit, e.g. 0.01 for centimeters
+    pub scale: f32,
+}
+
+impl ImportTransform {
+    /// Already in the engine's conventions
+    pub const IDENTITY: Self = Self {
+        up_axis: UpAxis::Y,
+        handedness: Handedness::Right,
+        scale: 1.0,
+    };
+    /// Blender: right-handed, Z up
+    pub const BLENDER: Self = Self {
+        up_axis: UpAxis::Z,
+        handedness: Handedness::Right,
+        scale: 1.0,
+    };
+    /// Unity: left-handed, Y up
+    pub const UNITY: Self = Self {
+        up_axis: UpAxis::Y,
+        handedness: Handedness::Left,
+        scale: 1.0,
+    };
+
+    /// Maps source positions to engine positions
+    pub fn matrix(&self) -> Matrix3<f32> {
+        // Mirror the source's forward axis to make it right-handed, then bring its up axis to Y
+        let handedness = match (self.handedness, self.up_axis) {
+            (Handedness::Right, _) => Matrix3::identity(),
+            (Handedness::Left, UpAxis::Y) => Matrix3::from_diagonal(&Vector3::new(1.0, 1.0, -1.0)),
+            (Handedness::Left, UpAxis::Z) => Matrix3::from_diagonal(&Vector3::new(1.0, -1.0, 1.0)),
+        };
+        let up = match self.up_axis {
+            UpAxis::Y => Matrix3::identity(),
+            // (x, y, z) -> (x, z, -y)
+            UpAxis::Z => Matrix3::new(1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -1.0, 0.0),
+        };
+        up * handedness * self.scale
+    }
+}
+
+impl Default for ImportTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
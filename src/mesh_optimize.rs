@@ -0,0 +1,320 @@
+use crate::vertex::Vertex;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Post-transform vertex cache size assumed by `optimize_vertex_cache`. Real caches vary, but
+/// orderings tuned for 16 entries do well on most GPUs.
+pub const VERTEX_CACHE_SIZE: usize = 16;
+
+/// Fails if any index is outside of `0..vertex_count`
+fn check_indices(indices: &[u16], vertex_count: usize) -> Result<()> {
+    if let Some(index) = indices
+        .iter()
+        .find(|&&index| index as usize >= vertex_count)
+    {
+        anyhow::bail!(
+            "Index {} is out of range for {} vertices",
+            index,
+            vertex_count
+        );
+    }
+    Ok(())
+}
+
+/// Reorder the triangles of a triangle list so that consecutive triangles share vertices,
+/// reducing how often the GPU transforms the same vertex twice. Uses Tipsify (Sander et al.,
+/// "Fast Triangle Reordering for Vertex Locality and Reduced Overdraw"), which fans around one
+/// vertex at a time and moves on to a neighbour still likely to be in the cache. Fails if an
+/// index is out of range.
+pub fn optimize_vertex_cache(
+    indices: &[u16],
+    vertex_count: usize,
+    cache_size: usize,
+) -> Result<Vec<u16>> {
+    check_indices(indices, vertex_count)?;
+    let triangle_count = indices.len() / 3;
+    let indices = &indices[..triangle_count * 3];
+
+    // Triangles using each vertex, as ranges of one flat list
+    let mut live = vec![0usize; vertex_count];
+    for &index in indices {
+        live[index as usize] += 1;
+    }
+    let mut offsets = Vec::with_capacity(vertex_count + 1);
+    offsets.push(0);
+    for count in &live {
+        offsets.push(offsets.last().unwrap() + count);
+    }
+    let mut adjacency = vec![0; indices.len()];
+    let mut fill = offsets.clone();
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for &index in corners {
+            adjacency[fill[index as usize]] = triangle;
+            fill[index as usize] += 1;
+        }
+    }
+
+    // A vertex is in the cache if it was last used within `cache_size` time steps
+    let mut timestamps = vec![0usize; vertex_count];
+    let mut time = cache_size + 1;
+    let mut emitted = vec![false; triangle_count];
+    let mut dead_end = Vec::new();
+    let mut cursor = 0;
+    let mut output = Vec::with_capacity(indices.len());
+
+    let mut fanning = indices.first().map(|&index| index as usize);
+    while let Some(vertex) = fanning {
+        let mut candidates = Vec::new();
+        for &triangle in &adjacency[offsets[vertex]..offsets[vertex + 1]] {
+            if emitted[triangle] {
+                continue;
+            }
+            emitted[triangle] = true;
+            for &index in &indices[triangle * 3..triangle * 3 + 3] {
+                let index = index as usize;
+                output.push(index as u16);
+                dead_end.push(index);
+                candidates.push(index);
+                live[index] -= 1;
+                if time - timestamps[index] > cache_size {
+                    timestamps[index] = time;
+                    time += 1;
+                }
+            }
+        }
+
+        // Prefer the candidate that will still be cached after fanning around it, and was
+        // cached longest
+        let mut best = None;
+        let mut best_priority = -1;
+        for &candidate in &candidates {
+            if live[candidate] == 0 {
+                continue;
+            }
+            let age = time - timestamps[candidate];
+            let priority = if age + 2 * live[candidate] <= cache_size {
+                age as i64
+            } else {
+                0
+            };
+            if priority > best_priority {
+                best_priority = priority;
+                best = Some(candidate);
+            }
+        }
+
+        fanning = best.or_else(|| {
+            // Recently used vertices first, then anything left in input order
+            while let Some(index) = dead_end.pop() {
+                if live[index] > 0 {
+                    return Some(index);
+                }
+            }
+            while cursor < vertex_count {
+                cursor += 1;
+                if live[cursor - 1] > 0 {
+                    return Some(cursor - 1);
+                }
+            }
+            None
+        });
+    }
+
+    Ok(output)
+}
+
+/// Reorder vertices into the order the indices first use them, so that vertex fetches walk
+/// memory mostly forwards. Vertices no index refers to are dropped. Fails if an index is out of
+/// range.
+pub fn optimize_vertex_fetch(
+    vertices: &[Vertex],
+    indices: &[u16],
+) -> Result<(Vec<Vertex>, Vec<u16>)> {
+    check_indices(indices, vertices.len())?;
+    let mut remap = vec![None; vertices.len()];
+    let mut new_vertices = Vec::with_capacity(vertices.len());
+    let new_indices = indices
+        .iter()
+        .map(|&index| {
+            *remap[index as usize].get_or_insert_with(|| {
+                new_vertices.push(vertices[index as usize]);
+                (new_vertices.len() - 1) as u16
+            })
+        })
+        .collect();
+    Ok((new_vertices, new_indices))
+}
+
+/// Simplify a triangle list by merging all vertices within the same cell of a grid with
+/// `cell_size` spacing into one at their average position and color. Triangles left with fewer
+/// than three distinct corners are removed. Fast and robust on any input, though coarse cells
+/// can close small holes or merge nearby surfaces. Fails if an index is out of range.
+pub fn simplify(
+    vertices: &[Vertex],
+    indices: &[u16],
+    cell_size: f32,
+) -> Result<(Vec<Vertex>, Vec<u16>)> {
+    check_indices(indices, vertices.len())?;
+    let cell_of = |vertex: &Vertex| {
+        let mut cell = [0i32; 3];
+        for (cell, coord) in cell.iter_mut().zip(&vertex.pos) {
+            *cell = (coord / cell_size).floor() as i32;
+        }
+        cell
+    };
+
+    // Sum up the vertices in each occupied cell
+    let mut cells: HashMap<[i32; 3], usize> = HashMap::new();
+    let mut sums: Vec<([f32; 3], [f32; 3], f32)> = Vec::new();
+    let mut cluster = Vec::with_capacity(vertices.len());
+    for vertex in vertices {
+        let index = *cells.entry(cell_of(vertex)).or_insert_with(|| {
+            sums.push(([0.0; 3], [0.0; 3], 0.0));
+            sums.len() - 1
+        });
+        let (pos, color, count) = &mut sums[index];
+        for i in 0..3 {
+            pos[i] += vertex.pos[i];
+            color[i] += vertex.color[i];
+        }
+        *count += 1.0;
+        cluster.push(index as u16);
+    }
+
+    let new_vertices = sums
+        .iter()
+        .map(|(pos, color, count)| Vertex {
+            pos: [pos[0] / count, pos[1] / count, pos[2] / count],
+            color: [color[0] / count, color[1] / count, color[2] / count],
+        })
+        .collect();
+
+    let mut new_indices = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [
+            cluster[triangle[0] as usize],
+            cluster[triangle[1] as usize],
+            cluster[triangle[2] as usize],
+        ];
+        if a != b && b != c && a != c {
+            new_indices.extend_from_slice(&[a, b, c]);
+        }
+    }
+
+    Ok((new_vertices, new_indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(x: f32, z: f32) -> Vertex {
+        Vertex {
+            pos: [x, 0.0, z],
+            color: [1.0; 3],
+        }
+    }
+
+    /// A `size` by `size` grid of quads, two triangles each, in the order given by `shuffle`
+    fn grid(size: u16, shuffle: impl Fn(usize) -> usize) -> (Vec<Vertex>, Vec<u16>) {
+        let vertices = (0..=size)
+            .flat_map(|z| (0..=size).map(move |x| (x, z)))
+            .map(|(x, z)| vertex(x as f32, z as f32))
+            .collect();
+        let quads = (size as usize).pow(2);
+        let mut indices = Vec::new();
+        for quad in (0..quads).map(shuffle) {
+            let (x, z) = ((quad % size as usize) as u16, (quad / size as usize) as u16);
+            let corner = |dx, dz| (z + dz) * (size + 1) + x + dx;
+            indices.extend_from_slice(&[corner(0, 0), corner(0, 1), corner(1, 0)]);
+            indices.extend_from_slice(&[corner(1, 0), corner(0, 1), corner(1, 1)]);
+        }
+        (vertices, indices)
+    }
+
+    /// Triangles as sorted corner lists, sorted, for comparing meshes up to reordering
+    fn triangles(indices: &[u16]) -> Vec<[u16; 3]> {
+        let mut triangles: Vec<[u16; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let mut triangle = [triangle[0], triangle[1], triangle[2]];
+                triangle.sort_unstable();
+                triangle
+            })
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    /// Average number of cache misses per triangle on a FIFO cache
+    fn acmr(indices: &[u16], cache_size: usize) -> f32 {
+        let mut cache = std::collections::VecDeque::new();
+        let mut misses = 0;
+        for index in indices {
+            if !cache.contains(index) {
+                misses += 1;
+                cache.push_back(*index);
+                if cache.len() > cache_size {
+                    cache.pop_front();
+                }
+            }
+        }
+        misses as f32 / (indices.len() / 3) as f32
+    }
+
+    #[test]
+    fn vertex_cache_keeps_triangles_and_reduces_misses() {
+        let (vertices, indices) = grid(20, |quad| quad * 7 % 400);
+        let optimized = optimize_vertex_cache(&indices, vertices.len(), VERTEX_CACHE_SIZE).unwrap();
+        assert_eq!(triangles(&optimized), triangles(&indices));
+        let (before, after) = (
+            acmr(&indices, VERTEX_CACHE_SIZE),
+            acmr(&optimized, VERTEX_CACHE_SIZE),
+        );
+        assert!(
+            after < before * 0.75,
+            "ACMR went from {} to {}",
+            before,
+            after
+        );
+    }
+
+    #[test]
+    fn vertex_fetch_orders_by_first_use_and_drops_unused() {
+        let vertices: Vec<Vertex> = (0..5).map(|x| vertex(x as f32, 0.0)).collect();
+        let (new_vertices, new_indices) =
+            optimize_vertex_fetch(&vertices, &[3, 1, 4, 4, 1, 3]).unwrap();
+        assert_eq!(new_indices, [0, 1, 2, 2, 1, 0]);
+        let positions: Vec<f32> = new_vertices.iter().map(|vertex| vertex.pos[0]).collect();
+        assert_eq!(positions, [3.0, 1.0, 4.0]);
+    }
+
+    #[test]
+    fn simplify_merges_cells_and_drops_degenerate_triangles() {
+        let (vertices, indices) = grid(4, |quad| quad);
+        let (new_vertices, new_indices) = simplify(&vertices, &indices, 2.0).unwrap();
+        // Coordinates 0..=4 fall into cells 0, 0, 1, 1, 2 along each axis
+        assert_eq!(new_vertices.len(), 9);
+        assert!(new_indices.len() < indices.len());
+        for triangle in new_indices.chunks_exact(3) {
+            assert!(
+                triangle[0] != triangle[1]
+                    && triangle[1] != triangle[2]
+                    && triangle[0] != triangle[2]
+            );
+            assert!(triangle
+                .iter()
+                .all(|&index| (index as usize) < new_vertices.len()));
+        }
+        // The first cell averages x and z over 0 and 1
+        assert_eq!(new_vertices[0].pos, [0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_rejected() {
+        let vertices = vec![Vertex::default(); 3];
+        assert!(optimize_vertex_cache(&[0, 1, 3], 3, VERTEX_CACHE_SIZE).is_err());
+        assert!(optimize_vertex_fetch(&vertices, &[0, 1, 3]).is_err());
+        assert!(simplify(&vertices, &[0, 1, 3], 1.0).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+/// Maximum unique vertices per meshlet, matching common mesh shader limits
+pub const MESHLET_MAX_VERTICES: usize = 64;
+/// Maximum triangles per meshlet, matching common mesh shader limits
+pub const MESHLET_MAX_TRIANGLES: usize = 124;
+
+/// A small cluster of triangles, the unit of work of a mesh shader workgroup
+#[derive(Debug, Clone, Default)]
+pub struct Meshlet {
+    /// Indices into the source vertex buffer
+    pub vertices: Vec<u16>,
+    /// Triangles as indices into `vertices`
+    pub triangles: Vec<[u8; 3]>,
+}
+
+/// Greedily split a triangle list into meshlets, in index order.
+pub fn build_meshlets(indices: &[u16]) -> Vec<Meshlet> {
+    let mut meshlets = Vec::new();
+    let mut current = Meshlet::default();
+
+    for triangle in indices.chunks_exact(3) {
+        let new_vertices = triangle
+            .iter()
+            .filter(|i| !current.vertices.contains(i))
+            .count();
+        if current.vertices.len() + new_vertices > MESHLET_MAX_VERTICES
+            || current.triangles.len() + 1 > MESHLET_MAX_TRIANGLES
+        {
+            meshlets.push(std::mem::take(&mut current));
+        }
+
+        let mut local = [0u8; 3];
+        for (slot, &index) in local.iter_mut().zip(triangle) {
+            let position = match current.vertices.iter().position(|&v| v == index) {
+                Some(position) => position,
+                None => {
+                    current.vertices.push(index);
+                    current.vertices.len() - 1
+                }
+            };
+            *slot = position as u8;
+        }
+        current.triangles.push(local);
+    }
+
+    if !current.triangles.is_empty() {
+        meshlets.push(current);
+    }
+
+    meshlets
+}
@@ -0,0 +1,286 @@
+use crate::allocated_buffer::AllocatedBuffer;
+use crate::engine::MaterialId;
+use crate::hardware_query::HardwareSelection;
+use crate::memory::{self, Allocation, MemoryAllocator, MemoryLocation};
+use crate::pipeline::{Material, OutlineShaders, Pipeline};
+use anyhow::Result;
+use erupt::{vk1_0 as vk, DeviceLoader};
+use std::collections::HashMap;
+
+/// Color and depth images rendered outside of the swapchain, then copied into a host-visible
+/// buffer. Uses the swapchain's formats, but has pipelines of its own, so it can be drawn to
+/// whether or not a swapchain exists.
+pub(crate) struct OffscreenTarget {
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    pub extent: vk::Extent2D,
+    pub pipelines: HashMap<MaterialId, Pipeline>,
+    color_image: vk::Image,
+    color_image_mem: Option<Allocation>,
+    color_image_view: vk::ImageView,
+    depth_image: vk::Image,
+    depth_image_mem: Option<Allocation>,
+    depth_image_view: vk::ImageView,
+    readback: AllocatedBuffer<u8>,
+    freed: bool,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: &DeviceLoader,
+        hardware: &HardwareSelection,
+        allocator: &mut dyn MemoryAllocator,
+        extent: vk::Extent2D,
+    ) -> Result<Self> {
+        let (color_image, color_image_mem, color_image_view) = create_image(
+            device,
+            allocator,
+            extent,
+            hardware.format.format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+        let (depth_image, depth_image_mem, depth_image_view) = create_image(
+            device,
+            allocator,
+            extent,
+            hardware.depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+        )?;
+
+        // Same attachments as the swapchain's render pass, except the color image is left ready
+        // to be copied from
+        let attachments = [
+            vk::AttachmentDescriptionBuilder::new()
+                .format(hardware.format.format)
+                .samples(vk::SampleCountFlagBits::_1)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            vk::AttachmentDescriptionBuilder::new()
+                .format(hardware.depth_format)
+                .samples(vk::SampleCountFlagBits::_1)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(vk::AttachmentLoadOp::CLEAR)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+        ];
+
+        let color_attachment_refs = [vk::AttachmentReferenceBuilder::new()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+
+        let depth_attachment_ref = vk::AttachmentReferenceBuilder::new()
+            .attachment(1)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .build();
+
+        let subpasses = [vk::SubpassDescriptionBuilder::new()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)
+            .depth_stencil_attachment(&depth_attachment_ref)];
+
+        // The copy out of the color image waits for rendering to finish
+        let dependencies = [vk::SubpassDependencyBuilder::new()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)];
+
+        let create_info = vk::RenderPassCreateInfoBuilder::new()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&dependencies);
+
+        let render_pass =
+            unsafe { device.create_render_pass(&create_info, None, None) }.result()?;
+
+        let views = [color_image_view, depth_image_view];
+        let create_info = vk::FramebufferCreateInfoBuilder::new()
+            .render_pass(render_pass)
+            .attachments(&views)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer =
+            unsafe { device.create_framebuffer(&create_info, None, None) }.result()?;
+
+        // Swapchain formats are 4 bytes per pixel
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let readback = AllocatedBuffer::new(
+            extent.width as usize * extent.height as usize * 4,
+            create_info,
+            allocator,
+            device,
+        )?;
+
+        Ok(Self {
+            render_pass,
+            framebuffer,
+            extent,
+            pipelines: Default::default(),
+            color_image,
+            color_image_mem: Some(color_image_mem),
+            color_image_view,
+            depth_image,
+            depth_image_mem: Some(depth_image_mem),
+            depth_image_view,
+            readback,
+            freed: false,
+        })
+    }
+
+    /// Create pipelines for this target's render pass, as `Swapchain::add_pipelines` does
+    pub fn add_pipelines(
+        &mut self,
+        device: &DeviceLoader,
+        pipeline_cache: vk::PipelineCache,
+        outline_shaders: &OutlineShaders,
+        descriptor_set_layouts: &[vk::DescriptorSetLayout],
+        materials: &[(MaterialId, &Material)],
+    ) -> Result<()> {
+        let pipelines = Pipeline::new_batch(
+            device,
+            pipeline_cache,
+            &materials.iter().map(|(_, m)| *m).collect::<Vec<_>>(),
+            outline_shaders,
+            self.render_pass,
+            descriptor_set_layouts,
+            self.extent,
+        )?;
+        for ((id, _), pipeline) in materials.iter().zip(pipelines) {
+            self.pipelines.insert(*id, pipeline);
+        }
+        Ok(())
+    }
+
+    /// Record a copy of the rendered color image into the readback buffer, after the render pass
+    pub unsafe fn record_readback(&self, device: &DeviceLoader, command_buffer: vk::CommandBuffer) {
+        let region = vk::BufferImageCopyBuilder::new()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(
+                vk::ImageSubresourceLayersBuilder::new()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            });
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            self.color_image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            self.readback.buffer,
+            &[region],
+        );
+    }
+
+    /// Pixels of the color image in the swapchain's format, row by row. Only valid once the
+    /// readback has finished executing.
+    pub fn read(&self, device: &DeviceLoader) -> Result<Vec<u8>> {
+        let mut pixels = self
+            .readback
+            .allocation
+            .as_ref()
+            .expect("Use-after-free")
+            .read(device)?;
+        pixels.truncate(self.readback.size() as usize);
+        Ok(pixels)
+    }
+
+    pub fn free(
+        &mut self,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+    ) -> Result<()> {
+        self.readback.free(device, allocator)?;
+        for pipeline in self.pipelines.values_mut() {
+            pipeline.free(device);
+        }
+        unsafe {
+            device.destroy_framebuffer(Some(self.framebuffer), None);
+            device.destroy_render_pass(Some(self.render_pass), None);
+            device.destroy_image_view(Some(self.color_image_view), None);
+            device.destroy_image_view(Some(self.depth_image_view), None);
+            device.destroy_image(Some(self.color_image), None);
+            device.destroy_image(Some(self.depth_image), None);
+        }
+        allocator.free(device, self.color_image_mem.take().unwrap());
+        allocator.free(device, self.depth_image_mem.take().unwrap());
+        self.freed = true;
+        Ok(())
+    }
+}
+
+impl Drop for OffscreenTarget {
+    fn drop(&mut self) {
+        if !self.freed {
+            panic!("OffscreenTarget dropped before its free() method was called!");
+        }
+    }
+}
+
+fn create_image(
+    device: &DeviceLoader,
+    allocator: &mut dyn MemoryAllocator,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    aspect_mask: vk::ImageAspectFlags,
+) -> Result<(vk::Image, Allocation, vk::ImageView)> {
+    let create_info = vk::ImageCreateInfoBuilder::new()
+        .image_type(vk::ImageType::_2D)
+        .extent(
+            vk::Extent3DBuilder::new()
+                .width(extent.width)
+                .height(extent.height)
+                .depth(1)
+                .build(),
+        )
+        .mip_levels(1)
+        .array_layers(1)
+        .format(format)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(usage)
+        .samples(vk::SampleCountFlagBits::_1)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    let image = unsafe { device.create_image(&create_info, None, None) }.result()?;
+
+    let allocation = memory::allocate_image(allocator, device, image, MemoryLocation::GpuOnly)?;
+
+    let create_info = vk::ImageViewCreateInfoBuilder::new()
+        .image(image)
+        .view_type(vk::ImageViewType::_2D)
+        .format(format)
+        .subresource_range(
+            vk::ImageSubresourceRangeBuilder::new()
+                .aspect_mask(aspect_mask)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1)
+                .build(),
+        );
+    let view = unsafe { device.create_image_view(&create_info, None, None) }.result()?;
+
+    Ok((image, allocation, view))
+}
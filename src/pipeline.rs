@@ -1,13 +1,23 @@
-use crate::vertex::Vertex;
+use crate::debug_name::set_debug_name;
+use crate::engine::{ObjectPushConstants, ALPHA_TESTED_CONSTANT, FEATURE_CONSTANT_BASE};
+use crate::shader_interface::{self, Stage};
+use crate::vertex::VertexFormat;
 use anyhow::Result;
 use erupt::{utils, vk1_0 as vk, DeviceLoader};
-use crate::engine::RealtimeUBO;
 use std::ffi::CString;
 
+const OUTLINE_VERT: &[u8] = include_bytes!("../shaders/outline.vert.spv");
+const OUTLINE_FRAG: &[u8] = include_bytes!("../shaders/outline.frag.spv");
+
 /// Represents a backing pipeline that can render an object
 /// with the material from which it was created.
 pub struct Pipeline {
     pub pipeline: vk::Pipeline,
+    /// Draws a flat-colored copy of an object with the engine's outline shaders, wherever the
+    /// stencil wasn't marked by `pipeline`. Absent for materials without vertex input.
+    pub outline_pipeline: Option<vk::Pipeline>,
+    /// Writes only depth, if the material has `depth_prepass` set
+    pub prepass_pipeline: Option<vk::Pipeline>,
     pub pipeline_layout: vk::PipelineLayout,
     freed: bool,
 }
@@ -15,15 +25,125 @@ pub struct Pipeline {
 /// Represents a set of drawing parameters to be turned into a pipeline
 pub struct Material {
     draw_type: DrawType,
+    options: MaterialOptions,
     vertex: vk::ShaderModule,
     fragment: vk::ShaderModule,
+    /// Layout of set 2, present if the material has its own uniform block
+    descriptor_set_layout: Option<vk::DescriptorSetLayout>,
     freed: bool,
 }
 
+/// The engine's own shaders for the outline pass, shared by every material so that
+/// highlighting works whatever the material's shaders do
+pub struct OutlineShaders {
+    vertex: vk::ShaderModule,
+    fragment: vk::ShaderModule,
+    freed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DrawType {
     Triangles,
     Lines,
     Points,
+    /// Points read from a point cloud's storage buffer by `gl_VertexIndex`, with no vertex input
+    PointCloud,
+    /// Alpha-blended gaussian splats, six vertices each, read from a splat cloud's storage
+    /// buffers in back-to-front order. Drawn after all opaque geometry without writing depth.
+    Splats,
+}
+
+/// Optional per-material pipeline settings
+#[derive(Debug, Clone, Default)]
+pub struct MaterialOptions {
+    /// Shown in error messages, and given to the material's Vulkan objects for debugging tools
+    pub name: Option<String>,
+    /// Specialization constants applied to both shader stages, keyed by `constant_id`
+    pub specialization: Vec<(u32, SpecConstant)>,
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    /// Offsets depth values to avoid z-fighting, e.g. for decals or lines drawn over surfaces
+    pub depth_bias: Option<DepthBias>,
+    /// Rasterized width of lines, clamped to what the device supports. Defaults to 1.
+    pub line_width: Option<f32>,
+    /// Size in bytes of the material's own uniform block, bound at set 2, binding 0 of both
+    /// shader stages. Zero for none.
+    pub uniform_bytes: usize,
+    /// Number of storage buffers the material's shaders read, at set 2, bindings 1 onwards.
+    /// Each must be given a buffer with `Engine::bind_storage_buffer` before the material is
+    /// drawn.
+    pub storage_buffers: u32,
+    /// GPU layout of the vertices of objects drawn with this material
+    pub vertex_format: VertexFormat,
+    /// Lay down the depth of objects drawn with this material in a prepass without a fragment
+    /// stage, so that the fragment shader later runs only for visible fragments. Pays off for
+    /// expensive fragment shaders on fill-bound GPUs. Not available for point or splat clouds.
+    pub depth_prepass: bool,
+    /// Discard fragments with alpha below this, e.g. for foliage, fences or icons. Shaders see
+    /// it as the float specialization constant `ALPHA_CUTOFF_CONSTANT`, with the alpha-tested
+    /// feature flag set, and must do the discard themselves (`ALPHA_TEST` in
+    /// `shaders/include/engine.glsl`). The depth prepass runs the fragment shader of such
+    /// materials, so that discarded fragments write no depth.
+    pub alpha_cutoff: Option<f32>,
+}
+
+/// Polygon offset applied to fragment depth. Negative factors pull geometry towards the camera.
+#[derive(Debug, Clone, Copy)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub slope_factor: f32,
+}
+
+/// Which faces are discarded during rasterization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CullMode {
+    /// Double-sided; nothing is culled
+    None,
+    Front,
+    Back,
+}
+
+/// Winding order of front-facing triangles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+impl Default for CullMode {
+    fn default() -> Self {
+        CullMode::Back
+    }
+}
+
+impl Default for FrontFace {
+    fn default() -> Self {
+        FrontFace::CounterClockwise
+    }
+}
+
+/// `constant_id` of a material's alpha cutoff, see `MaterialOptions::alpha_cutoff`
+pub const ALPHA_CUTOFF_CONSTANT: u32 = FEATURE_CONSTANT_BASE + 4;
+
+/// Value of a SPIR-V specialization constant
+#[derive(Debug, Clone, Copy)]
+pub enum SpecConstant {
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Float(f32),
+}
+
+impl SpecConstant {
+    fn bytes(self) -> [u8; 4] {
+        match self {
+            SpecConstant::Bool(b) => (b as u32).to_ne_bytes(),
+            SpecConstant::Int(i) => i.to_ne_bytes(),
+            SpecConstant::UInt(u) => u.to_ne_bytes(),
+            SpecConstant::Float(f) => f.to_ne_bytes(),
+        }
+    }
 }
 
 impl Material {
@@ -32,17 +152,163 @@ impl Material {
         vertex_src: &[u8],
         fragment_src: &[u8],
         draw_type: DrawType,
+        mut options: MaterialOptions,
+        user_uniform: bool,
     ) -> Result<Self> {
+        anyhow::ensure!(
+            !options.depth_prepass || !matches!(draw_type, DrawType::PointCloud | DrawType::Splats),
+            "Point and splat clouds can't use a depth prepass"
+        );
+
+        if let Some(cutoff) = options.alpha_cutoff {
+            anyhow::ensure!(
+                (0.0..=1.0).contains(&cutoff),
+                "Alpha cutoff must be between 0 and 1, got {}",
+                cutoff
+            );
+            let alpha_constants = [ALPHA_TESTED_CONSTANT, ALPHA_CUTOFF_CONSTANT];
+            options
+                .specialization
+                .retain(|(id, _)| !alpha_constants.contains(id));
+            options
+                .specialization
+                .push((ALPHA_TESTED_CONSTANT, SpecConstant::Bool(true)));
+            options
+                .specialization
+                .push((ALPHA_CUTOFF_CONSTANT, SpecConstant::Float(cutoff)));
+        }
+
         let vert_decoded = utils::decode_spv(vertex_src)?;
+        let frag_decoded = utils::decode_spv(fragment_src)?;
+        for (code, stage) in &[
+            (&vert_decoded, Stage::Vertex),
+            (&frag_decoded, Stage::Fragment),
+        ] {
+            shader_interface::validate(code, *stage, draw_type, &options, user_uniform)?;
+        }
+
         let create_info = vk::ShaderModuleCreateInfoBuilder::new().code(&vert_decoded);
         let vertex = unsafe { device.create_shader_module(&create_info, None, None) }.result()?;
 
-        let frag_decoded = utils::decode_spv(fragment_src)?;
         let create_info = vk::ShaderModuleCreateInfoBuilder::new().code(&frag_decoded);
         let fragment = unsafe { device.create_shader_module(&create_info, None, None) }.result()?;
 
+        if let Some(name) = &options.name {
+            set_debug_name(
+                device,
+                vk::ObjectType::SHADER_MODULE,
+                vertex.0,
+                &format!("{} (vertex)", name),
+            )?;
+            set_debug_name(
+                device,
+                vk::ObjectType::SHADER_MODULE,
+                fragment.0,
+                &format!("{} (fragment)", name),
+            )?;
+        }
+
+        let descriptor_set_layout = if options.uniform_bytes > 0 || options.storage_buffers > 0 {
+            let mut bindings = Vec::new();
+            if options.uniform_bytes > 0 {
+                bindings.push(
+                    vk::DescriptorSetLayoutBindingBuilder::new()
+                        .binding(0)
+                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                        .descriptor_count(1)
+                        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+                );
+            }
+            for binding in 1..=options.storage_buffers {
+                bindings.push(
+                    vk::DescriptorSetLayoutBindingBuilder::new()
+                        .binding(binding)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .descriptor_count(1)
+                        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+                );
+            }
+            let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+            Some(unsafe { device.create_descriptor_set_layout(&create_info, None, None) }.result()?)
+        } else {
+            None
+        };
+
         Ok(Self {
             draw_type,
+            options,
+            vertex,
+            fragment,
+            descriptor_set_layout,
+            freed: false,
+        })
+    }
+
+    pub fn draw_type(&self) -> DrawType {
+        self.draw_type
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.options.name.as_deref()
+    }
+
+    pub fn uniform_bytes(&self) -> usize {
+        self.options.uniform_bytes
+    }
+
+    pub fn storage_buffers(&self) -> u32 {
+        self.options.storage_buffers
+    }
+
+    pub fn vertex_format(&self) -> VertexFormat {
+        self.options.vertex_format
+    }
+
+    pub fn descriptor_set_layout(&self) -> Option<vk::DescriptorSetLayout> {
+        self.descriptor_set_layout
+    }
+
+    /// Create a material with the same settings as this one but different shaders
+    pub fn with_shaders(
+        &self,
+        device: &DeviceLoader,
+        vertex_src: &[u8],
+        fragment_src: &[u8],
+        user_uniform: bool,
+    ) -> Result<Self> {
+        Self::new(
+            device,
+            vertex_src,
+            fragment_src,
+            self.draw_type,
+            self.options.clone(),
+            user_uniform,
+        )
+    }
+
+    pub fn free(&mut self, device: &DeviceLoader) {
+        unsafe {
+            device.destroy_shader_module(Some(self.fragment), None);
+            device.destroy_shader_module(Some(self.vertex), None);
+            if let Some(layout) = self.descriptor_set_layout {
+                device.destroy_descriptor_set_layout(Some(layout), None);
+            }
+        }
+        self.freed = true;
+    }
+}
+
+impl OutlineShaders {
+    pub fn new(device: &DeviceLoader) -> Result<Self> {
+        let vert_decoded = utils::decode_spv(OUTLINE_VERT)?;
+        let create_info = vk::ShaderModuleCreateInfoBuilder::new().code(&vert_decoded);
+        let vertex = unsafe { device.create_shader_module(&create_info, None, None) }.result()?;
+
+        let frag_decoded = utils::decode_spv(OUTLINE_FRAG)?;
+        let create_info = vk::ShaderModuleCreateInfoBuilder::new().code(&frag_decoded);
+        let fragment = unsafe { device.create_shader_module(&create_info, None, None) }.result()?;
+
+        Ok(Self {
             vertex,
             fragment,
             freed: false,
@@ -59,29 +325,42 @@ impl Material {
 }
 
 impl Pipeline {
-    pub fn new(
+    /// Create the pipelines for several materials with a single `vkCreateGraphicsPipelines`
+    /// call, in the same order as `materials`
+    pub fn new_batch(
         device: &DeviceLoader,
-        material: &Material,
+        pipeline_cache: vk::PipelineCache,
+        materials: &[&Material],
+        outline_shaders: &OutlineShaders,
         render_pass: vk::RenderPass,
-        descriptor_set_layout: vk::DescriptorSetLayout,
+        descriptor_set_layouts: &[vk::DescriptorSetLayout],
         extent: vk::Extent2D,
-    ) -> Result<Self> {
-        let attribute_descriptions = Vertex::get_attribute_descriptions();
-        let binding_descriptions = [Vertex::binding_description()];
+    ) -> Result<Vec<Self>> {
+        if materials.is_empty() {
+            return Ok(Vec::new());
+        }
 
-        let vertex_input = vk::PipelineVertexInputStateCreateInfoBuilder::new()
-            .vertex_attribute_descriptions(&attribute_descriptions[..])
-            .vertex_binding_descriptions(&binding_descriptions);
+        // State shared by every material
+        let full_attributes = VertexFormat::Full.attribute_descriptions();
+        let full_bindings = [VertexFormat::Full.binding_description()];
+        let full_input = vk::PipelineVertexInputStateCreateInfoBuilder::new()
+            .vertex_attribute_descriptions(&full_attributes[..])
+            .vertex_binding_descriptions(&full_bindings);
 
-        let draw_type = match material.draw_type {
-            DrawType::Triangles => vk::PrimitiveTopology::TRIANGLE_LIST,
-            DrawType::Points => vk::PrimitiveTopology::POINT_LIST,
-            DrawType::Lines => vk::PrimitiveTopology::LINE_LIST,
-        };
+        let compact_attributes = VertexFormat::Compact.attribute_descriptions();
+        let compact_bindings = [VertexFormat::Compact.binding_description()];
+        let compact_input = vk::PipelineVertexInputStateCreateInfoBuilder::new()
+            .vertex_attribute_descriptions(&compact_attributes[..])
+            .vertex_binding_descriptions(&compact_bindings);
 
-        let input_assembly = vk::PipelineInputAssemblyStateCreateInfoBuilder::new()
-            .topology(draw_type)
-            .primitive_restart_enable(false);
+        let position_attributes = VertexFormat::Position.attribute_descriptions();
+        let position_bindings = [VertexFormat::Position.binding_description()];
+        let position_input = vk::PipelineVertexInputStateCreateInfoBuilder::new()
+            .vertex_attribute_descriptions(&position_attributes[..])
+            .vertex_binding_descriptions(&position_bindings);
+
+        // Point clouds and splats read their data from storage buffers instead
+        let pulled_input = vk::PipelineVertexInputStateCreateInfoBuilder::new();
 
         let viewports = [vk::ViewportBuilder::new()
             .x(0.0)
@@ -99,93 +378,379 @@ impl Pipeline {
             .viewports(&viewports)
             .scissors(&scissors);
 
-        let rasterizer = vk::PipelineRasterizationStateCreateInfoBuilder::new()
-            .depth_clamp_enable(false)
-            .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .line_width(1.0)
-            .cull_mode(vk::CullModeFlags::BACK)
-            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-            .depth_clamp_enable(false);
-
         let multisampling = vk::PipelineMultisampleStateCreateInfoBuilder::new()
             .sample_shading_enable(false)
             .rasterization_samples(vk::SampleCountFlagBits::_1);
 
-        let color_blend_attachments = [vk::PipelineColorBlendAttachmentStateBuilder::new()
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                    | vk::ColorComponentFlags::G
-                    | vk::ColorComponentFlags::B
-                    | vk::ColorComponentFlags::A,
-            )
+        let color_write_mask = vk::ColorComponentFlags::R
+            | vk::ColorComponentFlags::G
+            | vk::ColorComponentFlags::B
+            | vk::ColorComponentFlags::A;
+        let opaque_attachments = [vk::PipelineColorBlendAttachmentStateBuilder::new()
+            .color_write_mask(color_write_mask)
             .blend_enable(false)];
-        let color_blending = vk::PipelineColorBlendStateCreateInfoBuilder::new()
+        let opaque_blending = vk::PipelineColorBlendStateCreateInfoBuilder::new()
             .logic_op_enable(false)
-            .attachments(&color_blend_attachments);
+            .attachments(&opaque_attachments);
 
-        let entry_point = CString::new("main")?;
+        // The depth prepass has no fragment stage unless it discards alpha-tested fragments,
+        // and must leave color untouched
+        let depth_only_attachments = [vk::PipelineColorBlendAttachmentStateBuilder::new()
+            .color_write_mask(vk::ColorComponentFlags::empty())
+            .blend_enable(false)];
+        let depth_only_blending = vk::PipelineColorBlendStateCreateInfoBuilder::new()
+            .logic_op_enable(false)
+            .attachments(&depth_only_attachments);
 
-        let shader_stages = [
-            vk::PipelineShaderStageCreateInfoBuilder::new()
-                .stage(vk::ShaderStageFlagBits::VERTEX)
-                .module(material.vertex)
-                .name(&entry_point),
-            vk::PipelineShaderStageCreateInfoBuilder::new()
-                .stage(vk::ShaderStageFlagBits::FRAGMENT)
-                .module(material.fragment)
-                .name(&entry_point),
-        ];
+        let blended_attachments = [vk::PipelineColorBlendAttachmentStateBuilder::new()
+            .color_write_mask(color_write_mask)
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .alpha_blend_op(vk::BlendOp::ADD)];
+        let blended_blending = vk::PipelineColorBlendStateCreateInfoBuilder::new()
+            .logic_op_enable(false)
+            .attachments(&blended_attachments);
 
-        let descriptor_set_layouts = [descriptor_set_layout];
+        let entry_point = CString::new("main")?;
 
         let push_constant_ranges = [
             vk::PushConstantRangeBuilder::new()
                 .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
                 .offset(0)
-                .size(std::mem::size_of::<RealtimeUBO>() as u32),
+                .size(std::mem::size_of::<ObjectPushConstants>() as u32),
         ];
 
-        let create_info = vk::PipelineLayoutCreateInfoBuilder::new()
-            .push_constant_ranges(&push_constant_ranges)
-            .set_layouts(&descriptor_set_layouts);
-
-        let pipeline_layout =
-            unsafe { device.create_pipeline_layout(&create_info, None, None) }.result()?;
+        // Every draw writes its stencil reference, so highlighted objects can be outlined later
+        let stencil_write = vk::StencilOpStateBuilder::new()
+            .fail_op(vk::StencilOp::KEEP)
+            .pass_op(vk::StencilOp::REPLACE)
+            .depth_fail_op(vk::StencilOp::KEEP)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .compare_mask(0xFF)
+            .write_mask(0xFF)
+            .build();
 
         let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfoBuilder::new()
             .depth_test_enable(true)
             .depth_write_enable(true)
             .depth_compare_op(vk::CompareOp::LESS)// TODO: Play with this! For fun!
             .depth_bounds_test_enable(false)
+            .stencil_test_enable(true)
+            .front(stencil_write)
+            .back(stencil_write);
+
+        // Blended geometry is depth tested against opaque geometry, but doesn't occlude
+        let blended_depth_stencil_state = depth_stencil_state.clone().depth_write_enable(false);
+
+        // Materials with a depth prepass write depth there, then shade only the fragments
+        // which matched it
+        let prepass_depth_stencil_state = vk::PipelineDepthStencilStateCreateInfoBuilder::new()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS)
+            .depth_bounds_test_enable(false)
             .stencil_test_enable(false);
+        let after_prepass_depth_stencil_state = depth_stencil_state
+            .clone()
+            .depth_write_enable(false)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
 
-        let create_info = vk::GraphicsPipelineCreateInfoBuilder::new()
-            .stages(&shader_stages)
-            .vertex_input_state(&vertex_input)
-            .input_assembly_state(&input_assembly)
-            .viewport_state(&viewport_state)
-            .rasterization_state(&rasterizer)
-            .multisample_state(&multisampling)
-            .color_blend_state(&color_blending)
-            .depth_stencil_state(&depth_stencil_state)
-            .layout(pipeline_layout)
-            .render_pass(render_pass)
-            .subpass(0);
-
-        let pipeline =
-            unsafe { device.create_graphics_pipelines(None, &[create_info], None) }.result()?[0];
+        // The outline is drawn outside of the silhouettes of highlighted objects, and hidden by
+        // whatever is in front of it like the object itself would be
+        let stencil_outline = vk::StencilOpStateBuilder::new()
+            .fail_op(vk::StencilOp::KEEP)
+            .pass_op(vk::StencilOp::KEEP)
+            .depth_fail_op(vk::StencilOp::KEEP)
+            .compare_op(vk::CompareOp::NOT_EQUAL)
+            .compare_mask(0xFF)
+            .write_mask(0x00)
+            .build();
 
-        Ok(Self {
-            pipeline,
-            pipeline_layout,
-            freed: false,
-        })
+        let outline_depth_stencil_state = vk::PipelineDepthStencilStateCreateInfoBuilder::new()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(true)
+            .front(stencil_outline)
+            .back(stencil_outline);
+
+        // The viewport is dynamic so objects can remap their depth range, and along with the
+        // scissor so pipelines can draw to targets other than the swapchain
+        let dynamic_states = [
+            vk::DynamicState::STENCIL_REFERENCE,
+            vk::DynamicState::VIEWPORT,
+            vk::DynamicState::SCISSOR,
+        ];
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfoBuilder::new().dynamic_states(&dynamic_states);
+
+        // Per-material state. Each list is complete before anything borrows from it.
+        let spec_data = materials
+            .iter()
+            .map(|material| {
+                material
+                    .options
+                    .specialization
+                    .iter()
+                    .flat_map(|(_, value)| value.bytes().to_vec())
+                    .collect::<Vec<u8>>()
+            })
+            .collect::<Vec<_>>();
+        let spec_entries = materials
+            .iter()
+            .map(|material| {
+                material
+                    .options
+                    .specialization
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (id, _))| {
+                        vk::SpecializationMapEntryBuilder::new()
+                            .constant_id(*id)
+                            .offset(i as u32 * 4)
+                            .size(4)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let spec_infos = spec_data
+            .iter()
+            .zip(&spec_entries)
+            .map(|(data, entries)| {
+                vk::SpecializationInfoBuilder::new()
+                    .map_entries(entries)
+                    .data(data)
+            })
+            .collect::<Vec<_>>();
+
+        let shader_stages = materials
+            .iter()
+            .zip(&spec_infos)
+            .map(|(material, spec_info)| {
+                [
+                    vk::PipelineShaderStageCreateInfoBuilder::new()
+                        .stage(vk::ShaderStageFlagBits::VERTEX)
+                        .module(material.vertex)
+                        .name(&entry_point)
+                        .specialization_info(spec_info),
+                    vk::PipelineShaderStageCreateInfoBuilder::new()
+                        .stage(vk::ShaderStageFlagBits::FRAGMENT)
+                        .module(material.fragment)
+                        .name(&entry_point)
+                        .specialization_info(spec_info),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let outline_stages = [
+            vk::PipelineShaderStageCreateInfoBuilder::new()
+                .stage(vk::ShaderStageFlagBits::VERTEX)
+                .module(outline_shaders.vertex)
+                .name(&entry_point),
+            vk::PipelineShaderStageCreateInfoBuilder::new()
+                .stage(vk::ShaderStageFlagBits::FRAGMENT)
+                .module(outline_shaders.fragment)
+                .name(&entry_point),
+        ];
+
+        let input_assemblies = materials
+            .iter()
+            .map(|material| {
+                let topology = match material.draw_type {
+                    DrawType::Triangles | DrawType::Splats => {
+                        vk::PrimitiveTopology::TRIANGLE_LIST
+                    }
+                    DrawType::Points | DrawType::PointCloud => vk::PrimitiveTopology::POINT_LIST,
+                    DrawType::Lines => vk::PrimitiveTopology::LINE_LIST,
+                };
+                vk::PipelineInputAssemblyStateCreateInfoBuilder::new()
+                    .topology(topology)
+                    .primitive_restart_enable(false)
+            })
+            .collect::<Vec<_>>();
+
+        let rasterizers = materials
+            .iter()
+            .map(|material| {
+                let cull_mode = match material.options.cull_mode {
+                    _ if material.draw_type == DrawType::Splats => vk::CullModeFlags::NONE,
+                    CullMode::None => vk::CullModeFlags::NONE,
+                    CullMode::Front => vk::CullModeFlags::FRONT,
+                    CullMode::Back => vk::CullModeFlags::BACK,
+                };
+
+                let front_face = match material.options.front_face {
+                    FrontFace::CounterClockwise => vk::FrontFace::COUNTER_CLOCKWISE,
+                    FrontFace::Clockwise => vk::FrontFace::CLOCKWISE,
+                };
+
+                let depth_bias = material.options.depth_bias;
+
+                vk::PipelineRasterizationStateCreateInfoBuilder::new()
+                    .depth_clamp_enable(false)
+                    .rasterizer_discard_enable(false)
+                    .polygon_mode(vk::PolygonMode::FILL)
+                    .line_width(material.options.line_width.unwrap_or(1.0))
+                    .cull_mode(cull_mode)
+                    .front_face(front_face)
+                    .depth_bias_enable(depth_bias.is_some())
+                    .depth_bias_constant_factor(
+                        depth_bias.map(|b| b.constant_factor).unwrap_or(0.0),
+                    )
+                    .depth_bias_slope_factor(depth_bias.map(|b| b.slope_factor).unwrap_or(0.0))
+            })
+            .collect::<Vec<_>>();
+
+        let pipeline_layouts = materials
+            .iter()
+            .map(|material| {
+                let mut set_layouts = descriptor_set_layouts.to_vec();
+                set_layouts.extend(material.descriptor_set_layout);
+                let create_info = vk::PipelineLayoutCreateInfoBuilder::new()
+                    .push_constant_ranges(&push_constant_ranges)
+                    .set_layouts(&set_layouts);
+                Ok(unsafe { device.create_pipeline_layout(&create_info, None, None) }.result()?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Each material gets its pipeline. Outline pipelines follow all of those, then prepass
+        // pipelines, each in the same order as their materials.
+        let mut create_infos = Vec::with_capacity(materials.len() * 2);
+        let mut outline_create_infos = Vec::new();
+        let mut prepass_create_infos = Vec::new();
+        for (i, material) in materials.iter().enumerate() {
+            let blended = material.draw_type == DrawType::Splats;
+            let pulled = matches!(material.draw_type, DrawType::PointCloud | DrawType::Splats);
+            let vertex_input = match material.draw_type {
+                DrawType::PointCloud | DrawType::Splats => &pulled_input,
+                _ => match material.options.vertex_format {
+                    VertexFormat::Full => &full_input,
+                    VertexFormat::Compact => &compact_input,
+                    VertexFormat::Position => &position_input,
+                },
+            };
+
+            let create_info = vk::GraphicsPipelineCreateInfoBuilder::new()
+                .stages(&shader_stages[i])
+                .vertex_input_state(vertex_input)
+                .input_assembly_state(&input_assemblies[i])
+                .viewport_state(&viewport_state)
+                .rasterization_state(&rasterizers[i])
+                .multisample_state(&multisampling)
+                .color_blend_state(if blended {
+                    &blended_blending
+                } else {
+                    &opaque_blending
+                })
+                .depth_stencil_state(if blended {
+                    &blended_depth_stencil_state
+                } else if material.options.depth_prepass {
+                    &after_prepass_depth_stencil_state
+                } else {
+                    &depth_stencil_state
+                })
+                .dynamic_state(&dynamic_state)
+                .layout(pipeline_layouts[i])
+                .render_pass(render_pass)
+                .subpass(0);
+
+            if !pulled {
+                outline_create_infos.push(
+                    create_info
+                        .clone()
+                        .stages(&outline_stages)
+                        .color_blend_state(&opaque_blending)
+                        .depth_stencil_state(&outline_depth_stencil_state),
+                );
+            }
+
+            if material.options.depth_prepass {
+                let stages = if material.options.alpha_cutoff.is_some() {
+                    &shader_stages[i][..]
+                } else {
+                    &shader_stages[i][..1]
+                };
+                prepass_create_infos.push(
+                    create_info
+                        .clone()
+                        .stages(stages)
+                        .color_blend_state(&depth_only_blending)
+                        .depth_stencil_state(&prepass_depth_stencil_state),
+                );
+            }
+
+            create_infos.push(create_info);
+        }
+        let n_main = create_infos.len();
+        let n_outline = outline_create_infos.len();
+        create_infos.extend(outline_create_infos);
+        create_infos.extend(prepass_create_infos);
+
+        let pipelines = unsafe {
+            device.create_graphics_pipelines(Some(pipeline_cache), &create_infos, None)
+        }
+        .result()?;
+        let (pipelines, rest) = pipelines.split_at(n_main);
+        let (outline_pipelines, prepass_pipelines) = rest.split_at(n_outline);
+        let mut outline_pipelines = outline_pipelines.iter().copied();
+        let mut prepass_pipelines = prepass_pipelines.iter().copied();
+
+        let mut result = Vec::with_capacity(materials.len());
+        for ((material, &pipeline), pipeline_layout) in
+            materials.iter().zip(pipelines).zip(pipeline_layouts)
+        {
+            let outline_pipeline = match material.draw_type {
+                DrawType::PointCloud | DrawType::Splats => None,
+                _ => outline_pipelines.next(),
+            };
+            let prepass_pipeline = if material.options.depth_prepass {
+                prepass_pipelines.next()
+            } else {
+                None
+            };
+            if let Some(name) = material.name() {
+                set_debug_name(device, vk::ObjectType::PIPELINE, pipeline.0, name)?;
+                if let Some(outline) = outline_pipeline {
+                    set_debug_name(
+                        device,
+                        vk::ObjectType::PIPELINE,
+                        outline.0,
+                        &format!("{} (outline)", name),
+                    )?;
+                }
+                if let Some(prepass) = prepass_pipeline {
+                    set_debug_name(
+                        device,
+                        vk::ObjectType::PIPELINE,
+                        prepass.0,
+                        &format!("{} (depth prepass)", name),
+                    )?;
+                }
+            }
+            result.push(Self {
+                pipeline,
+                outline_pipeline,
+                prepass_pipeline,
+                pipeline_layout,
+                freed: false,
+            });
+        }
+        Ok(result)
     }
 
     pub fn free(&mut self, device: &DeviceLoader) {
         unsafe {
             device.destroy_pipeline(Some(self.pipeline), None);
+            if let Some(outline) = self.outline_pipeline {
+                device.destroy_pipeline(Some(outline), None);
+            }
+            if let Some(prepass) = self.prepass_pipeline {
+                device.destroy_pipeline(Some(prepass), None);
+            }
             device.destroy_pipeline_layout(Some(self.pipeline_layout), None);
         }
         self.freed = true;
@@ -207,3 +772,11 @@ impl Drop for Material {
         }
     }
 }
+
+impl Drop for OutlineShaders {
+    fn drop(&mut self) {
+        if !self.freed {
+            panic!("OutlineShaders was dropped before it was freed!");
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use crate::allocated_buffer::AllocatedBuffer;
+use crate::deletion_queue::DeletionQueue;
+use crate::descriptors::{DescriptorAllocation, DescriptorAllocator};
+use crate::engine::MaterialId;
+use crate::memory::MemoryAllocator;
+use anyhow::Result;
+use erupt::{vk1_0 as vk, DeviceLoader};
+use nalgebra::Matrix4;
+
+/// A single point of a point cloud, matching the layout read by `shaders/points.vert`
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Point {
+    pub pos: [f32; 3],
+    /// Diameter in pixels. Sizes other than 1 need the `largePoints` device feature.
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Point {}
+unsafe impl bytemuck::Pod for Point {}
+
+impl Point {
+    pub fn new(pos: [f32; 3], color: [f32; 3]) -> Self {
+        Self {
+            pos,
+            size: 1.0,
+            color: [color[0], color[1], color[2], 1.0],
+        }
+    }
+}
+
+/// Points held in a device-local storage buffer, read by the vertex shader by index. Appends
+/// are staged on the host and copied into place at the start of the next frame.
+pub(crate) struct PointCloud {
+    pub material: MaterialId,
+    pub transform: Matrix4<f32>,
+    pub buffer: AllocatedBuffer<Point>,
+    pub descriptor: DescriptorAllocation,
+    /// Number of points drawn
+    pub len: usize,
+    /// Number of points drawn once all staged appends have been copied
+    end: usize,
+    /// Appends waiting to be copied, with their destination index
+    staged: Vec<(usize, AllocatedBuffer<Point>)>,
+    /// Staging buffers read by each frame in flight
+    in_flight: Vec<Vec<AllocatedBuffer<Point>>>,
+}
+
+impl PointCloud {
+    pub fn new(
+        material: MaterialId,
+        capacity: usize,
+        frames_in_flight: usize,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        descriptor_allocator: &mut DescriptorAllocator,
+        allocator: &mut dyn MemoryAllocator,
+        device: &DeviceLoader,
+    ) -> Result<Self> {
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = AllocatedBuffer::new_gpu_only(capacity, create_info, allocator, device)?;
+
+        let descriptor = descriptor_allocator.allocate(device, descriptor_set_layout)?;
+
+        let buffer_infos = [vk::DescriptorBufferInfoBuilder::new()
+            .buffer(buffer.buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)];
+        let writes = [vk::WriteDescriptorSetBuilder::new()
+            .buffer_info(&buffer_infos)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .dst_set(descriptor.set)
+            .dst_binding(0)
+            .dst_array_element(0)];
+        unsafe {
+            device.update_descriptor_sets(&writes, &[]);
+        }
+
+        Ok(Self {
+            material,
+            transform: Matrix4::identity(),
+            buffer,
+            descriptor,
+            len: 0,
+            end: 0,
+            staged: Vec::new(),
+            in_flight: (0..frames_in_flight).map(|_| Vec::new()).collect(),
+        })
+    }
+
+    /// Stage points to be added after the existing ones
+    pub fn append(
+        &mut self,
+        points: &[Point],
+        allocator: &mut dyn MemoryAllocator,
+        device: &DeviceLoader,
+    ) -> Result<()> {
+        if points.is_empty() {
+            return Ok(());
+        }
+        anyhow::ensure!(
+            self.end + points.len() <= self.buffer.count(),
+            "Point cloud capacity exceeded"
+        );
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let staging = AllocatedBuffer::new(points.len(), create_info, allocator, device)?;
+        staging.map(device, points)?;
+        self.staged.push((self.end, staging));
+        self.end += points.len();
+        Ok(())
+    }
+
+    /// Remove all points, including any not yet uploaded
+    pub fn clear(&mut self, device: &DeviceLoader, allocator: &mut dyn MemoryAllocator) {
+        // Staged appends have not been copied by any frame yet
+        for (_, mut staging) in self.staged.drain(..) {
+            staging.free_unused(device, allocator);
+        }
+        self.len = 0;
+        self.end = 0;
+    }
+
+    /// Record copies of staged points into `command_buffer`, outside of any render pass. Call
+    /// only once `frame`'s fence has signaled.
+    pub unsafe fn record_uploads(
+        &mut self,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+    ) -> Result<()> {
+        for mut staging in self.in_flight[frame].drain(..) {
+            staging.free_unused(device, allocator);
+        }
+        if self.staged.is_empty() {
+            return Ok(());
+        }
+
+        // Earlier frames may still be reading the points being overwritten after a clear
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::VERTEX_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            None,
+            &[],
+            &[],
+            &[],
+        );
+
+        let point_size = std::mem::size_of::<Point>() as u64;
+        for (offset, staging) in self.staged.drain(..) {
+            let copy_region = vk::BufferCopyBuilder::new()
+                .src_offset(0)
+                .dst_offset(offset as u64 * point_size)
+                .size(staging.size());
+            device.cmd_copy_buffer(
+                command_buffer,
+                staging.buffer,
+                self.buffer.buffer,
+                &[copy_region],
+            );
+            self.in_flight[frame].push(staging);
+        }
+
+        let barriers = [vk::BufferMemoryBarrierBuilder::new()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(self.buffer.buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)];
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::VERTEX_SHADER,
+            None,
+            &[],
+            &barriers,
+            &[],
+        );
+
+        self.len = self.end;
+        Ok(())
+    }
+
+    /// Free once every frame which may be using the cloud has completed, without waiting
+    pub fn retire(
+        &mut self,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+        deletion_queue: &mut DeletionQueue,
+    ) {
+        self.clear(device, allocator);
+        for frame in &mut self.in_flight {
+            for mut staging in frame.drain(..) {
+                staging.retire(deletion_queue);
+            }
+        }
+        self.buffer.retire(deletion_queue);
+        self.descriptor.retire(deletion_queue);
+    }
+}
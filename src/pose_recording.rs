@@ -0,0 +1,102 @@
+use crate::camera::Camera;
+use anyhow::{Context, Result};
+use nalgebra::Point3;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Camera pose at a point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseSample {
+    /// Seconds since the start of the recording
+    pub time: f32,
+    pub eye: Point3<f32>,
+    pub at: Point3<f32>,
+}
+
+/// Timestamped camera poses, captured while running and replayed later in place of user
+/// input, e.g. for regression testing or demo capture. Saved as one sample per line:
+/// `time eye.x eye.y eye.z at.x at.y at.z`.
+#[derive(Debug, Clone, Default)]
+pub struct PoseRecording {
+    pub samples: Vec<PoseSample>,
+}
+
+impl PoseRecording {
+    /// Append the camera's current pose. Times must not decrease.
+    pub fn record(&mut self, time: f32, camera: &Camera) {
+        self.samples.push(PoseSample {
+            time,
+            eye: camera.eye,
+            at: camera.at,
+        });
+    }
+
+    /// Time of the last sample
+    pub fn duration(&self) -> f32 {
+        self.samples.last().map(|s| s.time).unwrap_or(0.0)
+    }
+
+    /// Pose at `time`, interpolated between the nearest samples and clamped to the recording
+    pub fn sample(&self, time: f32) -> Option<(Point3<f32>, Point3<f32>)> {
+        let next = self.samples.iter().position(|s| s.time >= time);
+        let (a, b) = match next {
+            Some(0) => (self.samples.first()?, self.samples.first()?),
+            Some(i) => (&self.samples[i - 1], &self.samples[i]),
+            None => (self.samples.last()?, self.samples.last()?),
+        };
+        let span = b.time - a.time;
+        let t = if span > 0.0 {
+            (time - a.time) / span
+        } else {
+            0.0
+        };
+        Some((a.eye + (b.eye - a.eye) * t, a.at + (b.at - a.at) * t))
+    }
+
+    /// Move the camera to its recorded pose at `time`. Returns false once the recording has
+    /// ended, leaving the camera at the final pose.
+    pub fn replay(&self, time: f32, camera: &mut Camera) -> bool {
+        if let Some((eye, at)) = self.sample(time) {
+            camera.eye = eye;
+            camera.at = at;
+        }
+        time <= self.duration()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut text = String::new();
+        for s in &self.samples {
+            writeln!(
+                text,
+                "{} {} {} {} {} {} {}",
+                s.time, s.eye.x, s.eye.y, s.eye.z, s.at.x, s.at.y, s.at.z
+            )?;
+        }
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let samples = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let values = line
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<Result<Vec<f32>, _>>()
+                    .with_context(|| format!("Invalid number on line {}", i + 1))?;
+                anyhow::ensure!(values.len() == 7, "Expected 7 values on line {}", i + 1);
+                Ok(PoseSample {
+                    time: values[0],
+                    eye: Point3::new(values[1], values[2], values[3]),
+                    at: Point3::new(values[4], values[5], values[6]),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { samples })
+    }
+}
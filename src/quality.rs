@@ -0,0 +1,107 @@
+//! Frame-time driven quality control. Install a governor with `Engine::set_quality_governor`,
+//! which feeds it frame timings and applies its LOD bias; render scale and MSAA are up to
+//! applications with their own render targets. It can also be driven by hand through `update`.
+use crate::frame_pacing::FramePacingReport;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualitySettings {
+    /// Fraction of the output resolution to render at
+    pub render_scale: f32,
+    pub msaa_samples: u32,
+    /// Added to LOD selection; higher values pick coarser levels
+    pub lod_bias: f32,
+}
+
+/// Steps quality up and down between `min` and `max` to keep frames under the target time
+pub struct QualityGovernor {
+    pub min: QualitySettings,
+    pub max: QualitySettings,
+    /// Fraction of the target frame time that p95 frame time must stay below to raise quality
+    pub headroom: f32,
+    /// Consecutive evaluations required before changing level, to avoid oscillation
+    pub hysteresis: u32,
+    levels: u32,
+    level: u32,
+    over_budget: u32,
+    under_budget: u32,
+}
+
+impl QualityGovernor {
+    /// Start at the highest quality
+    pub fn new(min: QualitySettings, max: QualitySettings) -> Self {
+        let levels = 4;
+        Self {
+            min,
+            max,
+            headroom: 0.8,
+            hysteresis: 3,
+            levels,
+            level: levels,
+            over_budget: 0,
+            under_budget: 0,
+        }
+    }
+
+    /// Number of steps between `min` and `max`
+    pub fn levels(&self) -> u32 {
+        self.levels
+    }
+
+    /// Change the number of steps between `min` and `max`, clamping the current level to fit
+    pub fn set_levels(&mut self, levels: u32) {
+        self.levels = levels;
+        self.level = self.level.min(levels);
+    }
+
+    pub fn settings(&self) -> QualitySettings {
+        let t = self.level as f32 / self.levels.max(1) as f32;
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        let samples = lerp(self.min.msaa_samples as f32, self.max.msaa_samples as f32);
+        QualitySettings {
+            render_scale: lerp(self.min.render_scale, self.max.render_scale),
+            // Sample counts must be powers of two
+            msaa_samples: (samples.max(1.0) as u32 + 1).next_power_of_two() / 2,
+            lod_bias: lerp(self.min.lod_bias, self.max.lod_bias),
+        }
+    }
+
+    /// Feed a pacing report, e.g. once a second. Returns new settings when the level changed.
+    pub fn update(
+        &mut self,
+        report: &FramePacingReport,
+        target: Duration,
+    ) -> Option<QualitySettings> {
+        let frame_time = report
+            .gpu
+            .map(|g| g.p95)
+            .unwrap_or_default()
+            .max(report.cpu.p95);
+
+        if frame_time > target {
+            self.over_budget += 1;
+            self.under_budget = 0;
+        } else if frame_time.as_secs_f32() < target.as_secs_f32() * self.headroom {
+            self.under_budget += 1;
+            self.over_budget = 0;
+        } else {
+            self.over_budget = 0;
+            self.under_budget = 0;
+        }
+
+        let old = self.level;
+        if self.over_budget >= self.hysteresis && self.level > 0 {
+            self.level -= 1;
+            self.over_budget = 0;
+        } else if self.under_budget >= self.hysteresis && self.level < self.levels {
+            self.level += 1;
+            self.under_budget = 0;
+        }
+
+        if self.level != old {
+            Some(self.settings())
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Triggering frame captures through RenderDoc's in-application API, for applications launched
+//! from RenderDoc. Linux only.
+use crate::Engine;
+use anyhow::{bail, Context, Result};
+use libloading::os::unix::{Library, Symbol, RTLD_NOW};
+use std::os::raw::{c_int, c_void};
+
+/// `eRENDERDOC_API_Version_1_1_2`
+const API_VERSION: c_int = 10102;
+/// Only find the library if RenderDoc already injected it, rather than loading it ourselves
+const RTLD_NOLOAD: c_int = 0x4;
+
+type GetApi = unsafe extern "C" fn(version: c_int, out: *mut *mut c_void) -> c_int;
+
+/// The start of `RENDERDOC_API_1_1_2`, up to the only entry used
+#[repr(C)]
+struct Api {
+    _before: [*const c_void; 15],
+    trigger_capture: unsafe extern "C" fn(),
+}
+
+impl Engine {
+    /// Capture the next presented frame in RenderDoc. Fails unless the application was launched
+    /// from RenderDoc.
+    pub fn trigger_capture(&self) -> Result<()> {
+        let library = Library::open(Some("librenderdoc.so"), RTLD_NOW | RTLD_NOLOAD)
+            .context("RenderDoc is not attached")?;
+        unsafe {
+            let get_api: Symbol<GetApi> = library.get(b"RENDERDOC_GetAPI\0")?;
+            let mut api: *mut c_void = std::ptr::null_mut();
+            if get_api(API_VERSION, &mut api) != 1 || api.is_null() {
+                bail!("RenderDoc does not support API version 1.1.2");
+            }
+            let api = &*(api as *const Api);
+            (api.trigger_capture)();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{DrawType, Engine, MaterialId, MeshRetention, ObjectId, Vertex};
+use anyhow::Result;
+use nalgebra::Matrix4;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Serializable arrangement of materials and objects, stored as JSON
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scene {
+    pub materials: Vec<SceneMaterial>,
+    pub objects: Vec<SceneObject>,
+}
+
+/// Material referenced by SPIR-V paths, relative to the scene file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneMaterial {
+    pub vertex_shader: PathBuf,
+    pub fragment_shader: PathBuf,
+    pub draw_type: DrawType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneObject {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+    /// Index into `Scene::materials`
+    pub material: usize,
+    pub transform: [[f32; 4]; 4],
+    pub color: [f32; 4],
+    pub dynamic: bool,
+}
+
+/// IDs created by `Engine::load_scene`, in the same order as the scene's entries. Objects keep
+/// a copy of their geometry, so the scene can be written back with `Engine::save_scene`.
+pub struct LoadedScene {
+    pub materials: Vec<MaterialId>,
+    pub objects: Vec<ObjectId>,
+}
+
+impl Scene {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl Engine {
+    /// Load a scene file, creating its materials and objects
+    pub fn load_scene(&mut self, path: impl AsRef<Path>) -> Result<LoadedScene> {
+        let path = path.as_ref();
+        let scene = Scene::load(path)?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let materials = scene
+            .materials
+            .iter()
+            .map(|m| {
+                let vertex = fs::read(base.join(&m.vertex_shader))?;
+                let fragment = fs::read(base.join(&m.fragment_shader))?;
+                self.load_material(&vertex, &fragment, m.draw_type)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut objects = Vec::with_capacity(scene.objects.len());
+        for object in &scene.objects {
+            let material = *materials.get(object.material).ok_or_else(|| {
+                anyhow::format_err!(
+                    "Scene object refers to missing material {}",
+                    object.material
+                )
+            })?;
+            let id = self.add_object(
+                &object.vertices,
+                &object.indices,
+                material,
+                object.dynamic,
+                MeshRetention::Retain,
+            )?;
+            self.set_transform(id, Matrix4::from(object.transform))?;
+            self.set_color(id, object.color)?;
+            objects.push(id);
+        }
+
+        Ok(LoadedScene { materials, objects })
+    }
+
+    /// Build a scene from every object using one of `materials`, each paired with the paths
+    /// it is saved under. Materials are not recorded by the engine in a form that can be
+    /// written out, so any material left out of the list is skipped along with its objects.
+    /// Objects must have been created with `MeshRetention::Retain`.
+    pub fn export_scene(&self, materials: &[(MaterialId, SceneMaterial)]) -> Result<Scene> {
+        let mut objects = Vec::new();
+        for id in self.objects() {
+            let info = match self.object_info(id) {
+                Some(info) => info,
+                None => continue,
+            };
+            let material = match materials.iter().position(|(m, _)| *m == info.material) {
+                Some(index) => index,
+                None => continue,
+            };
+            let mesh = self.mesh_data(id).ok_or_else(|| {
+                anyhow::format_err!("Object {:?} does not retain its mesh data", id)
+            })?;
+            let transform = self
+                .transform(id)
+                .copied()
+                .unwrap_or_else(Matrix4::identity);
+            objects.push(SceneObject {
+                vertices: mesh.vertices.clone(),
+                indices: mesh.indices.clone(),
+                material,
+                transform: transform.into(),
+                color: self.color(id).unwrap_or([1.0; 4]),
+                dynamic: info.dynamic,
+            });
+        }
+
+        Ok(Scene {
+            materials: materials.iter().map(|(_, m)| m.clone()).collect(),
+            objects,
+        })
+    }
+
+    /// Export a scene as with `export_scene` and write it to `path`
+    pub fn save_scene(
+        &self,
+        path: impl AsRef<Path>,
+        materials: &[(MaterialId, SceneMaterial)],
+    ) -> Result<()> {
+        self.export_scene(materials)?.save(path)
+    }
+}
@@ -0,0 +1,614 @@
+use crate::engine::ObjectPushConstants;
+use crate::pipeline::{DrawType, MaterialOptions};
+use crate::vertex::VertexFormat;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+// SPIR-V opcodes, decorations and enumerants read by the validator
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_OUTPUT: u32 = 3;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const EXECUTION_MODEL_VERTEX: u32 = 0;
+const EXECUTION_MODEL_FRAGMENT: u32 = 4;
+
+/// Shader stage being validated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Vertex,
+    Fragment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Descriptor {
+    UniformBuffer,
+    StorageBuffer,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    /// Width in bits
+    Int(u32),
+    /// Width in bits
+    Float(u32),
+    /// Component type and count
+    Vector(u32, u32),
+    /// Column type and count
+    Matrix(u32, u32),
+    /// Element type, and the constant holding the length
+    Array(u32, u32),
+    /// Member types
+    Struct(Vec<u32>),
+    /// Pointee type
+    Pointer(u32),
+}
+
+#[derive(Debug, Default)]
+struct Decorations {
+    location: Option<u32>,
+    binding: Option<u32>,
+    set: Option<u32>,
+    built_in: bool,
+    buffer_block: bool,
+    array_stride: Option<u32>,
+}
+
+/// Decorations of one member of a struct
+#[derive(Debug, Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+}
+
+/// Check a shader's entry point, vertex inputs, fragment outputs and descriptor bindings
+/// against what the engine provides for `draw_type`, so mismatches are reported by name
+/// instead of failing inside pipeline creation or misrendering
+pub fn validate(
+    code: &[u32],
+    stage: Stage,
+    draw_type: DrawType,
+    options: &MaterialOptions,
+    user_uniform: bool,
+) -> Result<()> {
+    let module = Module::parse(code)?;
+    let material_uniforms = options.uniform_bytes > 0;
+
+    let pushed = std::mem::size_of::<ObjectPushConstants>() as u32;
+    if let Some(size) = module.push_constant_size() {
+        if size > pushed {
+            bail!(
+                "{:?} shader's push constant block is {} bytes, but the engine only pushes {}",
+                stage,
+                size,
+                pushed
+            );
+        }
+    }
+
+    let model = match stage {
+        Stage::Vertex => EXECUTION_MODEL_VERTEX,
+        Stage::Fragment => EXECUTION_MODEL_FRAGMENT,
+    };
+    if !module
+        .entry_points
+        .iter()
+        .any(|(m, name)| *m == model && name == "main")
+    {
+        bail!(
+            "{:?} shader has no {:?} entry point named \"main\"",
+            stage,
+            stage
+        );
+    }
+
+    for &(id, storage_class) in &module.variables {
+        let decorations = module.decorations.get(&id);
+        let built_in = decorations.map(|d| d.built_in).unwrap_or(false);
+        let location = decorations.and_then(|d| d.location);
+
+        match (stage, storage_class) {
+            (Stage::Vertex, STORAGE_CLASS_INPUT) if !built_in => {
+                let location = location.unwrap_or(u32::MAX);
+                if matches!(draw_type, DrawType::PointCloud | DrawType::Splats) {
+                    bail!(
+                        "Vertex shader reads input location {}, but {:?} materials have no \
+                         vertex buffer",
+                        location,
+                        draw_type
+                    );
+                }
+                if location == 1 && options.vertex_format == VertexFormat::Position {
+                    bail!(
+                        "Vertex shader reads a color at location 1, but the material uses \
+                         VertexFormat::Position"
+                    );
+                }
+                // Vertex has a vec3 position at location 0 and a vec3 color at location 1
+                if location > 1 {
+                    bail!(
+                        "Vertex shader reads input location {}, but Vertex only provides \
+                         locations 0 (pos) and 1 (color)",
+                        location
+                    );
+                }
+                if !module.is_float_vector(module.pointee(id)) {
+                    bail!(
+                        "Vertex shader input location {} must be a float or float vector",
+                        location
+                    );
+                }
+            }
+            (Stage::Fragment, STORAGE_CLASS_OUTPUT) if !built_in && location != Some(0) => {
+                bail!(
+                    "Fragment shader writes output location {:?}, but only location 0 has a \
+                     color attachment",
+                    location
+                );
+            }
+            (_, STORAGE_CLASS_UNIFORM)
+            | (_, STORAGE_CLASS_UNIFORM_CONSTANT)
+            | (_, STORAGE_CLASS_STORAGE_BUFFER) => {
+                let set = decorations.and_then(|d| d.set).unwrap_or(0);
+                let binding = decorations.and_then(|d| d.binding).unwrap_or(0);
+                let found = module.descriptor(id, storage_class);
+                let expected = match (set, binding) {
+                    (0, 0) => Some(Descriptor::UniformBuffer),
+                    (0, 1) if user_uniform => Some(Descriptor::UniformBuffer),
+                    (0, 2) => Some(Descriptor::UniformBuffer),
+                    (1, 0) | (1, 1) if stage == Stage::Vertex => Some(Descriptor::StorageBuffer),
+                    (2, 0) if material_uniforms => Some(Descriptor::UniformBuffer),
+                    (2, b) if (1..=options.storage_buffers).contains(&b) => {
+                        Some(Descriptor::StorageBuffer)
+                    }
+                    _ => None,
+                };
+                match expected {
+                    Some(expected) if expected == found => (),
+                    Some(expected) => bail!(
+                        "{:?} shader declares set {} binding {} as {:?}, but the engine binds \
+                         a {:?} there",
+                        stage,
+                        set,
+                        binding,
+                        found,
+                        expected
+                    ),
+                    None if (set, binding) == (0, 1) => bail!(
+                        "{:?} shader uses set 0 binding 1, but no user uniform has been set; call \
+                         Engine::set_user_uniform before loading the material",
+                        stage
+                    ),
+                    None if set == 2 && binding > 0 => bail!(
+                        "{:?} shader uses set 2 binding {}, but the material was created with \
+                         storage_buffers = {}",
+                        stage,
+                        binding,
+                        options.storage_buffers
+                    ),
+                    None if set == 2 && !material_uniforms => bail!(
+                        "{:?} shader uses set 2 binding {}, but the material was created with \
+                         uniform_bytes = 0",
+                        stage,
+                        binding
+                    ),
+                    None => bail!(
+                        "{:?} shader uses set {} binding {}, which the engine does not provide \
+                         to this stage",
+                        stage,
+                        set,
+                        binding
+                    ),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// The parts of a SPIR-V module the validator needs
+#[derive(Default)]
+struct Module {
+    entry_points: Vec<(u32, String)>,
+    types: HashMap<u32, Type>,
+    decorations: HashMap<u32, Decorations>,
+    /// Global variables and their storage classes
+    variables: Vec<(u32, u32)>,
+    /// Result type of each variable
+    variable_types: HashMap<u32, u32>,
+    /// By struct type and member index
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    /// Values of 32-bit integer constants, for array lengths
+    constants: HashMap<u32, u32>,
+}
+
+impl Module {
+    fn parse(code: &[u32]) -> Result<Self> {
+        if code.len() < 5 || code[0] != 0x0723_0203 {
+            bail!("Shader is not a SPIR-V module");
+        }
+
+        let mut module = Self::default();
+        let mut words = &code[5..];
+        while !words.is_empty() {
+            let opcode = words[0] & 0xFFFF;
+            let count = (words[0] >> 16) as usize;
+            if count == 0 || count > words.len() {
+                bail!("Malformed SPIR-V instruction");
+            }
+            let operands = &words[1..count];
+            words = &words[count..];
+
+            match opcode {
+                OP_ENTRY_POINT if operands.len() >= 3 => {
+                    module
+                        .entry_points
+                        .push((operands[0], string_operand(&operands[2..])));
+                }
+                OP_TYPE_INT if operands.len() >= 2 => {
+                    module.types.insert(operands[0], Type::Int(operands[1]));
+                }
+                OP_TYPE_FLOAT if operands.len() >= 2 => {
+                    module.types.insert(operands[0], Type::Float(operands[1]));
+                }
+                OP_TYPE_VECTOR if operands.len() >= 3 => {
+                    let vector = Type::Vector(operands[1], operands[2]);
+                    module.types.insert(operands[0], vector);
+                }
+                OP_TYPE_MATRIX if operands.len() >= 3 => {
+                    let matrix = Type::Matrix(operands[1], operands[2]);
+                    module.types.insert(operands[0], matrix);
+                }
+                OP_TYPE_ARRAY if operands.len() >= 3 => {
+                    let array = Type::Array(operands[1], operands[2]);
+                    module.types.insert(operands[0], array);
+                }
+                OP_TYPE_STRUCT if !operands.is_empty() => {
+                    let members = operands[1..].to_vec();
+                    module.types.insert(operands[0], Type::Struct(members));
+                }
+                OP_TYPE_POINTER if operands.len() >= 3 => {
+                    module.types.insert(operands[0], Type::Pointer(operands[2]));
+                }
+                OP_CONSTANT if operands.len() >= 3 => {
+                    module.constants.insert(operands[1], operands[2]);
+                }
+                OP_VARIABLE if operands.len() >= 3 => {
+                    module.variables.push((operands[1], operands[2]));
+                    module.variable_types.insert(operands[1], operands[0]);
+                }
+                OP_DECORATE if operands.len() >= 2 => {
+                    let decorations = module.decorations.entry(operands[0]).or_default();
+                    let value = operands.get(2).copied();
+                    match operands[1] {
+                        DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                        DECORATION_BUILT_IN => decorations.built_in = true,
+                        DECORATION_LOCATION => decorations.location = value,
+                        DECORATION_BINDING => decorations.binding = value,
+                        DECORATION_DESCRIPTOR_SET => decorations.set = value,
+                        DECORATION_ARRAY_STRIDE => decorations.array_stride = value,
+                        _ => (),
+                    }
+                }
+                OP_MEMBER_DECORATE if operands.len() >= 3 => {
+                    let decorations = module
+                        .member_decorations
+                        .entry((operands[0], operands[1]))
+                        .or_default();
+                    let value = operands.get(3).copied();
+                    match operands[2] {
+                        DECORATION_OFFSET => decorations.offset = value,
+                        DECORATION_MATRIX_STRIDE => decorations.matrix_stride = value,
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(module)
+    }
+
+    /// Type pointed to by a variable
+    fn pointee(&self, variable: u32) -> Option<u32> {
+        match self.types.get(self.variable_types.get(&variable)?)? {
+            Type::Pointer(pointee) => Some(*pointee),
+            _ => None,
+        }
+    }
+
+    fn is_float_vector(&self, id: Option<u32>) -> bool {
+        match id.and_then(|id| self.types.get(&id)) {
+            Some(Type::Float(_)) => true,
+            Some(Type::Vector(component, _)) => {
+                matches!(self.types.get(component), Some(Type::Float(_)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Bytes spanned by the push constant block, if the module declares one
+    fn push_constant_size(&self) -> Option<u32> {
+        let &(block, _) = self
+            .variables
+            .iter()
+            .find(|(_, storage_class)| *storage_class == STORAGE_CLASS_PUSH_CONSTANT)?;
+        self.size(self.pointee(block)?, None)
+    }
+
+    /// Bytes spanned by a value of type `id` laid out with explicit offsets and strides.
+    /// `matrix_stride` applies if `id` is a matrix member of a struct. `None` for types that
+    /// can't appear in a block or whose size depends on runtime data.
+    fn size(&self, id: u32, matrix_stride: Option<u32>) -> Option<u32> {
+        match self.types.get(&id)? {
+            Type::Int(width) | Type::Float(width) => Some(width / 8),
+            Type::Vector(component, count) => Some(self.size(*component, None)? * count),
+            Type::Matrix(column, count) => {
+                let stride = match matrix_stride {
+                    Some(stride) => stride,
+                    None => self.size(*column, None)?,
+                };
+                Some(stride * count)
+            }
+            Type::Array(element, length) => {
+                let length = *self.constants.get(length)?;
+                let stride = match self.decorations.get(&id).and_then(|d| d.array_stride) {
+                    Some(stride) => stride,
+                    None => self.size(*element, None)?,
+                };
+                Some(stride * length)
+            }
+            Type::Struct(members) => {
+                let mut end = 0;
+                for (index, &member) in members.iter().enumerate() {
+                    let decorations = self.member_decorations.get(&(id, index as u32));
+                    let offset = decorations.and_then(|d| d.offset).unwrap_or(end);
+                    let matrix_stride = decorations.and_then(|d| d.matrix_stride);
+                    end = end.max(offset + self.size(member, matrix_stride)?);
+                }
+                Some(end)
+            }
+            Type::Pointer(_) => None,
+        }
+    }
+
+    fn descriptor(&self, variable: u32, storage_class: u32) -> Descriptor {
+        let buffer_block = self
+            .pointee(variable)
+            .and_then(|pointee| self.decorations.get(&pointee))
+            .map(|d| d.buffer_block)
+            .unwrap_or(false);
+        match storage_class {
+            STORAGE_CLASS_STORAGE_BUFFER => Descriptor::StorageBuffer,
+            STORAGE_CLASS_UNIFORM if buffer_block => Descriptor::StorageBuffer,
+            STORAGE_CLASS_UNIFORM => Descriptor::UniformBuffer,
+            _ => Descriptor::Other,
+        }
+    }
+}
+
+/// Decode a nul-terminated UTF-8 literal packed into words
+fn string_operand(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .take_while(|&byte| byte != 0)
+        .collect::<Vec<u8>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE_VERT: &[u8] = include_bytes!("../shaders/triangle.vert.spv");
+    const TRIANGLE_FRAG: &[u8] = include_bytes!("../shaders/triangle.frag.spv");
+    const POINTS_VERT: &[u8] = include_bytes!("../shaders/points.vert.spv");
+    const SPLATS_VERT: &[u8] = include_bytes!("../shaders/splats.vert.spv");
+    const LIT_FRAG: &[u8] = include_bytes!("../shaders/builtin/lit.frag.spv");
+    const WATER_VERT: &[u8] = include_bytes!("../shaders/builtin/water.vert.spv");
+    const INTERFACE_VERT: &[u8] = include_bytes!("../shaders/test/interface.vert.spv");
+    const PUSH_CONSTANTS_VERT: &[u8] = include_bytes!("../shaders/test/push_constants.vert.spv");
+
+    fn words(bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect()
+    }
+
+    fn module(bytes: &[u8]) -> Module {
+        Module::parse(&words(bytes)).unwrap()
+    }
+
+    /// Set, binding and kind of every descriptor the module declares, sorted
+    fn bindings(module: &Module) -> Vec<(u32, u32, Descriptor)> {
+        let mut bindings: Vec<_> = module
+            .variables
+            .iter()
+            .filter_map(|&(id, storage_class)| {
+                let decorations = module.decorations.get(&id)?;
+                let set = decorations.set.unwrap_or(0);
+                Some((
+                    set,
+                    decorations.binding?,
+                    module.descriptor(id, storage_class),
+                ))
+            })
+            .collect();
+        bindings.sort_by_key(|&(set, binding, _)| (set, binding));
+        bindings
+    }
+
+    /// Locations of the module's non-built-in inputs, sorted
+    fn inputs(module: &Module) -> Vec<u32> {
+        let mut inputs: Vec<_> = module
+            .variables
+            .iter()
+            .filter(|(_, storage_class)| *storage_class == STORAGE_CLASS_INPUT)
+            .filter_map(|(id, _)| module.decorations.get(id))
+            .filter(|decorations| !decorations.built_in)
+            .filter_map(|decorations| decorations.location)
+            .collect();
+        inputs.sort_unstable();
+        inputs
+    }
+
+    #[test]
+    fn push_constant_block_matches_the_engine() {
+        let pushed = std::mem::size_of::<ObjectPushConstants>() as u32;
+        for shader in &[TRIANGLE_VERT, TRIANGLE_FRAG, POINTS_VERT, LIT_FRAG] {
+            assert_eq!(module(shader).push_constant_size(), Some(pushed));
+        }
+        assert_eq!(module(PUSH_CONSTANTS_VERT).push_constant_size(), Some(128));
+        let options = MaterialOptions::default();
+        let code = words(PUSH_CONSTANTS_VERT);
+        assert!(validate(&code, Stage::Vertex, DrawType::Triangles, &options, false).is_err());
+    }
+
+    #[test]
+    fn descriptor_layout_is_reflected() {
+        use Descriptor::*;
+        let uniform = |binding| (0, binding, UniformBuffer);
+        assert!(bindings(&module(TRIANGLE_VERT)).contains(&uniform(0)));
+        assert!(bindings(&module(LIT_FRAG)).contains(&uniform(2)));
+        assert!(bindings(&module(WATER_VERT)).contains(&(2, 0, UniformBuffer)));
+        assert_eq!(
+            bindings(&module(SPLATS_VERT)),
+            [
+                uniform(0),
+                uniform(2),
+                (1, 0, StorageBuffer),
+                (1, 1, StorageBuffer)
+            ]
+        );
+        assert_eq!(
+            bindings(&module(INTERFACE_VERT)),
+            [
+                uniform(0),
+                uniform(1),
+                uniform(2),
+                (2, 0, UniformBuffer),
+                (2, 1, StorageBuffer)
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_bindings_must_be_provided() {
+        let code = words(INTERFACE_VERT);
+        let options = MaterialOptions {
+            uniform_bytes: 16,
+            storage_buffers: 1,
+            ..Default::default()
+        };
+        let validate = |options: &MaterialOptions, user_uniform| {
+            validate(
+                &code,
+                Stage::Vertex,
+                DrawType::Triangles,
+                options,
+                user_uniform,
+            )
+        };
+        assert!(validate(&options, true).is_ok());
+        assert!(validate(&options, false).is_err());
+        let no_storage = MaterialOptions {
+            storage_buffers: 0,
+            ..options.clone()
+        };
+        assert!(validate(&no_storage, true).is_err());
+        let no_uniforms = MaterialOptions {
+            uniform_bytes: 0,
+            ..options
+        };
+        assert!(validate(&no_uniforms, true).is_err());
+    }
+
+    #[test]
+    fn vertex_inputs_are_reflected() {
+        assert_eq!(inputs(&module(TRIANGLE_VERT)), [0, 1]);
+        assert!(inputs(&module(POINTS_VERT)).is_empty());
+
+        let options = MaterialOptions::default();
+        let triangle = words(TRIANGLE_VERT);
+        let points = words(POINTS_VERT);
+        assert!(validate(
+            &triangle,
+            Stage::Vertex,
+            DrawType::Triangles,
+            &options,
+            false
+        )
+        .is_ok());
+        assert!(validate(
+            &triangle,
+            Stage::Vertex,
+            DrawType::PointCloud,
+            &options,
+            false
+        )
+        .is_err());
+        assert!(validate(
+            &points,
+            Stage::Vertex,
+            DrawType::PointCloud,
+            &options,
+            false
+        )
+        .is_ok());
+        let position_only = MaterialOptions {
+            vertex_format: VertexFormat::Position,
+            ..Default::default()
+        };
+        assert!(validate(
+            &triangle,
+            Stage::Vertex,
+            DrawType::Triangles,
+            &position_only,
+            false
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn malformed_modules_are_rejected() {
+        let code = words(TRIANGLE_VERT);
+        assert!(Module::parse(&code[..3]).is_err());
+        // Cut off inside the first instruction, an OpCapability
+        assert!(Module::parse(&code[..6]).is_err());
+        let mut bad_magic = code.clone();
+        bad_magic[0] = 0xDEAD_BEEF;
+        assert!(Module::parse(&bad_magic).is_err());
+        let mut zero_length = code;
+        zero_length[5] = 0;
+        assert!(Module::parse(&zero_length).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use crate::engine::ObjectId;
+use nalgebra::{Matrix3, Matrix4, Quaternion, Rotation3, UnitQuaternion, Vector3, U1, U3};
+use std::collections::HashMap;
+
+/// Object transform split into parts that interpolate well, stored as plain arrays so it
+/// serializes compactly
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObjectPose {
+    pub translation: [f32; 3],
+    /// Unit quaternion as `[i, j, k, w]`
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+/// Transforms of every object at a point in time, e.g. as received from a server. Object IDs
+/// agree between engines that create and remove objects in the same order.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransformSnapshot {
+    /// Application-defined timestamp, in seconds
+    pub time: f32,
+    pub poses: Vec<(ObjectId, ObjectPose)>,
+}
+
+impl ObjectPose {
+    /// Decompose a transform made of translation, rotation and scale. Shear is discarded.
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Self {
+        let translation = matrix.fixed_slice::<U3, U1>(0, 3).into_owned();
+        let linear = matrix.fixed_slice::<U3, U3>(0, 0).into_owned();
+        let scale = Vector3::new(
+            linear.column(0).norm(),
+            linear.column(1).norm(),
+            linear.column(2).norm(),
+        );
+        let inverse_scale = scale.map(|s| if s != 0.0 { 1.0 / s } else { 0.0 });
+        let rotation = linear * Matrix3::from_diagonal(&inverse_scale);
+        let rotation =
+            UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(rotation));
+        Self {
+            translation: [translation.x, translation.y, translation.z],
+            rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+            scale: [scale.x, scale.y, scale.z],
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&Vector3::from(self.translation))
+            * self.unit_rotation().to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&Vector3::from(self.scale))
+    }
+
+    /// Blend towards `other` by `t` in `0..1`, spherically for the rotation
+    pub fn interpolate(&self, other: &Self, t: f32) -> Self {
+        let lerp = |a: [f32; 3], b: [f32; 3]| {
+            let v = Vector3::from(a).lerp(&Vector3::from(b), t);
+            [v.x, v.y, v.z]
+        };
+        let rotation = self.unit_rotation().slerp(&other.unit_rotation(), t);
+        Self {
+            translation: lerp(self.translation, other.translation),
+            rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+            scale: lerp(self.scale, other.scale),
+        }
+    }
+
+    fn unit_rotation(&self) -> UnitQuaternion<f32> {
+        let [i, j, k, w] = self.rotation;
+        UnitQuaternion::from_quaternion(Quaternion::new(w, i, j, k))
+    }
+}
+
+impl TransformSnapshot {
+    /// Blend two snapshots at `time`, clamped to lie between their timestamps. Only objects
+    /// present in both are included.
+    pub fn interpolate(&self, next: &Self, time: f32) -> Self {
+        let span = next.time - self.time;
+        let t = if span > 0.0 {
+            ((time - self.time) / span).max(0.0).min(1.0)
+        } else {
+            1.0
+        };
+        let next_poses: HashMap<ObjectId, &ObjectPose> =
+            next.poses.iter().map(|(id, pose)| (*id, pose)).collect();
+        let poses = self
+            .poses
+            .iter()
+            .filter_map(|(id, pose)| {
+                let next_pose = next_poses.get(id)?;
+                Some((*id, pose.interpolate(next_pose, t)))
+            })
+            .collect();
+        Self { time, poses }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::allocated_buffer::AllocatedBuffer;
+use crate::deletion_queue::DeletionQueue;
+use crate::descriptors::{DescriptorAllocation, DescriptorAllocator};
+use crate::engine::MaterialId;
+use crate::memory::MemoryAllocator;
+use crate::streamed_buffer::StreamedBuffer;
+use anyhow::Result;
+use erupt::{vk1_0 as vk, DeviceLoader};
+use nalgebra::{Matrix4, Point3};
+
+/// An isotropic gaussian splat, matching the layout read by `shaders/splats.vert`
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Splat {
+    pub pos: [f32; 3],
+    /// World-space radius of the billboard the gaussian is drawn on
+    pub radius: f32,
+    /// Color and peak opacity
+    pub color: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Splat {}
+unsafe impl bytemuck::Pod for Splat {}
+
+/// Splats held in a device-local storage buffer, drawn through a per-frame index buffer sorted
+/// back to front on the host whenever the viewpoint changes.
+pub(crate) struct SplatCloud {
+    pub material: MaterialId,
+    pub transform: Matrix4<f32>,
+    pub buffer: AllocatedBuffer<Splat>,
+    pub order: StreamedBuffer<u32>,
+    /// One per frame in flight, binding `buffer` and that frame's copy of `order`
+    pub descriptors: Vec<DescriptorAllocation>,
+    /// Splat centers in model space, for sorting
+    positions: Vec<Point3<f32>>,
+    /// Viewpoint the current order was sorted for, in model space
+    sorted_from: Option<Point3<f32>>,
+}
+
+impl SplatCloud {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        material: MaterialId,
+        splats: &[Splat],
+        frames_in_flight: usize,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        descriptor_allocator: &mut DescriptorAllocator,
+        allocator: &mut dyn MemoryAllocator,
+        device: &DeviceLoader,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+    ) -> Result<Self> {
+        anyhow::ensure!(!splats.is_empty(), "Splat clouds need at least one splat");
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = AllocatedBuffer::new(splats.len(), create_info, allocator, device)?;
+        buffer.map(device, splats)?;
+        let buffer = buffer.gpu_only(device, allocator, command_pool, queue)?;
+
+        let create_info = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let initial_order = (0..splats.len() as u32).collect::<Vec<_>>();
+        let order = StreamedBuffer::new_dynamic(
+            &initial_order,
+            frames_in_flight,
+            create_info,
+            allocator,
+            device,
+        )?;
+
+        let descriptors = (0..frames_in_flight)
+            .map(|_| descriptor_allocator.allocate(device, descriptor_set_layout))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (frame, descriptor) in descriptors.iter().enumerate() {
+            let splat_infos = [vk::DescriptorBufferInfoBuilder::new()
+                .buffer(buffer.buffer)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)];
+            let order_infos = [vk::DescriptorBufferInfoBuilder::new()
+                .buffer(order.buffer(frame))
+                .offset(0)
+                .range(vk::WHOLE_SIZE)];
+            let writes = [
+                vk::WriteDescriptorSetBuilder::new()
+                    .buffer_info(&splat_infos)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .dst_set(descriptor.set)
+                    .dst_binding(0)
+                    .dst_array_element(0),
+                vk::WriteDescriptorSetBuilder::new()
+                    .buffer_info(&order_infos)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .dst_set(descriptor.set)
+                    .dst_binding(1)
+                    .dst_array_element(0),
+            ];
+            unsafe {
+                device.update_descriptor_sets(&writes, &[]);
+            }
+        }
+
+        Ok(Self {
+            material,
+            transform: Matrix4::identity(),
+            buffer,
+            order,
+            descriptors,
+            positions: splats
+                .iter()
+                .map(|s| Point3::new(s.pos[0], s.pos[1], s.pos[2]))
+                .collect(),
+            sorted_from: None,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Re-sort back to front if the viewpoint moved, then bring `frame`'s order up to date. Call
+    /// only once that frame's fence has signaled.
+    pub fn sort(
+        &mut self,
+        eye: &Point3<f32>,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+        deletion_queue: &mut DeletionQueue,
+        frame: usize,
+    ) -> Result<()> {
+        let eye = match self.transform.try_inverse() {
+            Some(inverse) => inverse.transform_point(eye),
+            None => *eye,
+        };
+        if self.sorted_from != Some(eye) {
+            let distances = self
+                .positions
+                .iter()
+                .map(|p| nalgebra::distance_squared(p, &eye))
+                .collect::<Vec<_>>();
+            let mut order = (0..self.positions.len() as u32).collect::<Vec<_>>();
+            order.sort_unstable_by(|a, b| {
+                distances[*b as usize]
+                    .partial_cmp(&distances[*a as usize])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            self.order.write(&order)?;
+            self.sorted_from = Some(eye);
+        }
+        self.order.flush(device, allocator, deletion_queue, frame)
+    }
+
+    /// Free once every frame which may be using the cloud has completed, without waiting
+    pub fn retire(&mut self, deletion_queue: &mut DeletionQueue) {
+        self.buffer.retire(deletion_queue);
+        self.order.retire(deletion_queue);
+        for descriptor in self.descriptors.drain(..) {
+            descriptor.retire(deletion_queue);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::allocated_buffer::AllocatedBuffer;
+use crate::debug_name::set_debug_name;
+use crate::deletion_queue::DeletionQueue;
+use crate::memory::MemoryAllocator;
+use anyhow::Result;
+use erupt::{vk1_0 as vk, DeviceLoader};
+use std::ops::Range;
+
+/// Either a single gpu-only buffer, or one host-visible copy per frame in flight so that writes
+/// never touch a buffer the GPU may still be reading. Dynamic buffers may change length; each
+/// copy is reallocated when its frame comes around.
+pub struct StreamedBuffer<T> {
+    copies: Vec<AllocatedBuffer<T>>,
+    create_info: vk::BufferCreateInfoBuilder<'static>,
+    /// Latest contents, kept to bring stale copies up to date
+    pending: Vec<T>,
+    /// The span of `pending` each copy still needs written to it
+    dirty: Vec<Option<Range<usize>>>,
+    /// Applied to copies as they are reallocated
+    debug_name: Option<String>,
+}
+
+impl<T: Sized + bytemuck::Pod> StreamedBuffer<T> {
+    /// Upload `data` once into device-local memory
+    pub fn new_static(
+        data: &[T],
+        create_info: vk::BufferCreateInfoBuilder<'static>,
+        allocator: &mut dyn MemoryAllocator,
+        device: &DeviceLoader,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+    ) -> Result<Self> {
+        let buffer = AllocatedBuffer::new(data.len(), create_info.clone(), allocator, device)?;
+        buffer.map(device, data)?;
+        let buffer = buffer.gpu_only(device, allocator, command_pool, queue)?;
+        Ok(Self {
+            copies: vec![buffer],
+            create_info,
+            pending: Vec::new(),
+            dirty: vec![None],
+            debug_name: None,
+        })
+    }
+
+    /// Create one host-visible copy of `data` per frame in flight
+    pub fn new_dynamic(
+        data: &[T],
+        frames_in_flight: usize,
+        create_info: vk::BufferCreateInfoBuilder<'static>,
+        allocator: &mut dyn MemoryAllocator,
+        device: &DeviceLoader,
+    ) -> Result<Self> {
+        let copies = (0..frames_in_flight)
+            .map(|_| {
+                let buffer =
+                    AllocatedBuffer::new(data.len(), create_info.clone(), allocator, device)?;
+                buffer.map(device, data)?;
+                Ok(buffer)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            copies,
+            create_info,
+            pending: data.to_vec(),
+            dirty: vec![None; frames_in_flight],
+            debug_name: None,
+        })
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.copies[0].is_dynamic()
+    }
+
+    /// Number of elements in the copy used by `frame`
+    pub fn count(&self, frame: usize) -> usize {
+        self.copies[frame % self.copies.len()].count()
+    }
+
+    /// Number of elements as of the latest write, which copies catch up to as their frames come
+    /// around
+    pub fn latest_count(&self) -> usize {
+        if self.is_dynamic() {
+            self.pending.len()
+        } else {
+            self.copies[0].count()
+        }
+    }
+
+    /// Latest contents staged with `write`, for dynamic buffers
+    pub fn contents(&self) -> &[T] {
+        &self.pending
+    }
+
+    /// Total size of all copies in bytes
+    pub fn size(&self) -> u64 {
+        self.copies.iter().map(|c| c.size()).sum()
+    }
+
+    /// Stage new contents, of any nonzero length; each copy is updated when its frame next comes
+    /// around
+    pub fn write(&mut self, data: &[T]) -> Result<()> {
+        anyhow::ensure!(self.is_dynamic(), "Cannot write to gpu-only memory");
+        anyhow::ensure!(!data.is_empty(), "Must write at least one element");
+        self.pending.clear();
+        self.pending.extend_from_slice(data);
+        let len = self.pending.len();
+        self.dirty.iter_mut().for_each(|d| *d = Some(0..len));
+        Ok(())
+    }
+
+    /// Stage new contents for the elements starting at index `offset`, leaving the rest as they
+    /// are. Only the changed span is copied when each frame comes around.
+    pub fn write_range(&mut self, offset: usize, data: &[T]) -> Result<()> {
+        anyhow::ensure!(self.is_dynamic(), "Cannot write to gpu-only memory");
+        let end = offset + data.len();
+        anyhow::ensure!(end <= self.pending.len(), "Range exceeds buffer");
+        self.pending[offset..end].copy_from_slice(data);
+        for dirty in &mut self.dirty {
+            *dirty = Some(match dirty.take() {
+                Some(range) => range.start.min(offset)..range.end.max(end),
+                None => offset..end,
+            });
+        }
+        Ok(())
+    }
+
+    /// Bring the copy for `frame` up to date. Call only once that frame's fence has signaled.
+    pub fn flush(
+        &mut self,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+        deletion_queue: &mut DeletionQueue,
+        frame: usize,
+    ) -> Result<()> {
+        let idx = frame % self.copies.len();
+        // Left dirty until the copy is written, so a failed flush is retried next time around
+        if let Some(range) = self.dirty[idx].clone() {
+            if self.copies[idx].count() != self.pending.len() {
+                // Allocate before retiring, so a failure leaves the old copy in place
+                let copy = AllocatedBuffer::new(
+                    self.pending.len(),
+                    self.create_info.clone(),
+                    allocator,
+                    device,
+                )?;
+                std::mem::replace(&mut self.copies[idx], copy).retire(deletion_queue);
+                self.copies[idx].map(device, &self.pending)?;
+                if let Some(name) = &self.debug_name {
+                    set_debug_name(
+                        device,
+                        vk::ObjectType::BUFFER,
+                        self.copies[idx].buffer.0,
+                        name,
+                    )?;
+                }
+            } else {
+                self.copies[idx].map_range(device, range.start, &self.pending[range])?;
+            }
+            self.dirty[idx] = None;
+        }
+        Ok(())
+    }
+
+    /// Buffer to bind when recording `frame`
+    pub fn buffer(&self, frame: usize) -> vk::Buffer {
+        self.copies[frame % self.copies.len()].buffer
+    }
+
+    /// Label every copy for debugging tools, including copies reallocated later
+    pub fn set_debug_name(&mut self, device: &DeviceLoader, name: &str) -> Result<()> {
+        for copy in &self.copies {
+            set_debug_name(device, vk::ObjectType::BUFFER, copy.buffer.0, name)?;
+        }
+        self.debug_name = Some(name.to_string());
+        Ok(())
+    }
+
+    pub fn free(
+        &mut self,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+    ) -> Result<()> {
+        for copy in &mut self.copies {
+            copy.free(device, allocator)?;
+        }
+        Ok(())
+    }
+
+    /// Free once every frame which may be using the buffer has completed, without waiting
+    pub(crate) fn retire(&mut self, deletion_queue: &mut DeletionQueue) {
+        for copy in &mut self.copies {
+            copy.retire(deletion_queue);
+        }
+    }
+}
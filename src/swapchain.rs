@@ -1,15 +1,53 @@
 use crate::engine::MaterialId;
 use crate::frame_sync::Frame;
 use crate::hardware_query::HardwareSelection;
-use crate::pipeline::{Material, Pipeline};
+use crate::memory::{self, Allocation, MemoryAllocator, MemoryLocation};
+use crate::pipeline::{Material, OutlineShaders, Pipeline};
 use anyhow::Result;
 use erupt::{
     extensions::{khr_surface, khr_swapchain},
-    utils::allocator::{Allocation, Allocator, MemoryTypeFinder},
     vk1_0 as vk, DeviceLoader, InstanceLoader,
 };
 use std::collections::HashMap;
 
+/// How finished frames are handed to the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Wait for vertical blank; never tears, but adds latency. Always supported.
+    Fifo,
+    /// Replace the queued frame with newer ones; low latency without tearing
+    Mailbox,
+    /// Present right away; lowest latency, but may tear
+    Immediate,
+}
+
+impl PresentMode {
+    pub(crate) fn to_vk(self) -> khr_surface::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => khr_surface::PresentModeKHR::FIFO_KHR,
+            PresentMode::Mailbox => khr_surface::PresentModeKHR::MAILBOX_KHR,
+            PresentMode::Immediate => khr_surface::PresentModeKHR::IMMEDIATE_KHR,
+        }
+    }
+
+    pub(crate) fn from_vk(mode: khr_surface::PresentModeKHR) -> Self {
+        match mode {
+            khr_surface::PresentModeKHR::MAILBOX_KHR => PresentMode::Mailbox,
+            khr_surface::PresentModeKHR::IMMEDIATE_KHR => PresentMode::Immediate,
+            _ => PresentMode::Fifo,
+        }
+    }
+
+    /// The next mode to try if this one isn't supported, keeping latency as low as possible
+    pub(crate) fn fallback(self) -> Option<Self> {
+        match self {
+            PresentMode::Immediate => Some(PresentMode::Mailbox),
+            PresentMode::Mailbox => Some(PresentMode::Fifo),
+            PresentMode::Fifo => None,
+        }
+    }
+}
+
 /// Describes everything that changes when the swapchain changes. This isn't ideal, and will likely
 /// be broken up later.
 pub struct Swapchain {
@@ -18,7 +56,7 @@ pub struct Swapchain {
     pub extent: vk::Extent2D,
     pub pipelines: HashMap<MaterialId, Pipeline>,
     pub depth_image: vk::Image,
-    pub depth_image_mem: Option<Allocation<vk::Image>>,
+    pub depth_image_mem: Option<Allocation>,
     pub depth_image_view: vk::ImageView,
     images: Vec<SwapChainImage>,
     freed: bool,
@@ -27,18 +65,20 @@ pub struct Swapchain {
 pub struct SwapChainImage {
     pub framebuffer: vk::Framebuffer,
     pub image_view: vk::ImageView,
-    /// Whether or not the frame which this swapchain image is dependent on is in flight or not
-    pub in_flight: vk::Fence,
+    /// Number of the frame last rendered to this image, which must finish before it is reused
+    pub frame: u64,
     freed: bool,
 }
 
 impl Swapchain {
-    /// Returns None if the swapchain is out of date
+    /// Returns None if the swapchain is out of date. The flag is set if the image was acquired
+    /// but the swapchain no longer matches the surface exactly, and should be recreated after
+    /// presenting it.
     pub fn next_image(
         &mut self,
         device: &DeviceLoader,
         frame: &Frame,
-    ) -> Result<Option<(u32, &mut SwapChainImage)>> {
+    ) -> Result<Option<(u32, &mut SwapChainImage, bool)>> {
         let image_index = unsafe {
             device.acquire_next_image_khr(
                 self.swapchain,
@@ -49,34 +89,33 @@ impl Swapchain {
             )
         };
 
-        let image_index = if image_index.raw == vk::Result::ERROR_OUT_OF_DATE_KHR {
-            return Ok(None);
-        } else {
-            image_index.unwrap()
+        let suboptimal = match image_index.raw {
+            vk::Result::ERROR_OUT_OF_DATE_KHR => return Ok(None),
+            vk::Result::SUBOPTIMAL_KHR => true,
+            _ => false,
         };
+        let image_index = image_index.result()?;
 
         let image = &mut self.images[image_index as usize];
+        Ok(Some((image_index, image, suboptimal)))
+    }
 
-        // Wait until the frame associated with this swapchain image is finisehd rendering, if any
-        // May be null if no frames have flowed just yet
-        if !image.in_flight.is_null() {
-            unsafe { device.wait_for_fences(&[image.in_flight], true, u64::MAX) }.result()?;
-        }
-
-        // Associate this swapchain image with the given frame. When the frame is finished, this
-        // swapchain image will know (see above) when this image is rendered.
-        image.in_flight = frame.in_flight_fence;
-
-        Ok(Some((image_index, image)))
+    pub fn image_mut(&mut self, index: u32) -> &mut SwapChainImage {
+        &mut self.images[index as usize]
     }
 
+    /// `window_extent` is used where the surface leaves the size up to the application. Returns
+    /// None if the surface has no area, e.g. while the window is minimized, since no swapchain
+    /// can be created for it.
     pub fn new(
         instance: &InstanceLoader,
         device: &DeviceLoader,
         hardware: &HardwareSelection,
         surface: khr_surface::SurfaceKHR,
-        allocator: &mut Allocator,
-    ) -> Result<Self> {
+        allocator: &mut dyn MemoryAllocator,
+        image_count: Option<u32>,
+        window_extent: Option<vk::Extent2D>,
+    ) -> Result<Option<Self>> {
         let surface_caps = unsafe {
             instance.get_physical_device_surface_capabilities_khr(
                 hardware.physical_device,
@@ -86,19 +125,26 @@ impl Swapchain {
         }
         .result()?;
 
-        let mut image_count = surface_caps.min_image_count + 1;
+        let extent = surface_extent(&surface_caps, window_extent);
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(None);
+        }
+
+        let mut image_count = image_count
+            .unwrap_or(surface_caps.min_image_count + 1)
+            .max(surface_caps.min_image_count);
         if surface_caps.max_image_count > 0 && image_count > surface_caps.max_image_count {
             image_count = surface_caps.max_image_count;
         }
 
         // Create depth image
-        let depth_format = vk::Format::D32_SFLOAT;
+        let depth_format = hardware.depth_format;
         let create_info = vk::ImageCreateInfoBuilder::new()
             .image_type(vk::ImageType::_2D)
             .extent(
                 vk::Extent3DBuilder::new()
-                    .width(surface_caps.current_extent.width)
-                    .height(surface_caps.current_extent.height)
+                    .width(extent.width)
+                    .height(extent.height)
                     .depth(1)
                     .build(),
             )
@@ -112,7 +158,8 @@ impl Swapchain {
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
         let depth_image = unsafe { device.create_image(&create_info, None, None) }.result()?;
 
-        let depth_image_mem = allocator.allocate(device, depth_image, MemoryTypeFinder::gpu_only()).result()?;
+        let depth_image_mem =
+            memory::allocate_image(allocator, device, depth_image, MemoryLocation::GpuOnly)?;
 
         let create_info = vk::ImageViewCreateInfoBuilder::new()
             .image(depth_image)
@@ -120,7 +167,7 @@ impl Swapchain {
             .format(depth_format)
             .subresource_range(
                 vk::ImageSubresourceRangeBuilder::new()
-                    .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                    .aspect_mask(vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL)
                     .base_mip_level(0)
                     .level_count(1)
                     .base_array_layer(0)
@@ -136,7 +183,7 @@ impl Swapchain {
             .min_image_count(image_count)
             .image_format(hardware.format.format)
             .image_color_space(hardware.format.color_space)
-            .image_extent(surface_caps.current_extent)
+            .image_extent(extent)
             .image_array_layers(1)
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
@@ -167,7 +214,7 @@ impl Swapchain {
             .samples(vk::SampleCountFlagBits::_1)
             .load_op(vk::AttachmentLoadOp::CLEAR)
             .store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::CLEAR)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
             .initial_layout(vk::ImageLayout::UNDEFINED)
             .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
@@ -212,43 +259,71 @@ impl Swapchain {
                     &device,
                     render_pass,
                     image,
-                    surface_caps.current_extent,
+                    extent,
                     hardware,
                     depth_image_view,
                 )
             })
             .collect::<Result<Vec<_>>>()?;
 
-        Ok(Self {
+        Ok(Some(Self {
             swapchain,
             render_pass,
-            extent: surface_caps.current_extent,
+            extent,
             pipelines: Default::default(),
             images,
             depth_image,
             depth_image_mem: Some(depth_image_mem),
             depth_image_view,
             freed: false,
-        })
+        }))
+    }
+
+    /// Size in bytes of the images owned by the swapchain (not including presentable images)
+    pub fn image_bytes(&self) -> u64 {
+        // Depth-stencil, assuming drivers pad to 8 bytes per texel at most
+        self.extent.width as u64 * self.extent.height as u64 * 8
     }
 
     pub fn add_pipeline(
         &mut self,
         device: &DeviceLoader,
-        descriptor_set_layout: vk::DescriptorSetLayout,
+        pipeline_cache: vk::PipelineCache,
+        outline_shaders: &OutlineShaders,
+        descriptor_set_layouts: &[vk::DescriptorSetLayout],
         id: MaterialId,
         material: &Material,
     ) -> Result<()> {
-        self.pipelines.insert(
-            id,
-            Pipeline::new(
-                &device,
-                material,
-                self.render_pass,
-                descriptor_set_layout,
-                self.extent,
-            )?,
-        );
+        self.add_pipelines(
+            device,
+            pipeline_cache,
+            outline_shaders,
+            descriptor_set_layouts,
+            &[(id, material)],
+        )
+    }
+
+    /// Create pipelines for many materials at once, e.g. after the swapchain is recreated
+    pub fn add_pipelines(
+        &mut self,
+        device: &DeviceLoader,
+        pipeline_cache: vk::PipelineCache,
+        outline_shaders: &OutlineShaders,
+        descriptor_set_layouts: &[vk::DescriptorSetLayout],
+        materials: &[(MaterialId, &Material)],
+    ) -> Result<()> {
+        let pipelines = Pipeline::new_batch(
+            device,
+            pipeline_cache,
+            &materials.iter().map(|(_, m)| *m).collect::<Vec<_>>(),
+            outline_shaders,
+            self.render_pass,
+            descriptor_set_layouts,
+            self.extent,
+        )?;
+        for ((id, _), pipeline) in materials.iter().zip(pipelines) {
+            self.pipelines.insert(*id, pipeline);
+        }
         Ok(())
     }
 
@@ -258,13 +333,20 @@ impl Swapchain {
         }
     }
 
-    pub fn free(&mut self, device: &DeviceLoader, allocator: &mut Allocator) -> Result<()> {
+    pub fn free(
+        &mut self,
+        device: &DeviceLoader,
+        allocator: &mut dyn MemoryAllocator,
+    ) -> Result<()> {
         unsafe {
             device.device_wait_idle().result()?;
             device.destroy_image_view(Some(self.depth_image_view), None);
         }
 
         allocator.free(device, self.depth_image_mem.take().unwrap());
+        unsafe {
+            device.destroy_image(Some(self.depth_image), None);
+        }
 
         for pipeline in self.pipelines.values_mut() {
             pipeline.free(device);
@@ -283,6 +365,34 @@ impl Swapchain {
     }
 }
 
+/// Whether the swapchain must be rebuilt after presenting with the result `present`, from an
+/// image acquired with `acquired_suboptimal` set. Suboptimal swapchains still present, but no
+/// longer match the surface, e.g. after a resize or a move to another display.
+pub(crate) fn needs_rebuild(present: vk::Result, acquired_suboptimal: bool) -> bool {
+    match present {
+        vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => true,
+        _ => acquired_suboptimal,
+    }
+}
+
+/// Size of the swapchain for a surface. Surfaces report a current extent of `u32::MAX` when
+/// the swapchain decides the window size instead (e.g. on Wayland), in which case the last
+/// known window size is used, clamped to what the surface supports.
+fn surface_extent(
+    surface_caps: &khr_surface::SurfaceCapabilitiesKHR,
+    window_extent: Option<vk::Extent2D>,
+) -> vk::Extent2D {
+    if surface_caps.current_extent.width != u32::MAX {
+        return surface_caps.current_extent;
+    }
+    let window_extent = window_extent.unwrap_or(surface_caps.min_image_extent);
+    let (min, max) = (surface_caps.min_image_extent, surface_caps.max_image_extent);
+    vk::Extent2D {
+        width: window_extent.width.max(min.width).min(max.width),
+        height: window_extent.height.max(min.height).min(max.height),
+    }
+}
+
 impl SwapChainImage {
     pub fn new(
         device: &DeviceLoader,
@@ -292,8 +402,6 @@ impl SwapChainImage {
         hardware: &HardwareSelection,
         depth_image_view: vk::ImageView,
     ) -> Result<Self> {
-        let in_flight = vk::Fence::null();
-
         let create_info = vk::ImageViewCreateInfoBuilder::new()
             .image(swapchain_image)
             .view_type(vk::ImageViewType::_2D)
@@ -333,7 +441,7 @@ impl SwapChainImage {
         Ok(Self {
             framebuffer,
             image_view,
-            in_flight,
+            frame: 0,
             freed: false,
         })
     }
@@ -362,3 +470,48 @@ impl Drop for Swapchain {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Swapchain size for a surface reporting `current`, between 1x1 and 4096x4096
+    fn size(current: (u32, u32), window: Option<(u32, u32)>) -> (u32, u32) {
+        let extent = |(width, height)| vk::Extent2D { width, height };
+        let caps = khr_surface::SurfaceCapabilitiesKHR {
+            current_extent: extent(current),
+            min_image_extent: extent((1, 1)),
+            max_image_extent: extent((4096, 4096)),
+            ..Default::default()
+        };
+        let size = surface_extent(&caps, window.map(extent));
+        (size.width, size.height)
+    }
+
+    #[test]
+    fn surface_extent_follows_the_surface() {
+        assert_eq!(size((800, 600), Some((1024, 768))), (800, 600));
+    }
+
+    #[test]
+    fn minimized_surfaces_have_no_area() {
+        // Windows report a zero current extent while minimized, which must not be clamped up
+        assert_eq!(size((0, 0), Some((800, 600))), (0, 0));
+    }
+
+    #[test]
+    fn undefined_surface_extent_uses_the_window_size() {
+        let undefined = (u32::MAX, u32::MAX);
+        assert_eq!(size(undefined, Some((1280, 720))), (1280, 720));
+        assert_eq!(size(undefined, Some((8000, 0))), (4096, 1));
+        assert_eq!(size(undefined, None), (1, 1));
+    }
+
+    #[test]
+    fn suboptimal_or_out_of_date_presents_rebuild() {
+        assert!(!needs_rebuild(vk::Result::SUCCESS, false));
+        assert!(needs_rebuild(vk::Result::SUCCESS, true));
+        assert!(needs_rebuild(vk::Result::SUBOPTIMAL_KHR, false));
+        assert!(needs_rebuild(vk::Result::ERROR_OUT_OF_DATE_KHR, false));
+    }
+}
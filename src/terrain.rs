@@ -0,0 +1,316 @@
+use crate::engine::ChunkPoolId;
+use crate::vertex::Vertex;
+use anyhow::Result;
+use nalgebra::{Point3, Vector2, Vector3};
+
+/// Heightmap samples per chunk side at full detail, minus one
+pub const TERRAIN_CHUNK_QUADS: usize = 32;
+/// Coarsest level of detail, at which a chunk is a single quad
+const MAX_LEVEL: u32 = 5;
+/// A chunk switches to the next coarser level every time its distance from the eye doubles past
+/// this many chunk widths
+const LOD_DISTANCE: f32 = 1.5;
+/// Depth of the skirts hanging from chunk edges to hide cracks between levels, as a fraction of
+/// the terrain's maximum height
+const SKIRT_DEPTH: f32 = 0.05;
+
+const CHUNK_SAMPLES: usize = TERRAIN_CHUNK_QUADS + 1;
+/// Vertices of a full detail chunk: its grid, plus one skirt vertex per edge sample
+pub(crate) const CHUNK_VERTICES: usize = CHUNK_SAMPLES * CHUNK_SAMPLES + 4 * CHUNK_SAMPLES;
+pub(crate) const CHUNK_INDICES: usize =
+    TERRAIN_CHUNK_QUADS * TERRAIN_CHUNK_QUADS * 6 + 4 * TERRAIN_CHUNK_QUADS * 6;
+
+/// Terrain elevation on a regular grid, row by row along +X, rows advancing along +Z
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    pub width: usize,
+    pub depth: usize,
+    /// `width * depth` heights from 0 to 1, scaled by the terrain's maximum height
+    pub heights: Vec<f32>,
+    /// Optional color per sample, e.g. blended from a splat map of terrain materials. White
+    /// if not given.
+    pub colors: Option<Vec<[f32; 3]>>,
+}
+
+impl Heightmap {
+    pub fn new(width: usize, depth: usize, heights: Vec<f32>) -> Result<Self> {
+        let heightmap = Self {
+            width,
+            depth,
+            heights,
+            colors: None,
+        };
+        heightmap.validate()?;
+        Ok(heightmap)
+    }
+
+    /// Color each sample, row by row like the heights
+    pub fn with_colors(mut self, colors: Vec<[f32; 3]>) -> Result<Self> {
+        self.colors = Some(colors);
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Fails if the size or the number of heights or colors don't agree, which the public
+    /// fields allow
+    pub(crate) fn validate(&self) -> Result<()> {
+        anyhow::ensure!(
+            self.width >= 2 && self.depth >= 2,
+            "Heightmaps need at least 2x2 samples"
+        );
+        let samples = self.width * self.depth;
+        anyhow::ensure!(
+            self.heights.len() == samples,
+            "Expected {} heights for a {}x{} heightmap, got {}",
+            samples,
+            self.width,
+            self.depth,
+            self.heights.len()
+        );
+        if let Some(colors) = &self.colors {
+            anyhow::ensure!(
+                colors.len() == samples,
+                "Expected {} colors for a {}x{} heightmap, got {}",
+                samples,
+                self.width,
+                self.depth,
+                colors.len()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn height(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.width + x]
+    }
+
+    fn color(&self, x: usize, z: usize) -> [f32; 3] {
+        match &self.colors {
+            Some(colors) => colors[z * self.width + x],
+            None => Vertex::DEFAULT_COLOR,
+        }
+    }
+}
+
+/// A remeshed terrain chunk, with vertices relative to the world position `origin`
+pub(crate) struct ChunkMesh {
+    pub slot: usize,
+    pub origin: Point3<f64>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+/// A heightmap drawn as a grid of chunks in a chunk pool, each remeshed at a level of detail
+/// matching its distance from the eye
+pub(crate) struct Terrain {
+    pub pool: ChunkPoolId,
+    heightmap: Heightmap,
+    /// World space position of the heightmap's first sample
+    origin: Point3<f64>,
+    /// World space distance between samples along X and Z
+    spacing: Vector2<f32>,
+    max_height: f32,
+    chunks_x: usize,
+    chunks_z: usize,
+    /// Level each chunk slot was last meshed at
+    levels: Vec<Option<u32>>,
+}
+
+impl Terrain {
+    /// Centered on the world origin in X and Z, spanning `extent`, with heights from 0 to
+    /// `max_height`
+    pub fn new(
+        pool: ChunkPoolId,
+        heightmap: Heightmap,
+        extent: Vector2<f32>,
+        max_height: f32,
+    ) -> Self {
+        let chunks_x = ceil_div(heightmap.width - 1, TERRAIN_CHUNK_QUADS);
+        let chunks_z = ceil_div(heightmap.depth - 1, TERRAIN_CHUNK_QUADS);
+        let spacing = Vector2::new(
+            extent.x / (heightmap.width - 1) as f32,
+            extent.y / (heightmap.depth - 1) as f32,
+        );
+        Self {
+            pool,
+            origin: Point3::new(-extent.x as f64 / 2.0, 0.0, -extent.y as f64 / 2.0),
+            spacing,
+            max_height,
+            chunks_x,
+            chunks_z,
+            levels: vec![None; chunks_x * chunks_z],
+            heightmap,
+        }
+    }
+
+    /// Chunk slots needed for a heightmap
+    pub fn chunk_count(heightmap: &Heightmap) -> usize {
+        ceil_div(heightmap.width - 1, TERRAIN_CHUNK_QUADS)
+            * ceil_div(heightmap.depth - 1, TERRAIN_CHUNK_QUADS)
+    }
+
+    /// Chunks whose level of detail changed for an eye at the world space position `eye`, with
+    /// their new meshes
+    pub fn update(&mut self, eye: &Point3<f64>) -> Vec<ChunkMesh> {
+        let chunk_size = self.spacing * TERRAIN_CHUNK_QUADS as f32;
+        let chunk_width = chunk_size.x.max(chunk_size.y);
+        let mut changed = Vec::new();
+        for cz in 0..self.chunks_z {
+            for cx in 0..self.chunks_x {
+                let center = Vector2::new(
+                    self.origin.x + (cx as f64 + 0.5) * chunk_size.x as f64,
+                    self.origin.z + (cz as f64 + 0.5) * chunk_size.y as f64,
+                );
+                let distance = (Vector2::new(eye.x, eye.z) - center).norm() as f32;
+                let level = (distance / (chunk_width * LOD_DISTANCE))
+                    .max(1.0)
+                    .log2()
+                    .floor() as u32;
+                let level = level.min(MAX_LEVEL);
+
+                let slot = cz * self.chunks_x + cx;
+                if self.levels[slot] != Some(level) {
+                    self.levels[slot] = Some(level);
+                    changed.push(self.mesh_chunk(slot, cx, cz, level));
+                }
+            }
+        }
+        changed
+    }
+
+    /// Mesh one chunk, sampling every `2^level`th height and always including its edges.
+    /// Vertices are relative to the chunk's first sample.
+    fn mesh_chunk(&self, slot: usize, cx: usize, cz: usize, level: u32) -> ChunkMesh {
+        let step = 1 << level;
+        let (x0, z0) = (cx * TERRAIN_CHUNK_QUADS, cz * TERRAIN_CHUNK_QUADS);
+        let origin = self.origin
+            + Vector3::new(
+                x0 as f64 * self.spacing.x as f64,
+                0.0,
+                z0 as f64 * self.spacing.y as f64,
+            );
+        let samples = |start: usize, len: usize| {
+            let end = (start + TERRAIN_CHUNK_QUADS).min(len - 1);
+            let mut samples = (start..end).step_by(step).collect::<Vec<_>>();
+            samples.push(end);
+            samples
+        };
+        let xs = samples(x0, self.heightmap.width);
+        let zs = samples(z0, self.heightmap.depth);
+
+        let vertex = |x: usize, z: usize, drop: f32| Vertex {
+            pos: [
+                (x - x0) as f32 * self.spacing.x,
+                self.heightmap.height(x, z) * self.max_height - drop,
+                (z - z0) as f32 * self.spacing.y,
+            ],
+            color: self.heightmap.color(x, z),
+        };
+
+        let mut vertices = Vec::with_capacity(xs.len() * zs.len());
+        for &z in &zs {
+            for &x in &xs {
+                vertices.push(vertex(x, z, 0.0));
+            }
+        }
+        let grid = |i: usize, j: usize| (j * xs.len() + i) as u16;
+
+        let mut indices = Vec::new();
+        for j in 0..zs.len() - 1 {
+            for i in 0..xs.len() - 1 {
+                let (a, b, c, d) = (
+                    grid(i, j),
+                    grid(i + 1, j),
+                    grid(i, j + 1),
+                    grid(i + 1, j + 1),
+                );
+                indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+
+        // Skirts: each edge of the grid, extended straight down
+        let skirt_depth = self.max_height * SKIRT_DEPTH;
+        let edges = [
+            (0..xs.len()).map(|i| (i, 0)).collect::<Vec<_>>(),
+            (0..zs.len()).map(|j| (xs.len() - 1, j)).collect(),
+            (0..xs.len()).rev().map(|i| (i, zs.len() - 1)).collect(),
+            (0..zs.len()).rev().map(|j| (0, j)).collect(),
+        ];
+        for edge in &edges {
+            let base = vertices.len() as u16;
+            for &(i, j) in edge {
+                vertices.push(vertex(xs[i], zs[j], skirt_depth));
+            }
+            for (k, pair) in edge.windows(2).enumerate() {
+                let (top_a, top_b) = (grid(pair[0].0, pair[0].1), grid(pair[1].0, pair[1].1));
+                let (bottom_a, bottom_b) = (base + k as u16, base + k as u16 + 1);
+                indices.extend_from_slice(&[top_a, top_b, bottom_a, bottom_a, top_b, bottom_b]);
+            }
+        }
+
+        ChunkMesh {
+            slot,
+            origin,
+            vertices,
+            indices,
+        }
+    }
+}
+
+fn ceil_div(a: usize, b: usize) -> usize {
+    (a + b - 1) / b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::Arena;
+
+    #[test]
+    fn heightmaps_need_a_height_per_sample() {
+        assert!(Heightmap::new(3, 2, vec![0.0; 6]).is_ok());
+        assert!(Heightmap::new(3, 2, vec![0.0; 5]).is_err());
+        assert!(Heightmap::new(1, 6, vec![0.0; 6]).is_err());
+    }
+
+    #[test]
+    fn heightmaps_need_a_color_per_sample() {
+        let heightmap = Heightmap::new(3, 2, vec![0.0; 6]).unwrap();
+        assert!(heightmap.clone().with_colors(vec![[1.0; 3]; 6]).is_ok());
+        assert!(heightmap.clone().with_colors(vec![[1.0; 3]; 5]).is_err());
+
+        // The fields are public, so mismatches can also be made directly
+        let mut heightmap = heightmap;
+        heightmap.colors = Some(vec![[1.0; 3]; 7]);
+        assert!(heightmap.validate().is_err());
+    }
+
+    #[test]
+    fn chunk_meshes_fit_their_slots() {
+        let pool = Arena::<ChunkPoolId, ()>::default().insert(());
+        for &(width, depth) in &[(2, 2), (33, 33), (70, 45), (129, 100)] {
+            let heights = (0..width * depth).map(|i| (i % 7) as f32 / 7.0).collect();
+            let heightmap = Heightmap::new(width, depth, heights).unwrap();
+            let slots = Terrain::chunk_count(&heightmap);
+            let mut terrain = Terrain::new(pool, heightmap, Vector2::new(500.0, 300.0), 20.0);
+
+            let mut meshed = vec![false; slots];
+            for eye in &[
+                Point3::new(0.0, 10.0, 0.0),
+                Point3::new(240.0, 0.0, -140.0),
+                Point3::new(5000.0, 0.0, 5000.0),
+            ] {
+                for chunk in terrain.update(eye) {
+                    assert!(chunk.slot < slots);
+                    assert!(chunk.vertices.len() <= CHUNK_VERTICES);
+                    assert!(chunk.indices.len() <= CHUNK_INDICES);
+                    assert_eq!(chunk.indices.len() % 3, 0);
+                    let vertex_count = chunk.vertices.len();
+                    assert!(chunk.indices.iter().all(|&i| (i as usize) < vertex_count));
+                    meshed[chunk.slot] = true;
+                }
+            }
+            assert!(meshed.iter().all(|&meshed| meshed));
+        }
+    }
+}
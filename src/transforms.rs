@@ -0,0 +1,45 @@
+use crate::engine::ObjectId;
+use nalgebra::Matrix4;
+
+/// Object transforms packed contiguously, so that updating all of them walks memory in order
+/// and can be split across threads. Removal swaps the last transform into the gap, so indices
+/// are only stable until the next removal.
+#[derive(Default)]
+pub(crate) struct TransformStore {
+    transforms: Vec<Matrix4<f32>>,
+    /// The object owning each transform
+    owners: Vec<ObjectId>,
+}
+
+impl TransformStore {
+    /// Index the next pushed transform will have
+    pub fn len(&self) -> usize {
+        self.transforms.len()
+    }
+
+    pub fn push(&mut self, owner: ObjectId, transform: Matrix4<f32>) {
+        self.transforms.push(transform);
+        self.owners.push(owner);
+    }
+
+    /// Remove the transform at `index`. Returns the object whose transform moved into `index`
+    /// to fill the gap, if any.
+    pub fn swap_remove(&mut self, index: usize) -> Option<ObjectId> {
+        self.transforms.swap_remove(index);
+        self.owners.swap_remove(index);
+        self.owners.get(index).copied()
+    }
+
+    pub fn get(&self, index: usize) -> &Matrix4<f32> {
+        &self.transforms[index]
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut Matrix4<f32> {
+        &mut self.transforms[index]
+    }
+
+    /// Owners and transforms, in the same order
+    pub fn slices_mut(&mut self) -> (&[ObjectId], &mut [Matrix4<f32>]) {
+        (&self.owners, &mut self.transforms)
+    }
+}
@@ -0,0 +1,319 @@
+//! Minimal world-space UI: flat colored panels with raised buttons and sliders, driven by a
+//! pointer ray. Widgets are plain engine objects, so any material that shows vertex colors can
+//! draw them.
+use crate::{Engine, MaterialId, MeshRetention, ObjectId, Vertex};
+use anyhow::Result;
+use nalgebra::{Matrix4, Point3, Vector3};
+
+/// How far widgets sit in front of their panel, to avoid z-fighting
+const WIDGET_OFFSET: f32 = 0.002;
+/// How far buttons and slider knobs stand out of the panel
+const HANDLE_DEPTH: f32 = 0.01;
+/// Brightness of the sides of buttons and knobs relative to their face, since vertex colors
+/// are unlit
+const SIDE_SHADE: f32 = 0.6;
+/// Outline color of the hovered widget
+const HOVER_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Rectangle in panel space. The panel's center is the origin, +X is right and +Y is up.
+#[derive(Debug, Clone, Copy)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WidgetId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UiEvent {
+    Clicked(WidgetId),
+    SliderChanged(WidgetId, f32),
+}
+
+enum WidgetKind {
+    Button,
+    Slider { value: f32, knob: ObjectId },
+}
+
+struct Widget {
+    rect: Rect,
+    kind: WidgetKind,
+    object: ObjectId,
+}
+
+/// A flat panel in the world, facing +Z in its own space
+pub struct UiPanel {
+    transform: Matrix4<f32>,
+    material: MaterialId,
+    background: ObjectId,
+    widgets: Vec<Widget>,
+    hovered: Option<WidgetId>,
+    was_pressed: bool,
+}
+
+impl Rect {
+    fn contains(&self, x: f32, y: f32) -> bool {
+        (x - self.x).abs() <= self.width / 2.0 && (y - self.y).abs() <= self.height / 2.0
+    }
+}
+
+impl UiPanel {
+    pub fn new(
+        engine: &mut Engine,
+        material: MaterialId,
+        transform: Matrix4<f32>,
+        width: f32,
+        height: f32,
+        color: [f32; 3],
+    ) -> Result<Self> {
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            width,
+            height,
+        };
+        let background = add_quad(engine, material, rect, 0.0, color)?;
+        engine.set_transform(background, transform)?;
+        Ok(Self {
+            transform,
+            material,
+            background,
+            widgets: Vec::new(),
+            hovered: None,
+            was_pressed: false,
+        })
+    }
+
+    pub fn add_button(
+        &mut self,
+        engine: &mut Engine,
+        rect: Rect,
+        color: [f32; 3],
+    ) -> Result<WidgetId> {
+        let object = add_box(engine, self.material, rect, WIDGET_OFFSET, color)?;
+        engine.set_transform(object, self.transform)?;
+        self.widgets.push(Widget {
+            rect,
+            kind: WidgetKind::Button,
+            object,
+        });
+        Ok(WidgetId(self.widgets.len() - 1))
+    }
+
+    /// Add a horizontal slider with a value in 0..=1
+    pub fn add_slider(
+        &mut self,
+        engine: &mut Engine,
+        rect: Rect,
+        color: [f32; 3],
+        knob_color: [f32; 3],
+        value: f32,
+    ) -> Result<WidgetId> {
+        let object = add_quad(engine, self.material, rect, WIDGET_OFFSET, color)?;
+        engine.set_transform(object, self.transform)?;
+        let knob_rect = Rect {
+            x: 0.0,
+            y: rect.y,
+            width: rect.height,
+            height: rect.height,
+        };
+        let knob = add_box(
+            engine,
+            self.material,
+            knob_rect,
+            WIDGET_OFFSET * 2.0,
+            knob_color,
+        )?;
+        self.widgets.push(Widget {
+            rect,
+            kind: WidgetKind::Slider { value, knob },
+            object,
+        });
+        let id = WidgetId(self.widgets.len() - 1);
+        self.place_knob(engine, id)?;
+        Ok(id)
+    }
+
+    pub fn slider_value(&self, id: WidgetId) -> Option<f32> {
+        match self.widgets.get(id.0)?.kind {
+            WidgetKind::Slider { value, .. } => Some(value),
+            WidgetKind::Button => None,
+        }
+    }
+
+    pub fn set_transform(&mut self, engine: &mut Engine, transform: Matrix4<f32>) -> Result<()> {
+        self.transform = transform;
+        engine.set_transform(self.background, transform)?;
+        for i in 0..self.widgets.len() {
+            engine.set_transform(self.widgets[i].object, transform)?;
+            self.place_knob(engine, WidgetId(i))?;
+        }
+        Ok(())
+    }
+
+    /// Hit-test a pointer ray (e.g. from a controller) against the panel. `pressed` is the
+    /// trigger state this frame; clicks fire on press, sliders follow the ray while held.
+    pub fn update(
+        &mut self,
+        engine: &mut Engine,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+        pressed: bool,
+    ) -> Result<Vec<UiEvent>> {
+        let mut events = Vec::new();
+        let hit = self.panel_hit(origin, direction);
+
+        let hovered = hit.and_then(|(x, y)| {
+            self.widgets
+                .iter()
+                .position(|w| w.rect.contains(x, y))
+                .map(WidgetId)
+        });
+
+        if hovered != self.hovered {
+            if let Some(old) = self.hovered {
+                engine.set_highlight(self.widgets[old.0].object, None)?;
+            }
+            if let Some(new) = hovered {
+                engine.set_highlight(self.widgets[new.0].object, Some(HOVER_COLOR))?;
+            }
+            self.hovered = hovered;
+        }
+
+        if let (Some(id), Some((x, _))) = (hovered, hit) {
+            let just_pressed = pressed && !self.was_pressed;
+            let widget = &mut self.widgets[id.0];
+            let mut moved = false;
+            match &mut widget.kind {
+                WidgetKind::Button if just_pressed => events.push(UiEvent::Clicked(id)),
+                WidgetKind::Slider { value, .. } if pressed => {
+                    let left = widget.rect.x - widget.rect.width / 2.0;
+                    let new = ((x - left) / widget.rect.width).max(0.0).min(1.0);
+                    if new != *value {
+                        *value = new;
+                        events.push(UiEvent::SliderChanged(id, new));
+                        moved = true;
+                    }
+                }
+                _ => (),
+            }
+            if moved {
+                self.place_knob(engine, id)?;
+            }
+        }
+
+        self.was_pressed = pressed;
+        Ok(events)
+    }
+
+    /// Remove all of the panel's objects from the engine
+    pub fn remove(self, engine: &mut Engine) -> Result<()> {
+        engine.remove_object(self.background)?;
+        for widget in self.widgets {
+            engine.remove_object(widget.object)?;
+            if let WidgetKind::Slider { knob, .. } = widget.kind {
+                engine.remove_object(knob)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Intersection of a ray with the panel, in panel space
+    fn panel_hit(&self, origin: Point3<f32>, direction: Vector3<f32>) -> Option<(f32, f32)> {
+        let inverse = self.transform.try_inverse()?;
+        let origin = inverse.transform_point(&origin);
+        let direction = inverse.transform_vector(&direction);
+        if direction.z.abs() < std::f32::EPSILON {
+            return None;
+        }
+        let t = -origin.z / direction.z;
+        if t < 0.0 {
+            return None;
+        }
+        let hit = origin + direction * t;
+        Some((hit.x, hit.y))
+    }
+
+    fn place_knob(&self, engine: &mut Engine, id: WidgetId) -> Result<()> {
+        let widget = &self.widgets[id.0];
+        if let WidgetKind::Slider { value, knob } = widget.kind {
+            let left = widget.rect.x - widget.rect.width / 2.0;
+            let x = left + value * widget.rect.width;
+            let offset = Matrix4::new_translation(&Vector3::new(x, 0.0, 0.0));
+            engine.set_transform(knob, self.transform * offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// Two-sided quad in panel space, `z` units in front of the panel
+fn add_quad(
+    engine: &mut Engine,
+    material: MaterialId,
+    rect: Rect,
+    z: f32,
+    color: [f32; 3],
+) -> Result<ObjectId> {
+    let (hw, hh) = (rect.width / 2.0, rect.height / 2.0);
+    let corners = [
+        [rect.x - hw, rect.y - hh, z],
+        [rect.x + hw, rect.y - hh, z],
+        [rect.x + hw, rect.y + hh, z],
+        [rect.x - hw, rect.y + hh, z],
+    ];
+    let vertices = corners
+        .iter()
+        .map(|&pos| Vertex { pos, color })
+        .collect::<Vec<_>>();
+    let indices = [0, 1, 2, 0, 2, 3, 0, 2, 1, 0, 3, 2];
+    engine.add_object(&vertices, &indices, material, false, MeshRetention::Discard)
+}
+
+/// Box over `rect` in panel space, from `z` units in front of the panel out to `z +
+/// HANDLE_DEPTH`. Every face is two-sided, as with `add_quad`.
+fn add_box(
+    engine: &mut Engine,
+    material: MaterialId,
+    rect: Rect,
+    z: f32,
+    color: [f32; 3],
+) -> Result<ObjectId> {
+    let (hw, hh) = (rect.width / 2.0, rect.height / 2.0);
+    // Bit 0 selects the right side, bit 1 the top and bit 2 the front
+    let corner = |i: usize| {
+        [
+            if i & 1 == 0 { rect.x - hw } else { rect.x + hw },
+            if i & 2 == 0 { rect.y - hh } else { rect.y + hh },
+            if i & 4 == 0 { z } else { z + HANDLE_DEPTH },
+        ]
+    };
+    let side = [
+        color[0] * SIDE_SHADE,
+        color[1] * SIDE_SHADE,
+        color[2] * SIDE_SHADE,
+    ];
+    // Front, then bottom, right, top and left; the back lies against the panel
+    let faces = [
+        ([4, 5, 7, 6], color),
+        ([0, 1, 5, 4], side),
+        ([1, 3, 7, 5], side),
+        ([3, 2, 6, 7], side),
+        ([2, 0, 4, 6], side),
+    ];
+
+    let mut vertices = Vec::with_capacity(faces.len() * 4);
+    let mut indices = Vec::with_capacity(faces.len() * 12);
+    for (corners, color) in faces.iter() {
+        let base = vertices.len() as u16;
+        vertices.extend(corners.iter().map(|&i| Vertex {
+            pos: corner(i),
+            color: *color,
+        }));
+        let quad = [0, 1, 2, 0, 2, 3, 0, 2, 1, 0, 3, 2];
+        indices.extend(quad.iter().map(|i| base + i));
+    }
+    engine.add_object(&vertices, &indices, material, false, MeshRetention::Discard)
+}
@@ -1,9 +1,11 @@
 use bytemuck::offset_of;
 use erupt::vk1_0 as vk;
 use nalgebra::Point3;
+use std::borrow::Cow;
 
 #[repr(C)]
-#[derive(Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vertex {
     pub pos: [f32; 3],
     pub color: [f32; 3],
@@ -13,6 +15,17 @@ unsafe impl bytemuck::Zeroable for Vertex {}
 unsafe impl bytemuck::Pod for Vertex {}
 
 impl Vertex {
+    /// Color given to vertices loaded without one
+    pub const DEFAULT_COLOR: [f32; 3] = [1.0; 3];
+
+    /// A vertex with `DEFAULT_COLOR`
+    pub fn from_position(pos: [f32; 3]) -> Self {
+        Self {
+            pos,
+            color: Self::DEFAULT_COLOR,
+        }
+    }
+
     pub fn from_nalgebra(pos: Point3<f32>, color: Point3<f32>) -> Self {
         Self {
             pos: *pos.coords.as_ref(),
@@ -43,3 +56,207 @@ impl Vertex {
         ]
     }
 }
+
+/// Layout of vertex data on the GPU. Vertices are always supplied as `Vertex` and converted on
+/// upload, so a material's shaders see the same inputs either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexFormat {
+    /// 32-bit float position and color, 24 bytes per vertex
+    Full,
+    /// Half-float position and 8-bit unorm color, 12 bytes per vertex. Positions keep about
+    /// three significant digits, so this suits meshes modelled near their origin.
+    Compact,
+    /// 32-bit float position only, 12 bytes per vertex, for materials whose shaders don't read
+    /// a vertex color
+    Position,
+}
+
+impl Default for VertexFormat {
+    fn default() -> Self {
+        VertexFormat::Full
+    }
+}
+
+/// GPU layout of `VertexFormat::Compact`
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CompactVertex {
+    /// Half floats; the fourth component is padding
+    pub pos: [u16; 4],
+    pub color: [u8; 4],
+}
+
+unsafe impl bytemuck::Zeroable for CompactVertex {}
+unsafe impl bytemuck::Pod for CompactVertex {}
+
+impl CompactVertex {
+    pub fn from_vertex(vertex: &Vertex) -> Self {
+        let [x, y, z] = vertex.pos;
+        let unorm = |c: f32| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+        let [r, g, b] = vertex.color;
+        Self {
+            pos: [f32_to_f16(x), f32_to_f16(y), f32_to_f16(z), f32_to_f16(1.0)],
+            color: [unorm(r), unorm(g), unorm(b), 255],
+        }
+    }
+}
+
+impl VertexFormat {
+    /// Size of one vertex in bytes
+    pub fn stride(self) -> usize {
+        match self {
+            VertexFormat::Full => std::mem::size_of::<Vertex>(),
+            VertexFormat::Compact => std::mem::size_of::<CompactVertex>(),
+            VertexFormat::Position => std::mem::size_of::<[f32; 3]>(),
+        }
+    }
+
+    /// Convert vertices to this format's GPU layout
+    pub fn encode(self, vertices: &[Vertex]) -> Cow<[u8]> {
+        match self {
+            VertexFormat::Full => Cow::Borrowed(bytemuck::cast_slice(vertices)),
+            VertexFormat::Compact => {
+                let compact = vertices
+                    .iter()
+                    .map(CompactVertex::from_vertex)
+                    .collect::<Vec<_>>();
+                Cow::Owned(bytemuck::cast_slice(&compact).to_vec())
+            }
+            VertexFormat::Position => {
+                let positions = vertices.iter().map(|v| v.pos).collect::<Vec<_>>();
+                Cow::Owned(bytemuck::cast_slice(&positions).to_vec())
+            }
+        }
+    }
+
+    pub fn binding_description(self) -> vk::VertexInputBindingDescriptionBuilder<'static> {
+        vk::VertexInputBindingDescriptionBuilder::new()
+            .binding(0)
+            .stride(self.stride() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+    }
+
+    /// Positions at location 0 and, except for `Position`, colors at location 1, read by
+    /// shaders as floats
+    pub fn attribute_descriptions(
+        self,
+    ) -> Vec<vk::VertexInputAttributeDescriptionBuilder<'static>> {
+        match self {
+            VertexFormat::Full => Vec::from(Vertex::get_attribute_descriptions()),
+            VertexFormat::Compact => vec![
+                vk::VertexInputAttributeDescriptionBuilder::new()
+                    .binding(0)
+                    .location(0)
+                    .format(vk::Format::R16G16B16A16_SFLOAT)
+                    .offset(offset_of!(CompactVertex, pos) as u32),
+                vk::VertexInputAttributeDescriptionBuilder::new()
+                    .binding(0)
+                    .location(1)
+                    .format(vk::Format::R8G8B8A8_UNORM)
+                    .offset(offset_of!(CompactVertex, color) as u32),
+            ],
+            VertexFormat::Position => vec![vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(0)],
+        }
+    }
+}
+
+/// Round to the nearest IEEE 754 half float, ties to even as the GPU and IEEE conversions do,
+/// saturating to infinity
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinity and NaN
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Too small for a normal half float; shift the mantissa, with its implicit bit, into a
+    // subnormal one
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        return sign | round_shift(mantissa, (14 - exponent) as u32) as u16;
+    }
+
+    // A carry out of the mantissa while rounding correctly bumps the exponent
+    sign | round_shift(((exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+/// Shift `value` right by `shift` bits, rounding to nearest with ties to even
+fn round_shift(value: u32, shift: u32) -> u32 {
+    let kept = value >> shift;
+    let dropped = value & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    if dropped > half || (dropped == half && kept & 1 == 1) {
+        kept + 1
+    } else {
+        kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::f32_to_f16;
+
+    #[test]
+    fn zeros_keep_their_sign() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+    }
+
+    #[test]
+    fn normals_round_to_nearest_even() {
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.5), 0xc100);
+        // Halfway between representable values rounds to the even mantissa
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+        // Just above halfway rounds up
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11) + 2f32.powi(-20)), 0x3c01);
+        // Rounding up out of the mantissa carries into the exponent
+        assert_eq!(f32_to_f16(2.0 - 2f32.powi(-12)), 0x4000);
+    }
+
+    #[test]
+    fn subnormals_round_to_nearest_even() {
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(2f32.powi(-14) - 2f32.powi(-24)), 0x03ff);
+        // Halfway cases, down to zero and up to two
+        assert_eq!(f32_to_f16(2f32.powi(-25)), 0x0000);
+        assert_eq!(f32_to_f16(3.0 * 2f32.powi(-25)), 0x0002);
+        assert_eq!(f32_to_f16(2f32.powi(-26)), 0x0000);
+        assert_eq!(f32_to_f16(-f32::MIN_POSITIVE), 0x8000);
+    }
+
+    #[test]
+    fn overflow_saturates_to_infinity() {
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        // Halfway to the next exponent rounds to even, which is infinity
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(1e10), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+    }
+
+    #[test]
+    fn nan_stays_nan() {
+        let half = f32_to_f16(f32::NAN);
+        assert_eq!(half & 0x7c00, 0x7c00);
+        assert_ne!(half & 0x3ff, 0);
+    }
+}